@@ -1,6 +1,7 @@
 use crate::builtin::BuiltInCommand;
-use crate::path::{find_partial_executable_matches_in_path, PathError};
+use crate::path::{starts_with, PathCache, PathError};
 use std::collections::HashSet;
+use std::path::Path;
 use strum::VariantNames;
 use thiserror::Error;
 
@@ -11,25 +12,123 @@ pub(crate) enum AutocompleteError {
 }
 
 pub(crate) trait Autocomplete {
+    /// Lists the completions of the last word of the input.
     fn completions(&self, input: &str) -> Result<HashSet<String>, AutocompleteError>;
 }
 
-pub(crate) struct CompositeAutocomplete {
-    autocompletes: Vec<Box<dyn Autocomplete>>,
+/// The word to complete, i.e. the text after the last unquoted whitespace of the input.
+#[derive(Default)]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub(crate) struct CompletionWord {
+    /// The byte position of the word within the input.
+    pub(crate) start: usize,
+
+    /// The word without its quotes and escapes, e.g. `my fi` for `"my fi`.
+    pub(crate) value: String,
+
+    /// The quote left open at the end of the word, if any.
+    quote: Option<char>,
+}
+
+/// The characters which end a word when they are not quoted, besides whitespace.
+const OPERATOR_CHARACTERS: &str = ";&|()<>";
+
+/// The characters escaped in unquoted completions, so they are taken literally.
+const SPECIAL_CHARACTERS: &str = " \t\n'\"\\$`&|;<>()*?[]{}!#";
+
+impl CompletionWord {
+    /// Quotes the completion the way the word is, so it reads as a single word: within the quote
+    /// left open, closing it if the completion is final, or escaping the special characters.
+    pub(crate) fn quote(&self, completion: &str, is_final: bool) -> String {
+        let escape = |special_characters: &str| {
+            completion.chars().fold(String::new(), |mut escaped, char| {
+                if special_characters.contains(char) {
+                    escaped.push('\\');
+                }
+                escaped.push(char);
+                escaped
+            })
+        };
+
+        match self.quote {
+            Some(quote) => {
+                let completion = if quote == '\'' {
+                    completion.replace('\'', r"'\''")
+                } else {
+                    escape("\"\\$`")
+                };
+                let closing_quote = if is_final {
+                    quote.to_string()
+                } else {
+                    String::new()
+                };
+
+                format!("{quote}{completion}{closing_quote}")
+            }
+            None => escape(SPECIAL_CHARACTERS),
+        }
+    }
+}
+
+/// Returns the word to complete, i.e. the text after the last whitespace or operator of the input
+/// which isn't quoted or escaped.
+pub(crate) fn completion_word(input: &str) -> CompletionWord {
+    let mut word = CompletionWord::default();
+
+    let mut chars = input.char_indices().peekable();
+    while let Some((index, char)) = chars.next() {
+        match (word.quote, char) {
+            // Only some characters are escaped within double quotes.
+            (Some('"'), '\\') => match chars.next_if(|(_, next)| "\"\\$`".contains(*next)) {
+                Some((_, escaped)) => word.value.push(escaped),
+                None => word.value.push(char),
+            },
+            (None, '\\') => word.value.extend(chars.next().map(|(_, escaped)| escaped)),
+            (None, '\'' | '"') => word.quote = Some(char),
+            (Some(quote), char) if char == quote => word.quote = None,
+            (None, char) if char.is_whitespace() || OPERATOR_CHARACTERS.contains(char) => {
+                word = CompletionWord {
+                    start: index + char.len_utf8(),
+                    ..CompletionWord::default()
+                };
+            }
+            (_, char) => word.value.push(char),
+        }
+    }
+
+    word
+}
+
+/// Whether the word to complete is a command, i.e. the first word of the input, of a pipe, of a list
+/// or of a subshell.
+fn is_completing_command(input: &str) -> bool {
+    let preceding = input[..completion_word(input).start].trim_end();
+
+    preceding.is_empty() || preceding.ends_with([';', '&', '|', '('])
+}
+
+/// Whether completions should ignore case, i.e. if `$COMPLETION_IGNORE_CASE` is set and not empty.
+fn is_ignoring_case() -> bool {
+    std::env::var_os("COMPLETION_IGNORE_CASE").is_some_and(|value| !value.is_empty())
+}
+
+pub(crate) struct CompositeAutocomplete<'a> {
+    autocompletes: Vec<Box<dyn Autocomplete + 'a>>,
 }
 
-impl CompositeAutocomplete {
-    pub(crate) fn new() -> Self {
+impl<'a> CompositeAutocomplete<'a> {
+    pub(crate) fn new(path_cache: &'a PathCache) -> Self {
         Self {
             autocompletes: vec![
                 Box::new(BuiltInAutocompletion {}),
-                Box::new(PathAutocompletion {}),
+                Box::new(PathAutocompletion { path_cache }),
+                Box::new(FileAutocompletion {}),
             ],
         }
     }
 }
 
-impl Autocomplete for CompositeAutocomplete {
+impl Autocomplete for CompositeAutocomplete<'_> {
     fn completions(&self, input: &str) -> Result<HashSet<String>, AutocompleteError> {
         // Collect into a HashSet to deduplicate entries.
         let completions: HashSet<_> = self
@@ -52,9 +151,15 @@ struct BuiltInAutocompletion {}
 
 impl Autocomplete for BuiltInAutocompletion {
     fn completions(&self, input: &str) -> Result<HashSet<String>, AutocompleteError> {
+        if !is_completing_command(input) {
+            return Ok(HashSet::new());
+        }
+
+        let word = completion_word(input);
+        let ignore_case = is_ignoring_case();
         let builtins = BuiltInCommand::VARIANTS
             .iter()
-            .filter(|cmd| cmd.starts_with(input))
+            .filter(|cmd| starts_with(cmd, &word.value, ignore_case))
             .map(ToString::to_string)
             .collect();
 
@@ -62,20 +167,82 @@ impl Autocomplete for BuiltInAutocompletion {
     }
 }
 
-struct PathAutocompletion {}
+/// Completes commands with the executables of the PATH directories, which are cached.
+struct PathAutocompletion<'a> {
+    path_cache: &'a PathCache,
+}
 
-impl Autocomplete for PathAutocompletion {
+impl Autocomplete for PathAutocompletion<'_> {
     fn completions(&self, input: &str) -> Result<HashSet<String>, AutocompleteError> {
-        let path_executables = find_partial_executable_matches_in_path(input)?;
+        if !is_completing_command(input) {
+            return Ok(HashSet::new());
+        }
+
+        let path_executables = self
+            .path_cache
+            .find_partial_matches(&completion_word(input).value, is_ignoring_case())?;
 
         Ok(path_executables)
     }
 }
 
+/// Completes arguments with the files and directories matching the word, relative to the current
+/// working directory unless the word is an absolute path.
+struct FileAutocompletion {}
+
+impl Autocomplete for FileAutocompletion {
+    fn completions(&self, input: &str) -> Result<HashSet<String>, AutocompleteError> {
+        if is_completing_command(input) {
+            return Ok(HashSet::new());
+        }
+
+        // Split the word into the directory to look into, and the start of the file name.
+        let word = completion_word(input).value;
+        let (directory, partial_name) = word
+            .rfind('/')
+            .map_or(("", word.as_str()), |index| word.split_at(index + 1));
+
+        let ignore_case = is_ignoring_case();
+        let Ok(entries) = Path::new(if directory.is_empty() { "." } else { directory }).read_dir()
+        else {
+            // Ignore missing directories, permissions, ...
+            return Ok(HashSet::new());
+        };
+
+        let files = entries
+            // Ignore file errors.
+            .filter_map(Result::ok)
+            // Ignore invalid UTF-8 filenames.
+            .filter_map(|entry| {
+                let file_name = entry.file_name().into_string().ok();
+
+                file_name.map(|file_name| (entry, file_name))
+            })
+            // Only list hidden files if explicitly requested.
+            .filter(|(_, file_name)| {
+                starts_with(file_name, partial_name, ignore_case)
+                    && (partial_name.starts_with('.') || !file_name.starts_with('.'))
+            })
+            // Suffix directories with a slash, following symlinks.
+            .map(|(entry, file_name)| {
+                let suffix = if entry.path().is_dir() { "/" } else { "" };
+
+                format!("{directory}{file_name}{suffix}")
+            })
+            .collect();
+
+        Ok(files)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::autocomplete::{Autocomplete, BuiltInAutocompletion};
+    use crate::autocomplete::{
+        completion_word, Autocomplete, BuiltInAutocompletion, FileAutocompletion,
+    };
+    use crate::path::starts_with;
     use std::collections::HashSet;
+    use std::fs;
 
     #[test]
     fn it_autocompletes_builtin() {
@@ -92,7 +259,7 @@ mod tests {
         );
         assert_eq!(
             HashSet::from(["exit".to_owned()]),
-            builtin_autocompletion.completions("ex").unwrap()
+            builtin_autocompletion.completions("exi").unwrap()
         );
 
         // With no match at all.
@@ -105,8 +272,126 @@ mod tests {
 
         // Abort when multiple matches.
         assert_eq!(
-            HashSet::from(["echo".to_owned(), "exit".to_owned()]),
+            HashSet::from(["echo".to_owned(), "exit".to_owned(), "export".to_owned()]),
             builtin_autocompletion.completions("e").unwrap()
         );
     }
+
+    #[test]
+    fn it_only_autocompletes_commands_for_the_first_word() {
+        let builtin_autocompletion = BuiltInAutocompletion {};
+
+        assert_eq!(
+            HashSet::<String>::new(),
+            builtin_autocompletion.completions("echo ech").unwrap()
+        );
+
+        // Complete commands after a pipe.
+        assert_eq!(
+            HashSet::from(["echo".to_owned()]),
+            builtin_autocompletion.completions("ls | ech").unwrap()
+        );
+
+        // Complete commands after the other command separators, and in subshells.
+        for input in ["true && ech", "true; ech", "(ech"] {
+            assert_eq!(
+                HashSet::from(["echo".to_owned()]),
+                builtin_autocompletion.completions(input).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn it_autocompletes_files() {
+        let directory = std::env::temp_dir().join("codecrafters_shell_autocomplete");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("alps")).unwrap();
+        fs::write(directory.join("alpha.txt"), "").unwrap();
+        fs::write(directory.join(".alpine"), "").unwrap();
+
+        let file_autocompletion = FileAutocompletion {};
+        let directory = directory.display();
+
+        // Suffix directories with a slash, and skip hidden files.
+        assert_eq!(
+            HashSet::from([
+                format!("{directory}/alpha.txt"),
+                format!("{directory}/alps/")
+            ]),
+            file_autocompletion
+                .completions(&format!("cat {directory}/al"))
+                .unwrap()
+        );
+
+        // List hidden files when requested.
+        assert_eq!(
+            HashSet::from([format!("{directory}/.alpine")]),
+            file_autocompletion
+                .completions(&format!("cat {directory}/."))
+                .unwrap()
+        );
+
+        // Complete the word within an open quote.
+        assert_eq!(
+            HashSet::from([format!("{directory}/alpha.txt")]),
+            file_autocompletion
+                .completions(&format!("cat \"{directory}/alph"))
+                .unwrap()
+        );
+
+        // Don't complete commands.
+        assert_eq!(
+            HashSet::<String>::new(),
+            file_autocompletion
+                .completions(&format!("{directory}/al"))
+                .unwrap()
+        );
+
+        fs::remove_dir_all(directory.to_string()).unwrap();
+    }
+
+    #[test]
+    fn it_matches_names_optionally_ignoring_case() {
+        assert!(starts_with("Downloads", "Down", false));
+        assert!(!starts_with("Downloads", "down", false));
+
+        assert!(starts_with("Downloads", "down", true));
+        assert!(starts_with("ÉCOLE", "éc", true));
+        assert!(!starts_with("Documents", "down", true));
+    }
+
+    #[test]
+    fn it_finds_the_word_to_complete() {
+        assert_eq!("ech", completion_word("ech").value);
+        assert_eq!("doc", completion_word("cat doc").value);
+        assert_eq!("", completion_word("cat ").value);
+
+        // Keep quoted and escaped whitespaces within the word.
+        for (input, start, value) in [
+            (r#"cat "my fi"#, 4, "my fi"),
+            (r#"cat 'a "b'c\ d"#, 4, r#"a "bc d"#),
+            (r#"cat "a\"b\c"#, 4, r#"a"b\c"#),
+            (r#"cat "a b" c"#, 10, "c"),
+        ] {
+            let word = completion_word(input);
+            assert_eq!((start, value), (word.start, word.value.as_str()));
+        }
+    }
+
+    #[test]
+    fn it_quotes_completions_like_the_word() {
+        // Escape the special characters of unquoted words.
+        assert_eq!(
+            r"my\ file\(1\).txt",
+            completion_word("cat my").quote("my file(1).txt", true)
+        );
+        assert_eq!("plain", completion_word("cat pl").quote("plain", true));
+
+        // Complete within the open quote, closing it once final.
+        let word = completion_word(r#"cat "my"#);
+        assert_eq!(r#""my file.txt""#, word.quote("my file.txt", true));
+        assert_eq!(r#""my dir/"#, word.quote("my dir/", false));
+        assert_eq!(r#""a \"b\" \$c""#, word.quote(r#"a "b" $c"#, true));
+        assert_eq!(r"'it'\''s'", completion_word("cat 'it").quote("it's", true));
+    }
 }
@@ -1,10 +1,21 @@
 use crate::io::FileDescriptor;
-use crate::parser::Descriptor;
-use crate::path::{find_file_in_path, PathError};
+use crate::jobs::{give_terminal_to, parse_signal, take_terminal_back, JobState};
+use crate::parser::{field_separators, is_variable_name, split_fields, Descriptor};
+use crate::path::{find_executables_in_path, find_file_in_path, PathError};
+use crate::runner::{run_script, RunnerError};
+use crate::state::{ShellOption, ShellState};
+use is_executable::IsExecutable;
 use std::collections::HashMap;
 use std::env::VarError;
-use std::io::Write;
+use std::fs;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Write};
+use std::mem::ManuallyDrop;
 use std::num::ParseIntError;
+use std::os::fd::FromRawFd;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+use strum::{IntoEnumIterator, VariantNames};
 use strum_macros::{Display, EnumString, VariantNames};
 use thiserror::Error;
 
@@ -19,21 +30,84 @@ pub(crate) enum BuiltInCommandError {
     #[error("Not enough arguments, found {found}, expected at least {min}")]
     NotEnoughArguments { found: usize, min: usize },
 
-    #[error("Too many arguments, found {found}, expected at most {max}")]
-    TooManyArguments { found: usize, max: usize },
+    #[error("{0}: too many arguments")]
+    TooManyArguments(String),
+
+    #[error("{0}: '{1}': not a valid identifier")]
+    InvalidIdentifier(String, String),
+
+    #[error("alias: {0}: not found")]
+    AliasNotFound(String),
+
+    #[error("unalias: {0}: not found")]
+    UnaliasNotFound(String),
+
+    #[error("history: {0}: numeric argument required")]
+    InvalidHistoryCount(String),
+
+    #[error("help: no help topics match `{0}'")]
+    HelpTopicNotFound(String),
+
+    #[error("set: {0}: invalid option")]
+    InvalidOption(String),
+
+    #[error("{0}: {1}: option requires an argument")]
+    OptionRequiresArgument(String, String),
+
+    #[error("{0}: missing `]'")]
+    MissingClosingBracket(String),
+
+    #[error("{0}: {1}: unary operator expected")]
+    UnaryOperatorExpected(String, String),
+
+    #[error("{0}: {1}: binary operator expected")]
+    BinaryOperatorExpected(String, String),
+
+    #[error("{0}: {1}: integer expression expected")]
+    IntegerExpected(String, String),
+
+    #[error("{0}: too many arguments")]
+    TooManyTestArguments(String),
+
+    #[error("kill: {0}: invalid signal specification")]
+    InvalidSignal(String),
+
+    #[error("kill: {0}: arguments must be process or job IDs")]
+    InvalidProcessId(String),
+
+    #[error("{0}: {1}: no such job")]
+    JobNotFound(String, String),
+
+    #[error("Failed to control the job: {0}")]
+    JobControlFailed(#[source] std::io::Error),
+
+    #[error("kill: ({0}) - {1}")]
+    SignalFailed(u32, #[source] std::io::Error),
 
     #[error("Invalid exit code '{0}': {1}")]
     InvalidExitCode(String, ParseIntError),
 
+    #[error("{0}: {1}")]
+    ReadScriptFailed(String, #[source] std::io::Error),
+
+    #[error(transparent)]
+    Script(Box<RunnerError>),
+
     #[error("Failed to search executable in PATH: {0}")]
     FindInPathFailed(#[from] PathError),
 
     #[error("Failed to read environment variable: {0}")]
     GetEnvFailed(#[from] VarError),
 
-    #[error("{0}: {1}")]
+    #[error("cd: {0}: {reason}", reason = describe_io_error(.1))]
     ChangeDirectoryFailed(String, #[source] std::io::Error),
 
+    #[error("cd: HOME not set")]
+    HomeNotSet,
+
+    #[error("cd: OLDPWD not set")]
+    PreviousDirectoryNotSet,
+
     #[error("Failed to determine the current working directory: {0}")]
     GetCurrentDirectoryFailed(#[source] std::io::Error),
 
@@ -45,6 +119,9 @@ pub(crate) enum BuiltInCommandError {
     Exit(i32),
 }
 
+/// The argument ending the flags of builtins.
+const END_OF_OPTIONS: &str = "--";
+
 pub(crate) fn try_into_builtin(command: &str) -> Result<BuiltInCommand, BuiltInCommandError> {
     BuiltInCommand::try_from(command)
         .map_err(|_| BuiltInCommandError::BuiltInCommandNotFound(command.to_owned()))
@@ -54,94 +131,1767 @@ pub(crate) fn try_into_builtin(command: &str) -> Result<BuiltInCommand, BuiltInC
 #[derive(Display, EnumString, VariantNames)]
 #[strum(serialize_all = "snake_case")]
 pub(crate) enum BuiltInCommand {
+    Alias,
+    #[strum(serialize = "bg")]
+    Background,
+    #[strum(serialize = "[")]
+    Bracket,
     #[strum(serialize = "cd")]
     ChangeDirectory,
+    #[strum(serialize = ":")]
+    Colon,
     Echo,
     Exit,
+    Export,
+    False,
+    #[strum(serialize = "fg")]
+    Foreground,
+    Hash,
+    Help,
+    History,
+    Jobs,
+    Kill,
+    Printenv,
     #[strum(serialize = "pwd")]
     PrintWorkingDirectory,
+    Read,
+    Set,
+    #[strum(to_string = "source", serialize = ".")]
+    Source,
+    Test,
+    True,
     Type,
+    Unalias,
+    Which,
 }
 
 impl BuiltInCommand {
-    /// Runs the built-in command.
+    /// Returns a one-line description of the command, e.g. for `help`.
+    fn description(&self) -> &'static str {
+        match self {
+            BuiltInCommand::Alias => {
+                "Define aliases replacing the first word of commands, or list them."
+            }
+            BuiltInCommand::Background => "Resume a job in the background.",
+            BuiltInCommand::Bracket | BuiltInCommand::Test => "Evaluate a conditional expression.",
+            BuiltInCommand::ChangeDirectory => "Change the current working directory.",
+            BuiltInCommand::Colon | BuiltInCommand::True => "Do nothing and succeed.",
+            BuiltInCommand::Echo => "Print the arguments.",
+            BuiltInCommand::Exit => "Exit the shell.",
+            BuiltInCommand::Export => "Set environment variables, or list them.",
+            BuiltInCommand::False => "Do nothing and fail.",
+            BuiltInCommand::Foreground => "Resume a job in the foreground.",
+            BuiltInCommand::Hash => "Remember or list the locations of commands.",
+            BuiltInCommand::Help => "Describe the builtin commands.",
+            BuiltInCommand::History => "List the history entries, or clear them.",
+            BuiltInCommand::Jobs => "List the background jobs.",
+            BuiltInCommand::Kill => "Send a signal to processes or jobs.",
+            BuiltInCommand::Printenv => "Print the environment variables.",
+            BuiltInCommand::PrintWorkingDirectory => "Print the current working directory.",
+            BuiltInCommand::Read => "Read a line of input into variables.",
+            BuiltInCommand::Set => "Toggle shell options, or list them.",
+            BuiltInCommand::Source => "Run the commands of a file in the current shell.",
+            BuiltInCommand::Type => "Describe how a command would be run.",
+            BuiltInCommand::Unalias => "Remove aliases.",
+            BuiltInCommand::Which => "Print the location of executables in the PATH.",
+        }
+    }
+
+    /// Returns the synopsis of the command, e.g. for `help`.
+    fn usage(&self) -> &'static str {
+        match self {
+            BuiltInCommand::Alias => "alias [name[=value] ...]",
+            BuiltInCommand::Background => "bg [job_spec]",
+            BuiltInCommand::Bracket => "[ expression ]",
+            BuiltInCommand::ChangeDirectory => "cd [-P] [dir | -]",
+            BuiltInCommand::Colon => ": [arguments]",
+            BuiltInCommand::Echo => "echo [-neE] [arg ...]",
+            BuiltInCommand::Exit => "exit [n]",
+            BuiltInCommand::Export => "export [name[=value] ...]",
+            BuiltInCommand::False => "false",
+            BuiltInCommand::Foreground => "fg [job_spec]",
+            BuiltInCommand::Hash => "hash [-r] [name ...]",
+            BuiltInCommand::Help => "help [builtin ...]",
+            BuiltInCommand::History => "history [n | -c]",
+            BuiltInCommand::Jobs => "jobs",
+            BuiltInCommand::Kill => "kill [-s sigspec | -sigspec] pid | jobspec ...",
+            BuiltInCommand::Printenv => "printenv [name ...]",
+            BuiltInCommand::PrintWorkingDirectory => "pwd",
+            BuiltInCommand::Read => "read [-r] [-p prompt] [name ...]",
+            BuiltInCommand::Set => "set [-o | +o] [option]",
+            BuiltInCommand::Source => "source filename [arguments]",
+            BuiltInCommand::Test => "test [expression]",
+            BuiltInCommand::True => "true",
+            BuiltInCommand::Type => "type [-a] name",
+            BuiltInCommand::Unalias => "unalias [-a] name [name ...]",
+            BuiltInCommand::Which => "which name [name ...]",
+        }
+    }
+
+    /// Runs the built-in command, and returns its exit status.
     ///
     /// # Note
-    /// The run method doesn't accept a stderr argument as it doesn't write to the standard error
-    /// under regular circumstances. It any error is encountered, they are returned as error types.
+    /// Errors preventing the command from completing are returned as error types, whereas the
+    /// standard error is only written to when the command completes with a failure status.
     pub(crate) fn run(
         &self,
         args: &[String],
         mut descriptors: HashMap<Descriptor, FileDescriptor>,
-    ) -> Result<(), BuiltInCommandError> {
+        state: &mut ShellState,
+    ) -> Result<i32, BuiltInCommandError> {
         let mut stdout = descriptors
             .remove(&Descriptor::stdout())
             .unwrap_or_else(FileDescriptor::stdout);
 
         match self {
+            BuiltInCommand::Alias => {
+                if args.is_empty() {
+                    // List all the aliases, sorted by name.
+                    for (name, value) in state.aliases() {
+                        stdout.write_fmt(format_args!(
+                            "alias {name}='{}'\n",
+                            escape_single_quoted(value)
+                        ))?;
+                    }
+
+                    return Ok(0);
+                }
+
+                for arg in args {
+                    match arg.split_once('=') {
+                        Some((name, value)) => {
+                            state
+                                .aliases_mut()
+                                .insert(name.to_owned(), value.to_owned());
+                        }
+                        // Print the alias when no value is given.
+                        None => {
+                            let value = state
+                                .aliases()
+                                .get(arg)
+                                .ok_or_else(|| BuiltInCommandError::AliasNotFound(arg.clone()))?;
+
+                            stdout.write_fmt(format_args!(
+                                "alias {arg}='{}'\n",
+                                escape_single_quoted(value)
+                            ))?;
+                        }
+                    }
+                }
+            }
+            BuiltInCommand::Background => {
+                let id = get_optional_argument(self, args)?;
+
+                state.jobs_mut().poll();
+                let job = state.jobs_mut().find_mut(id.as_deref()).ok_or_else(|| {
+                    let id = id.unwrap_or_else(|| "current".to_owned());
+                    BuiltInCommandError::JobNotFound(self.to_string(), id)
+                })?;
+
+                if job.state() != JobState::Stopped {
+                    let mut stderr = descriptors
+                        .remove(&Descriptor::stderr())
+                        .unwrap_or_else(FileDescriptor::stderr);
+                    stderr
+                        .write_fmt(format_args!("bg: job {} already in background\n", job.id()))?;
+                    return Ok(0);
+                }
+
+                // Resume the job without giving it the terminal.
+                job.resume()
+                    .map_err(BuiltInCommandError::JobControlFailed)?;
+                stdout.write_fmt(format_args!("[{}] {} &\n", job.id(), job.command()))?;
+            }
+            BuiltInCommand::Bracket | BuiltInCommand::Test => {
+                let name = self.to_string();
+                let mut args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+                // The expression of `[` must be closed with `]`.
+                let result = if matches!(self, BuiltInCommand::Bracket) && args.pop() != Some("]") {
+                    Err(BuiltInCommandError::MissingClosingBracket(name))
+                } else {
+                    evaluate_test(&name, &args)
+                };
+
+                // Convey the result through the status only, invalid expressions failing with 2.
+                return match result {
+                    Ok(true) => Ok(0),
+                    Ok(false) => Ok(1),
+                    Err(error) => {
+                        let mut stderr = descriptors
+                            .remove(&Descriptor::stderr())
+                            .unwrap_or_else(FileDescriptor::stderr);
+                        stderr.write_fmt(format_args!("{error}\n"))?;
+
+                        Ok(2)
+                    }
+                };
+            }
             BuiltInCommand::ChangeDirectory => {
-                let arg = get_single_argument(args)?;
+                // Parse the flags: `-P` resolves symbolic links, `-L` (the default) keeps them.
+                let (flags, args) = split_flags(args, |arg| matches!(arg, "-L" | "-P"));
+                let physical = flags.last().is_some_and(|flag| flag == "-P");
+                let arg = get_optional_argument(self, args)?;
+
+                let working_dir = match arg.as_deref() {
+                    None => std::env::var("HOME").map_err(|_| BuiltInCommandError::HomeNotSet)?,
+                    Some("-") => std::env::var("OLDPWD")
+                        .map_err(|_| BuiltInCommandError::PreviousDirectoryNotSet)?,
+                    Some(dir) => dir.to_owned(),
+                };
 
-                let working_dir = if arg == "~" {
-                    std::env::var("HOME")?
+                let previous_dir = logical_current_dir()?;
+
+                // Go through the symbolic links as typed, unless resolving them.
+                let target = if physical {
+                    PathBuf::from(&working_dir)
                 } else {
-                    arg
+                    normalize_path(&previous_dir.join(&working_dir))
                 };
 
-                std::env::set_current_dir(&working_dir)
+                std::env::set_current_dir(&target)
                     .map_err(|e| BuiltInCommandError::ChangeDirectoryFailed(working_dir, e))?;
+
+                let cwd = if physical {
+                    std::env::current_dir()
+                        .map_err(BuiltInCommandError::GetCurrentDirectoryFailed)?
+                } else {
+                    target
+                };
+
+                // Remember the previous directory for `cd -`, and expose the new one.
+                std::env::set_var("OLDPWD", previous_dir);
+                std::env::set_var("PWD", &cwd);
+
+                // Print the new directory when going back, like bash.
+                if arg.as_deref() == Some("-") {
+                    stdout.write_fmt(format_args!("{}\n", cwd.display()))?;
+                }
             }
             BuiltInCommand::Echo => {
-                stdout.write_fmt(format_args!("{}\n", args.join(" ")))?;
+                // Parse the leading flags, e.g. `-n`, `-e`, or combined `-ne`.
+                let mut newline = true;
+                let mut escapes = false;
+
+                let (flags, args) = split_flags(args, is_echo_flags);
+                for flag in flags.iter().flat_map(|arg| arg.chars().skip(1)) {
+                    match flag {
+                        'n' => newline = false,
+                        'e' => escapes = true,
+                        _ => escapes = false,
+                    }
+                }
+
+                let text = args.join(" ");
+                let mut output = if escapes {
+                    // Stop printing at `\c`, including the trailing newline.
+                    let (output, is_stopped) = interpret_escapes(&text);
+                    newline &= !is_stopped;
+                    output
+                } else {
+                    text.into_bytes()
+                };
+                if newline {
+                    output.push(b'\n');
+                }
+
+                stdout.write_all(&output)?;
             }
             BuiltInCommand::Exit => {
-                let arg = get_single_argument(args)?;
+                // Exit with the status of the last command when no code is given.
+                let exit_code = match get_optional_argument(self, args)? {
+                    Some(arg) => arg
+                        .parse::<i64>()
+                        .map_err(|e| BuiltInCommandError::InvalidExitCode(arg, e))?,
+                    None => state.last_status().into(),
+                };
+
+                // Keep the lowest byte only, like bash, e.g. `exit 257` exits with 1.
+                return Err(BuiltInCommandError::Exit((exit_code & 0xFF) as i32));
+            }
+            BuiltInCommand::Export => {
+                if args.is_empty() {
+                    // List all the exported variables, sorted by name.
+                    let mut variables: Vec<_> = std::env::vars_os()
+                        .map(|(name, value)| {
+                            (
+                                name.to_string_lossy().into_owned(),
+                                value.to_string_lossy().into_owned(),
+                            )
+                        })
+                        .collect();
+                    variables.sort();
+
+                    for (name, value) in variables {
+                        stdout.write_fmt(format_args!(
+                            "export {name}=\"{}\"\n",
+                            escape_double_quoted(&value)
+                        ))?;
+                    }
+
+                    return Ok(0);
+                }
+
+                // Validate all the names before exporting anything.
+                let assignments = args
+                    .iter()
+                    .map(|arg| {
+                        let (name, value) = arg
+                            .split_once('=')
+                            .map_or((arg.as_str(), None), |(name, value)| (name, Some(value)));
+
+                        if is_variable_name(name) {
+                            Ok((name, value))
+                        } else {
+                            Err(BuiltInCommandError::InvalidIdentifier(
+                                self.to_string(),
+                                arg.clone(),
+                            ))
+                        }
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                for (name, value) in assignments {
+                    // All variables live in the environment, so they are already exported when
+                    // no value is given.
+                    if let Some(value) = value {
+                        std::env::set_var(name, value);
+                    }
+                }
+            }
+            BuiltInCommand::False => {
+                // Ignore any argument.
+                return Ok(1);
+            }
+            BuiltInCommand::Foreground => {
+                let id = get_optional_argument(self, args)?;
+
+                state.jobs_mut().poll();
+                let job = state.jobs_mut().find_mut(id.as_deref()).ok_or_else(|| {
+                    let id = id.unwrap_or_else(|| "current".to_owned());
+                    BuiltInCommandError::JobNotFound(self.to_string(), id)
+                })?;
+
+                // Print the command line of the job, like bash.
+                stdout.write_fmt(format_args!("{}\n", job.command()))?;
+
+                // Give the terminal to the job while waiting for it, so it receives the keyboard
+                // input and signals, e.g. Ctrl+C.
+                give_terminal_to(job.process_group());
+                let result = match job.state() {
+                    JobState::Stopped => job.resume(),
+                    _ => Ok(()),
+                };
+                let result = result.and_then(|()| job.wait());
+                take_terminal_back();
+                result.map_err(BuiltInCommandError::JobControlFailed)?;
+
+                let mut stderr = descriptors
+                    .remove(&Descriptor::stderr())
+                    .unwrap_or_else(FileDescriptor::stderr);
+                let JobState::Done(status) = job.state() else {
+                    // The job was stopped again, so it stays in the background.
+                    stderr.write_fmt(format_args!("\n{job}\n"))?;
+                    return Ok(128 + libc::SIGTSTP);
+                };
+
+                // Start the next prompt on a new line after Ctrl+C, like bash.
+                if status == 128 + libc::SIGINT {
+                    stderr.write_all(b"\n")?;
+                }
+
+                let id = job.id();
+                state.jobs_mut().remove(id);
+                return Ok(status);
+            }
+            BuiltInCommand::Help => {
+                // List all the builtins without arguments.
+                if args.is_empty() {
+                    for name in BuiltInCommand::VARIANTS {
+                        let description = try_into_builtin(name)?.description();
+                        stdout.write_fmt(format_args!("{name:<10} {description}\n"))?;
+                    }
+
+                    return Ok(0);
+                }
+
+                for arg in args {
+                    let builtin = try_into_builtin(arg)
+                        .map_err(|_| BuiltInCommandError::HelpTopicNotFound(arg.clone()))?;
+                    stdout.write_fmt(format_args!(
+                        "{arg}: {}\n    {}\n",
+                        builtin.usage(),
+                        builtin.description()
+                    ))?;
+                }
+            }
+            BuiltInCommand::Hash => {
+                // Forget all the locations with `-r`, before remembering the given commands if any.
+                let (flags, args) = split_flags(args, |arg| arg == "-r");
+                let is_reset = !flags.is_empty();
+                if is_reset {
+                    state.command_hashes_mut().clear();
+                }
+
+                if args.is_empty() && !is_reset {
+                    let entries = state.command_hashes().entries();
+                    if entries.is_empty() {
+                        stdout.write_fmt(format_args!("hash: hash table empty\n"))?;
+                        return Ok(0);
+                    }
+
+                    // List the locations, along with the number of times they were run.
+                    stdout.write_fmt(format_args!("hits\tcommand\n"))?;
+                    for command in entries.values() {
+                        stdout.write_fmt(format_args!(
+                            "{:>4}\t{}\n",
+                            command.hits(),
+                            command.location().display()
+                        ))?;
+                    }
+
+                    return Ok(0);
+                }
+
+                let mut stderr = descriptors
+                    .remove(&Descriptor::stderr())
+                    .unwrap_or_else(FileDescriptor::stderr);
+
+                // Remember the locations of the commands, skipping builtins.
+                let mut status = 0;
+                for arg in args {
+                    if try_into_builtin(arg).is_err() && !state.hash_command(arg)? {
+                        stderr.write_fmt(format_args!("hash: {arg}: not found\n"))?;
+                        status = 1;
+                    }
+                }
+
+                return Ok(status);
+            }
+            BuiltInCommand::History => {
+                let arg = get_optional_argument(self, args)?;
+
+                let entries = state.history().entries();
+                let count = match arg.as_deref() {
+                    None => entries.len(),
+                    Some("-c") => {
+                        state.history_mut().clear();
+                        return Ok(0);
+                    }
+                    Some(count) => count
+                        .parse::<usize>()
+                        .map_err(|_| BuiltInCommandError::InvalidHistoryCount(count.to_owned()))?,
+                };
 
-                let exit_code = arg
-                    .parse::<i32>()
-                    .map_err(|e| BuiltInCommandError::InvalidExitCode(arg, e))?;
+                // Number the entries from the oldest one, so the numbers don't depend on the count.
+                let skipped = entries.len().saturating_sub(count);
+                for (index, entry) in entries.iter().enumerate().skip(skipped) {
+                    stdout.write_fmt(format_args!("{:>5}  {entry}\n", index + 1))?;
+                }
+            }
+            BuiltInCommand::Jobs => {
+                if !args.is_empty() {
+                    return Err(BuiltInCommandError::TooManyArguments(self.to_string()));
+                }
+
+                for job in state.jobs_mut().poll() {
+                    stdout.write_fmt(format_args!("{job}\n"))?;
+                }
 
-                return Err(BuiltInCommandError::Exit(exit_code));
+                // Only report the finished jobs once.
+                state.jobs_mut().remove_finished();
+            }
+            BuiltInCommand::Kill => {
+                let mut stderr = descriptors
+                    .remove(&Descriptor::stderr())
+                    .unwrap_or_else(FileDescriptor::stderr);
+
+                // The signal comes first, e.g. `-9`, `-KILL` or `-s KILL`, and defaults to SIGTERM.
+                let (signal, targets) = match args {
+                    [option, signal, targets @ ..] if option == "-s" => (Some(signal), targets),
+                    [option, targets @ ..] if option.len() > 1 && option.starts_with('-') => {
+                        (Some(option), targets)
+                    }
+                    _ => (None, args),
+                };
+                let signal = match signal {
+                    None => libc::SIGTERM,
+                    Some(signal) => {
+                        let signal = signal.strip_prefix('-').unwrap_or(signal);
+                        match parse_signal(signal) {
+                            Some(signal) => signal,
+                            None => {
+                                let error = BuiltInCommandError::InvalidSignal(signal.to_owned());
+                                stderr.write_fmt(format_args!("{error}\n"))?;
+                                return Ok(1);
+                            }
+                        }
+                    }
+                };
+
+                if targets.is_empty() {
+                    return Err(BuiltInCommandError::NotEnoughArguments { min: 1, found: 0 });
+                }
+
+                // Signal every target, reporting those which failed.
+                let mut status = 0;
+                for target in targets {
+                    if let Err(error) = send_signal(target, signal, state) {
+                        stderr.write_fmt(format_args!("{error}\n"))?;
+                        status = 1;
+                    }
+                }
+
+                return Ok(status);
+            }
+            BuiltInCommand::Printenv => {
+                if args.is_empty() {
+                    // List all the variables, sorted by name.
+                    let mut variables: Vec<_> = std::env::vars_os().collect();
+                    variables.sort();
+
+                    for (name, value) in variables {
+                        stdout.write_fmt(format_args!(
+                            "{}={}\n",
+                            name.to_string_lossy(),
+                            value.to_string_lossy()
+                        ))?;
+                    }
+
+                    return Ok(0);
+                }
+
+                // Print the values of the requested variables, failing if any of them is unset.
+                let mut status = 0;
+                for name in args {
+                    match std::env::var_os(name) {
+                        Some(value) => {
+                            stdout.write_fmt(format_args!("{}\n", value.to_string_lossy()))?;
+                        }
+                        None => status = 1,
+                    }
+                }
+
+                return Ok(status);
             }
             BuiltInCommand::PrintWorkingDirectory => {
                 if !args.is_empty() {
-                    return Err(BuiltInCommandError::TooManyArguments {
-                        max: 0,
-                        found: args.len(),
-                    });
+                    return Err(BuiltInCommandError::TooManyArguments(self.to_string()));
                 }
 
-                let cwd = std::env::current_dir()
-                    .map_err(BuiltInCommandError::GetCurrentDirectoryFailed)?;
+                let cwd = logical_current_dir()?;
 
                 stdout.write_fmt(format_args!("{}\n", &cwd.display()))?;
             }
+            BuiltInCommand::Read => {
+                // Parse the flags: `-r` keeps backslashes, `-p PROMPT` prints a prompt first.
+                let mut raw = false;
+                let mut prompt = None;
+                let mut args = args.iter();
+                let mut names = vec![];
+                while let Some(arg) = args.next() {
+                    match arg.as_str() {
+                        "-r" if names.is_empty() => raw = true,
+                        "-p" if names.is_empty() => match args.next() {
+                            Some(value) => prompt = Some(value),
+                            None => {
+                                // Fail with 2 like bash, as for other misuses.
+                                let error = BuiltInCommandError::OptionRequiresArgument(
+                                    self.to_string(),
+                                    arg.clone(),
+                                );
+                                let mut stderr = descriptors
+                                    .remove(&Descriptor::stderr())
+                                    .unwrap_or_else(FileDescriptor::stderr);
+                                stderr.write_fmt(format_args!("{error}\n"))?;
+
+                                return Ok(2);
+                            }
+                        },
+                        name if is_variable_name(name) => names.push(name),
+                        _ => {
+                            return Err(BuiltInCommandError::InvalidIdentifier(
+                                self.to_string(),
+                                arg.clone(),
+                            ))
+                        }
+                    }
+                }
+                if names.is_empty() {
+                    names.push("REPLY");
+                }
+
+                if let Some(prompt) = prompt {
+                    let mut stderr = descriptors
+                        .remove(&Descriptor::stderr())
+                        .unwrap_or_else(FileDescriptor::stderr);
+                    stderr.write_all(prompt.as_bytes())?;
+                    stderr.flush()?;
+                }
+
+                let line = match descriptors.remove(&Descriptor::stdin()) {
+                    Some(mut stdin) => read_line(&mut stdin, raw)?,
+                    None => {
+                        // Read the terminal directly, as buffering would steal the next lines
+                        // from the shell. The descriptor is borrowed, so it must not be closed.
+                        // SAFETY: the standard input stays open for the lifetime of the shell.
+                        let mut stdin = ManuallyDrop::new(unsafe { File::from_raw_fd(0) });
+                        read_line(&mut *stdin, raw)?
+                    }
+                };
+
+                // Fail at the end of the input, so loops reading lines can stop.
+                let Some(line) = line else {
+                    return Ok(1);
+                };
+
+                let mut fields = split_fields(&line, &field_separators(), names.len()).into_iter();
+                for name in names {
+                    std::env::set_var(name, fields.next().unwrap_or_default());
+                }
+            }
+            BuiltInCommand::Set => {
+                let mut args = args.iter();
+                while let Some(arg) = args.next() {
+                    // Options are enabled with `-`, and disabled with `+`.
+                    let (enabled, flags) = match arg.split_at_checked(1) {
+                        Some(("-", flags)) if !flags.is_empty() => (true, flags),
+                        Some(("+", flags)) if !flags.is_empty() => (false, flags),
+                        _ => return Err(BuiltInCommandError::InvalidOption(arg.clone())),
+                    };
+
+                    if flags != "o" {
+                        for flag in flags.chars() {
+                            let option = ShellOption::from_flag(flag).ok_or_else(|| {
+                                BuiltInCommandError::InvalidOption(format!("{}{flag}", &arg[..1]))
+                            })?;
+                            state.set_option(option, enabled);
+                        }
+                    } else if let Some(name) = args.next() {
+                        let option = name
+                            .parse()
+                            .map_err(|_| BuiltInCommandError::InvalidOption(name.clone()))?;
+                        state.set_option(option, enabled);
+                    } else {
+                        // List the options without a name, as commands restoring them with `+o`.
+                        for option in ShellOption::iter() {
+                            let is_enabled = state.is_enabled(option);
+                            if enabled {
+                                let value = if is_enabled { "on" } else { "off" };
+                                stdout.write_fmt(format_args!("{option:<15}\t{value}\n"))?;
+                            } else {
+                                let sign = if is_enabled { '-' } else { '+' };
+                                stdout.write_fmt(format_args!("set {sign}o {option}\n"))?;
+                            }
+                        }
+                    }
+                }
+            }
+            BuiltInCommand::Source => {
+                let filename = args
+                    .first()
+                    .ok_or(BuiltInCommandError::NotEnoughArguments { min: 1, found: 0 })?;
+
+                let script = std::fs::read_to_string(filename)
+                    .map_err(|e| BuiltInCommandError::ReadScriptFailed(filename.clone(), e))?;
+
+                // Run the script in the current shell, so its side effects persist, e.g. `cd`.
+                state.set_last_status(0);
+
+                // Pass the next arguments as positional parameters while the script runs, if any.
+                let previous_arguments =
+                    (args.len() > 1).then(|| state.replace_arguments(args[1..].to_vec()));
+                let result = run_script(&script, state);
+                if let Some(arguments) = previous_arguments {
+                    state.replace_arguments(arguments);
+                }
+
+                result.map_err(|error| match error {
+                    RunnerError::BuiltInCommand(error) => error,
+                    error => BuiltInCommandError::Script(Box::new(error)),
+                })?;
+
+                return Ok(state.last_status());
+            }
+            BuiltInCommand::Colon | BuiltInCommand::True => {
+                // Ignore any argument, which was expanded already, e.g. `: $(echo > file)`.
+            }
             BuiltInCommand::Type => {
-                let arg = get_single_argument(args)?;
+                // Parse the `-a` flag, to list all the matches rather than the first one.
+                let (flags, args) = split_flags(args, |arg| arg == "-a");
+                let all = !flags.is_empty();
+                let arg = get_single_argument(self, args)?;
+
+                // Resolve aliases first, then builtins, then executables, like bash does.
+                let alias = state.aliases().get(&arg);
+                if let Some(value) = alias {
+                    stdout.write_fmt(format_args!("{arg} is aliased to `{value}'\n"))?;
+                }
 
-                if let Ok(sub_command) = try_into_builtin(arg.as_ref()) {
-                    stdout.write_fmt(format_args!("{sub_command} is a shell builtin\n"))?;
+                if all {
+                    let is_builtin = try_into_builtin(&arg).is_ok();
+                    if is_builtin {
+                        stdout.write_fmt(format_args!("{arg} is a shell builtin\n"))?;
+                    }
+
+                    let locations = find_executables_in_path(&arg)?;
+                    for location in &locations {
+                        stdout.write_fmt(format_args!("{} is {}\n", arg, location.display()))?;
+                    }
+
+                    if alias.is_none() && !is_builtin && locations.is_empty() {
+                        return Err(BuiltInCommandError::PathCommandNotFound(arg));
+                    }
+                } else if alias.is_some() {
+                    // The alias takes precedence over the other matches.
+                } else if try_into_builtin(&arg).is_ok() {
+                    stdout.write_fmt(format_args!("{arg} is a shell builtin\n"))?;
                 } else if let Some(location) = find_file_in_path(&arg)? {
                     stdout.write_fmt(format_args!("{} is {}\n", arg, location.display()))?;
                 } else {
                     return Err(BuiltInCommandError::PathCommandNotFound(arg));
                 }
             }
+            BuiltInCommand::Unalias => {
+                // Remove all the aliases with `-a`.
+                let (flags, args) = split_flags(args, |arg| arg == "-a");
+                if !flags.is_empty() {
+                    state.aliases_mut().clear();
+                    return Ok(0);
+                }
+
+                if args.is_empty() {
+                    return Err(BuiltInCommandError::NotEnoughArguments { min: 1, found: 0 });
+                }
+
+                for arg in args {
+                    state
+                        .aliases_mut()
+                        .remove(arg)
+                        .ok_or_else(|| BuiltInCommandError::UnaliasNotFound(arg.clone()))?;
+                }
+            }
+            BuiltInCommand::Which => {
+                if args.is_empty() {
+                    return Err(BuiltInCommandError::NotEnoughArguments { min: 1, found: 0 });
+                }
+
+                let mut stderr = descriptors
+                    .remove(&Descriptor::stderr())
+                    .unwrap_or_else(FileDescriptor::stderr);
+
+                // Resolve every argument, failing if any of them is missing.
+                let mut status = 0;
+                for arg in args {
+                    if let Some(location) = find_file_in_path(arg)? {
+                        stdout.write_fmt(format_args!("{}\n", location.display()))?;
+                    } else {
+                        let error = BuiltInCommandError::PathCommandNotFound(arg.clone());
+                        stderr.write_fmt(format_args!("{error}\n"))?;
+                        status = 1;
+                    }
+                }
+
+                return Ok(status);
+            }
+        }
+
+        Ok(0)
+    }
+}
+
+/// Reads a line byte by byte, so nothing past the line is consumed, or `None` at the end of the
+/// input.
+///
+/// Unless raw, backslashes escape the next character, and escaped newlines continue the line.
+fn read_line(reader: &mut impl Read, raw: bool) -> Result<Option<String>, BuiltInCommandError> {
+    let mut line = vec![];
+    let mut is_escaping = false;
+    let mut has_input = false;
+
+    let mut byte = [0];
+    while reader.read(&mut byte)? == 1 {
+        has_input = true;
+
+        match byte[0] {
+            b'\\' if !raw && !is_escaping => is_escaping = true,
+            b'\n' if is_escaping => is_escaping = false,
+            b'\n' => break,
+            other => {
+                line.push(other);
+                is_escaping = false;
+            }
+        }
+    }
+
+    Ok(has_input.then(|| String::from_utf8_lossy(&line).into_owned()))
+}
+
+/// Evaluates the expression of `test`, depending on the number of arguments like POSIX specifies.
+fn evaluate_test(name: &str, args: &[&str]) -> Result<bool, BuiltInCommandError> {
+    match args {
+        [] => Ok(false),
+        [value] => Ok(!value.is_empty()),
+        ["!", operand] => Ok(operand.is_empty()),
+        [operator, operand] => evaluate_unary_test(name, operator, operand),
+        [left, operator, right] if is_binary_test_operator(operator) => {
+            evaluate_binary_test(name, left, operator, right)
+        }
+        ["!", rest @ ..] if args.len() <= 4 => Ok(!evaluate_test(name, rest)?),
+        ["(", inner @ .., ")"] if args.len() <= 4 => evaluate_test(name, inner),
+        [_, operator, _] => Err(BuiltInCommandError::BinaryOperatorExpected(
+            name.to_owned(),
+            operator.to_string(),
+        )),
+        _ => Err(BuiltInCommandError::TooManyTestArguments(name.to_owned())),
+    }
+}
+
+/// Evaluates a file test, e.g. `-f file`, or a string test, e.g. `-z text`.
+fn evaluate_unary_test(
+    name: &str,
+    operator: &str,
+    operand: &str,
+) -> Result<bool, BuiltInCommandError> {
+    let path = Path::new(operand);
+
+    match operator {
+        "-e" => Ok(path.exists()),
+        "-f" => Ok(path.is_file()),
+        "-d" => Ok(path.is_dir()),
+        "-s" => Ok(path.metadata().is_ok_and(|metadata| metadata.len() > 0)),
+        "-x" => Ok(path.is_executable()),
+        "-z" => Ok(operand.is_empty()),
+        "-n" => Ok(!operand.is_empty()),
+        _ => Err(BuiltInCommandError::UnaryOperatorExpected(
+            name.to_owned(),
+            operator.to_owned(),
+        )),
+    }
+}
+
+fn is_binary_test_operator(operator: &str) -> bool {
+    ["=", "==", "!=", "-eq", "-ne", "-lt", "-le", "-gt", "-ge"].contains(&operator)
+}
+
+/// Evaluates a string comparison, e.g. `a = b`, or an integer comparison, e.g. `1 -lt 2`.
+fn evaluate_binary_test(
+    name: &str,
+    left: &str,
+    operator: &str,
+    right: &str,
+) -> Result<bool, BuiltInCommandError> {
+    match operator {
+        "=" | "==" => return Ok(left == right),
+        "!=" => return Ok(left != right),
+        _ => {}
+    }
+
+    let parse_integer = |operand: &str| {
+        operand
+            .trim()
+            .parse::<i64>()
+            .map_err(|_| BuiltInCommandError::IntegerExpected(name.to_owned(), operand.to_owned()))
+    };
+    let (left, right) = (parse_integer(left)?, parse_integer(right)?);
+
+    Ok(match operator {
+        "-eq" => left == right,
+        "-ne" => left != right,
+        "-lt" => left < right,
+        "-le" => left <= right,
+        "-gt" => left > right,
+        _ => left >= right,
+    })
+}
+
+/// Sends the signal to a process id, or to all the processes of a job given as `%id`.
+fn send_signal(
+    target: &str,
+    signal: i32,
+    state: &mut ShellState,
+) -> Result<(), BuiltInCommandError> {
+    let pids = if target.starts_with('%') {
+        state
+            .jobs_mut()
+            .find_mut(Some(target))
+            .ok_or_else(|| BuiltInCommandError::JobNotFound("kill".to_owned(), target.to_owned()))?
+            .pids()
+    } else {
+        vec![target
+            .parse()
+            .map_err(|_| BuiltInCommandError::InvalidProcessId(target.to_owned()))?]
+    };
+
+    for pid in pids {
+        // SAFETY: sending a signal has no memory safety requirements.
+        if unsafe { libc::kill(pid as libc::pid_t, signal) } < 0 {
+            return Err(BuiltInCommandError::SignalFailed(
+                pid,
+                std::io::Error::last_os_error(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Describes the error like the C library does, e.g. `No such file or directory`, without the error
+/// code.
+pub(crate) fn describe_io_error(error: &std::io::Error) -> String {
+    match error.kind() {
+        ErrorKind::NotFound => "No such file or directory".to_owned(),
+        ErrorKind::NotADirectory => "Not a directory".to_owned(),
+        ErrorKind::PermissionDenied => "Permission denied".to_owned(),
+        _ => error.to_string(),
+    }
+}
+
+/// The current directory as reached through symbolic links, i.e. `$PWD` if it still refers to it.
+fn logical_current_dir() -> Result<PathBuf, BuiltInCommandError> {
+    let cwd = std::env::current_dir().map_err(BuiltInCommandError::GetCurrentDirectoryFailed)?;
+
+    let is_same_directory = |path: &Path| match (fs::metadata(path), fs::metadata(&cwd)) {
+        (Ok(path), Ok(cwd)) => path.dev() == cwd.dev() && path.ino() == cwd.ino(),
+        _ => false,
+    };
+
+    Ok(std::env::var_os("PWD")
+        .map(PathBuf::from)
+        .filter(|pwd| pwd.is_absolute() && is_same_directory(pwd))
+        .unwrap_or(cwd))
+}
+
+/// Resolves the `.` and `..` components of the path lexically, without following symbolic links,
+/// and drops repeated and trailing slashes.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
         }
+    }
 
-        Ok(())
+    normalized
+}
+
+/// Splits the leading flags from the other arguments, stopping at the first argument which isn't a
+/// flag, or at `--` which is dropped so the next arguments aren't flags, e.g. `echo -- -n`.
+fn split_flags(args: &[String], is_flag: impl Fn(&str) -> bool) -> (&[String], &[String]) {
+    let count = args
+        .iter()
+        .take_while(|arg| *arg != END_OF_OPTIONS && is_flag(arg))
+        .count();
+    let (flags, rest) = args.split_at(count);
+
+    // Skip the `--` ending the flags, if any.
+    let rest = rest
+        .split_first()
+        .filter(|(first, _)| *first == END_OF_OPTIONS)
+        .map_or(rest, |(_, rest)| rest);
+
+    (flags, rest)
+}
+
+fn get_optional_argument(
+    builtin: &BuiltInCommand,
+    args: &[String],
+) -> Result<Option<String>, BuiltInCommandError> {
+    if args.is_empty() {
+        Ok(None)
+    } else {
+        get_single_argument(builtin, args).map(Some)
     }
 }
 
-fn get_single_argument(args: &[String]) -> Result<String, BuiltInCommandError> {
+fn get_single_argument(
+    builtin: &BuiltInCommand,
+    args: &[String],
+) -> Result<String, BuiltInCommandError> {
     if args.is_empty() {
         Err(BuiltInCommandError::NotEnoughArguments { min: 1, found: 0 })
     } else if 1 < args.len() {
-        Err(BuiltInCommandError::TooManyArguments {
-            max: 1,
-            found: args.len(),
-        })
+        Err(BuiltInCommandError::TooManyArguments(builtin.to_string()))
     } else {
         Ok(args[0].trim().to_owned())
     }
 }
+
+/// Escapes the single-quotes within a single-quoted string, by closing and reopening it.
+fn escape_single_quoted(value: &str) -> String {
+    value.replace('\'', r"'\''")
+}
+
+/// Escapes the characters which are special within double-quotes.
+fn escape_double_quoted(value: &str) -> String {
+    value
+        .chars()
+        .flat_map(|char| match char {
+            '"' | '\\' | '$' => vec!['\\', char],
+            _ => vec![char],
+        })
+        .collect()
+}
+
+/// Whether the argument only contains `echo` flags, e.g. `-n`, `-e`, `-E`, or `-neE`.
+fn is_echo_flags(arg: &str) -> bool {
+    arg.strip_prefix('-')
+        .is_some_and(|flags| !flags.is_empty() && flags.chars().all(|c| "neE".contains(c)))
+}
+
+/// Interprets the backslash escapes of `echo -e` into the bytes to write, keeping unknown escapes as
+/// they are, and returns whether `\c` stopped the output before the end of the text.
+fn interpret_escapes(text: &str) -> (Vec<u8>, bool) {
+    let mut result = Vec::new();
+    let push = |result: &mut Vec<u8>, char: char| {
+        result.extend_from_slice(char.encode_utf8(&mut [0; 4]).as_bytes())
+    };
+
+    let mut chars = text.chars().peekable();
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            push(&mut result, char);
+            continue;
+        }
+
+        match chars.next() {
+            Some('a') => result.push(0x07),
+            Some('b') => result.push(0x08),
+            Some('c') => return (result, true),
+            Some('e') => result.push(0x1b),
+            Some('f') => result.push(0x0c),
+            Some('n') => result.push(b'\n'),
+            Some('r') => result.push(b'\r'),
+            Some('t') => result.push(b'\t'),
+            Some('v') => result.push(0x0b),
+            Some('\\') => result.push(b'\\'),
+            Some(radix @ ('0' | 'x')) => {
+                // Octal `\0nnn` has up to 3 digits, hexadecimal `\xHH` up to 2.
+                let (radix, max_digits) = if radix == '0' { (8, 3) } else { (16, 2) };
+
+                let mut digits = String::new();
+                while digits.len() < max_digits {
+                    match chars.next_if(|c| c.is_digit(radix)) {
+                        Some(digit) => digits.push(digit),
+                        None => break,
+                    }
+                }
+
+                // Write the value as a raw byte, keeping its lowest byte like bash, e.g. `\0400`.
+                match u32::from_str_radix(&digits, radix) {
+                    Ok(value) => result.push((value & 0xFF) as u8),
+                    // `\x` without digits is kept as is, `\0` without digits is a null byte.
+                    Err(_) if radix == 16 => result.extend_from_slice(b"\\x"),
+                    Err(_) => result.push(0),
+                }
+            }
+            Some(other) => {
+                result.push(b'\\');
+                push(&mut result, other);
+            }
+            None => result.push(b'\\'),
+        }
+    }
+
+    (result, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        evaluate_test, interpret_escapes, is_echo_flags, normalize_path, split_flags,
+        BuiltInCommand, BuiltInCommandError,
+    };
+    use crate::io::FileDescriptor;
+    use crate::jobs::JobState;
+    use crate::parser::Descriptor;
+    use crate::path::run_subshell;
+    use crate::state::{ShellOption, ShellState};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::process::{CommandExt, ExitStatusExt};
+    use std::path::Path;
+    use std::process::Command;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use strum::VariantNames;
+
+    /// Runs the builtin with the arguments, and returns its result along with what it wrote to its
+    /// standard output and error.
+    fn run_builtin(
+        builtin: BuiltInCommand,
+        args: &[&str],
+        state: &mut ShellState,
+    ) -> (Result<i32, BuiltInCommandError>, String) {
+        run_builtin_with_input(builtin, args, "", state)
+    }
+
+    /// Runs the test in a copy of the process, so changing its working directory or environment
+    /// doesn't affect the other tests.
+    fn run_in_subshell(test: impl FnOnce()) {
+        let status = run_subshell(HashMap::new(), || {
+            test();
+            0
+        });
+
+        assert_eq!(0, status);
+    }
+
+    /// Runs the builtin like [`run_builtin`], feeding the input to its standard input.
+    fn run_builtin_with_input(
+        builtin: BuiltInCommand,
+        args: &[&str],
+        input: &str,
+        state: &mut ShellState,
+    ) -> (Result<i32, BuiltInCommandError>, String) {
+        // Write to a file unique to each run, as tests run concurrently.
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+        let output = std::env::temp_dir().join(format!(
+            "codecrafters_shell_builtin_{}_{}.txt",
+            std::process::id(),
+            RUNS.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        // Share the file between both outputs, so they are written in order.
+        let stdout = FileDescriptor::file(&output.display().to_string(), false).unwrap();
+        let descriptors = HashMap::from([
+            (
+                Descriptor::stdin(),
+                FileDescriptor::text(input.to_owned()).unwrap(),
+            ),
+            (Descriptor::stderr(), stdout.try_clone().unwrap()),
+            (Descriptor::stdout(), stdout),
+        ]);
+        let args: Vec<_> = args.iter().map(|arg| arg.to_string()).collect();
+
+        let result = builtin.run(&args, descriptors, state);
+        let written = fs::read_to_string(&output).unwrap();
+        fs::remove_file(output).unwrap();
+
+        (result, written)
+    }
+
+    #[test]
+    fn it_recognizes_echo_flags() {
+        assert!(is_echo_flags("-n"));
+        assert!(is_echo_flags("-e"));
+        assert!(is_echo_flags("-neE"));
+
+        assert!(!is_echo_flags("-"));
+        assert!(!is_echo_flags("-x"));
+        assert!(!is_echo_flags("-nx"));
+        assert!(!is_echo_flags("n"));
+    }
+
+    #[test]
+    fn it_ends_flags_at_a_double_dash() {
+        let args = ["-n", "-e", "--", "-e", "--", "test"].map(ToOwned::to_owned);
+
+        let (flags, operands) = split_flags(&args, is_echo_flags);
+        assert_eq!(["-n", "-e"], flags);
+        assert_eq!(["-e", "--", "test"], operands);
+
+        // Stop at the first operand, keeping any later `--`.
+        let (flags, operands) = split_flags(&args[3..], |arg| arg == "-n");
+        assert!(flags.is_empty());
+        assert_eq!(["-e", "--", "test"], operands);
+    }
+
+    #[test]
+    fn it_normalizes_paths() {
+        assert_eq!(
+            Path::new("/tmp/sub"),
+            normalize_path(Path::new("/tmp/../tmp//./sub/"))
+        );
+        assert_eq!(Path::new("/"), normalize_path(Path::new("/../..")));
+        assert_eq!(
+            Path::new("/home"),
+            normalize_path(Path::new("/home/link/.."))
+        );
+    }
+
+    #[test]
+    fn it_reports_directory_changes_which_fail() {
+        let run_cd = |dirs: &[&str]| {
+            let (result, _) = run_builtin(
+                BuiltInCommand::ChangeDirectory,
+                dirs,
+                &mut ShellState::new(),
+            );
+            result.unwrap_err().to_string()
+        };
+
+        assert_eq!("cd: Cargo.toml: Not a directory", run_cd(&["Cargo.toml"]));
+        assert_eq!(
+            "cd: /codecrafters_shell_missing: No such file or directory",
+            run_cd(&["/codecrafters_shell_missing"])
+        );
+
+        // Stay in the current directory when given several ones.
+        let current_dir = std::env::current_dir().unwrap();
+        assert_eq!("cd: too many arguments", run_cd(&["/", "/tmp"]));
+        assert_eq!(current_dir, std::env::current_dir().unwrap());
+    }
+
+    #[test]
+    fn it_changes_to_the_home_directory_without_arguments() {
+        run_in_subshell(|| {
+            let mut state = ShellState::new();
+
+            std::env::set_var("HOME", "/");
+            assert_eq!(
+                0,
+                run_builtin(BuiltInCommand::ChangeDirectory, &[], &mut state)
+                    .0
+                    .unwrap()
+            );
+            assert_eq!(Path::new("/"), std::env::current_dir().unwrap());
+
+            std::env::remove_var("HOME");
+            assert_eq!(
+                "cd: HOME not set",
+                run_builtin(BuiltInCommand::ChangeDirectory, &[], &mut state)
+                    .0
+                    .unwrap_err()
+                    .to_string()
+            );
+        });
+    }
+
+    #[test]
+    fn it_changes_back_to_the_previous_directory() {
+        run_in_subshell(|| {
+            let mut state = ShellState::new();
+            let temp_dir = std::env::temp_dir();
+
+            std::env::remove_var("OLDPWD");
+            assert_eq!(
+                "cd: OLDPWD not set",
+                run_builtin(BuiltInCommand::ChangeDirectory, &["-"], &mut state)
+                    .0
+                    .unwrap_err()
+                    .to_string()
+            );
+
+            for dir in ["/", &temp_dir.display().to_string()] {
+                let (status, _) = run_builtin(BuiltInCommand::ChangeDirectory, &[dir], &mut state);
+                assert_eq!(0, status.unwrap());
+            }
+
+            // Print the directory when going back to it.
+            let (status, output) = run_builtin(BuiltInCommand::ChangeDirectory, &["-"], &mut state);
+            assert_eq!((0, "/\n".to_owned()), (status.unwrap(), output));
+            assert_eq!(Path::new("/"), std::env::current_dir().unwrap());
+            assert_eq!(temp_dir, Path::new(&std::env::var("OLDPWD").unwrap()));
+        });
+    }
+
+    #[test]
+    fn it_interprets_echo_escapes() {
+        assert_eq!(
+            (b"a\nb\tc\\d".to_vec(), false),
+            interpret_escapes(r"a\nb\tc\\d")
+        );
+        assert_eq!((b"AB".to_vec(), false), interpret_escapes(r"\x41\0102"));
+
+        // Write raw bytes rather than characters, and wrap octal values to a byte.
+        assert_eq!((vec![0xFF], false), interpret_escapes(r"\xff"));
+        assert_eq!((vec![0x00, 0x41], false), interpret_escapes(r"\0400\0501"));
+        assert_eq!(("é".as_bytes().to_vec(), false), interpret_escapes("é"));
+
+        // Keep unknown escapes as they are.
+        assert_eq!((br"\q\x".to_vec(), false), interpret_escapes(r"\q\x"));
+        assert_eq!(
+            (b"trailing\\".to_vec(), false),
+            interpret_escapes("trailing\\")
+        );
+
+        // Stop the output at `\c`.
+        assert_eq!((b"abc".to_vec(), true), interpret_escapes(r"abc\cdef\n"));
+    }
+
+    #[test]
+    fn it_exits_with_the_given_code_or_the_last_status() {
+        let mut state = ShellState::new();
+        state.set_last_status(3);
+
+        let mut run_exit = |args: &[&str]| run_builtin(BuiltInCommand::Exit, args, &mut state).0;
+
+        assert!(matches!(run_exit(&[]), Err(BuiltInCommandError::Exit(3))));
+        assert!(matches!(
+            run_exit(&["42"]),
+            Err(BuiltInCommandError::Exit(42))
+        ));
+
+        // Mask the code to its lowest byte.
+        assert!(matches!(
+            run_exit(&["256"]),
+            Err(BuiltInCommandError::Exit(0))
+        ));
+        assert!(matches!(
+            run_exit(&["257"]),
+            Err(BuiltInCommandError::Exit(1))
+        ));
+        assert!(matches!(
+            run_exit(&["-1"]),
+            Err(BuiltInCommandError::Exit(255))
+        ));
+
+        assert!(matches!(
+            run_exit(&["abc"]),
+            Err(BuiltInCommandError::InvalidExitCode(..))
+        ));
+    }
+
+    #[test]
+    fn it_exports_variables_or_lists_them() {
+        let mut state = ShellState::new();
+        std::env::set_var("CODECRAFTERS_EXPORT_LISTED", r#"a "b" $c"#);
+
+        // List the variables quoted, so they can be read back.
+        let (status, output) = run_builtin(BuiltInCommand::Export, &[], &mut state);
+        assert_eq!(0, status.unwrap());
+        assert!(output.contains("export CODECRAFTERS_EXPORT_LISTED=\"a \\\"b\\\" \\$c\"\n"));
+
+        // Export nothing when any name is invalid.
+        let (status, _) = run_builtin(
+            BuiltInCommand::Export,
+            &["CODECRAFTERS_EXPORT_VALID=a", "1A=b"],
+            &mut state,
+        );
+        assert_eq!(
+            "export: '1A=b': not a valid identifier",
+            status.unwrap_err().to_string()
+        );
+        assert!(std::env::var_os("CODECRAFTERS_EXPORT_VALID").is_none());
+
+        let (status, _) = run_builtin(
+            BuiltInCommand::Export,
+            &["CODECRAFTERS_EXPORT_VALID=a"],
+            &mut state,
+        );
+        assert_eq!(0, status.unwrap());
+        assert_eq!("a", std::env::var("CODECRAFTERS_EXPORT_VALID").unwrap());
+
+        std::env::remove_var("CODECRAFTERS_EXPORT_LISTED");
+        std::env::remove_var("CODECRAFTERS_EXPORT_VALID");
+    }
+
+    #[test]
+    fn it_evaluates_test_expressions() {
+        let test = |args: &[&str]| evaluate_test("test", args);
+
+        // Test strings.
+        assert!(!test(&[]).unwrap());
+        assert!(test(&["a"]).unwrap());
+        assert!(!test(&[""]).unwrap());
+        assert!(test(&["-z", ""]).unwrap());
+        assert!(test(&["-n", "a"]).unwrap());
+        assert!(test(&["a", "=", "a"]).unwrap());
+        assert!(test(&["a", "!=", "b"]).unwrap());
+
+        // Test integers.
+        assert!(test(&["2", "-gt", "-1"]).unwrap());
+        assert!(test(&[" 3", "-eq", "3"]).unwrap());
+        assert!(!test(&["3", "-lt", "3"]).unwrap());
+
+        // Test files.
+        assert!(test(&["-d", "/"]).unwrap());
+        assert!(!test(&["-f", "/"]).unwrap());
+        assert!(test(&["-e", "/"]).unwrap());
+        assert!(!test(&["-e", "/codecrafters_shell_missing"]).unwrap());
+
+        // Negate and group expressions.
+        assert!(test(&["!", "a", "=", "b"]).unwrap());
+        assert!(test(&["(", "-n", "a", ")"]).unwrap());
+        assert!(test(&["!", "-z", "a"]).unwrap());
+
+        // Reject invalid expressions.
+        assert!(matches!(
+            test(&["-q", "a"]),
+            Err(BuiltInCommandError::UnaryOperatorExpected(..))
+        ));
+        assert!(matches!(
+            test(&["a", "-q", "b"]),
+            Err(BuiltInCommandError::BinaryOperatorExpected(..))
+        ));
+        assert!(matches!(
+            test(&["a", "-eq", "1"]),
+            Err(BuiltInCommandError::IntegerExpected(..))
+        ));
+        assert!(matches!(
+            test(&["a", "b", "c", "d", "e"]),
+            Err(BuiltInCommandError::TooManyTestArguments(..))
+        ));
+    }
+
+    #[test]
+    fn it_requires_a_closing_bracket() {
+        let run_bracket = |args: &[&str]| {
+            let (status, errors) =
+                run_builtin(BuiltInCommand::Bracket, args, &mut ShellState::new());
+            (status.unwrap(), errors)
+        };
+
+        assert_eq!(0, run_bracket(&["1", "-lt", "2", "]"]).0);
+        assert_eq!(1, run_bracket(&["-z", "a", "]"]).0);
+
+        assert_eq!(
+            (2, "[: missing `]'\n".to_owned()),
+            run_bracket(&["-z", "a"])
+        );
+    }
+
+    #[test]
+    fn it_describes_builtins_with_help() {
+        let mut state = ShellState::new();
+
+        // List every builtin without arguments.
+        let (status, listing) = run_builtin(BuiltInCommand::Help, &[], &mut state);
+        assert_eq!(0, status.unwrap());
+        assert_eq!(BuiltInCommand::VARIANTS.len(), listing.lines().count());
+        assert!(listing.contains("\ncd         Change the current working directory.\n"));
+
+        // Print the usage of the given builtins.
+        let (status, usage) = run_builtin(BuiltInCommand::Help, &["cd"], &mut state);
+        assert_eq!(0, status.unwrap());
+        assert_eq!(
+            "cd: cd [-P] [dir | -]\n    Change the current working directory.\n",
+            usage
+        );
+
+        assert!(matches!(
+            run_builtin(
+                BuiltInCommand::Help,
+                &["codecrafters_shell_missing"],
+                &mut state
+            )
+            .0,
+            Err(BuiltInCommandError::HelpTopicNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn it_resets_the_hashed_commands_before_hashing_new_ones() {
+        let mut state = ShellState::new();
+        let mut run_hash = |args: &[&str]| {
+            let (status, _) = run_builtin(BuiltInCommand::Hash, args, &mut state);
+            assert_eq!(0, status.unwrap());
+
+            state
+                .command_hashes()
+                .entries()
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec!["cat", "sh"], run_hash(&["sh", "cat"]));
+        assert_eq!(vec!["sh"], run_hash(&["-r", "sh"]));
+        assert!(run_hash(&["-r"]).is_empty());
+    }
+
+    #[test]
+    fn it_lists_history_entries() {
+        let mut state = ShellState::new();
+        for entry in ["echo a", "pwd", "history"] {
+            state.history_mut().push(entry).unwrap();
+        }
+
+        let mut run_history = |args: &[&str]| {
+            let (status, output) = run_builtin(BuiltInCommand::History, args, &mut state);
+            assert_eq!(0, status.unwrap());
+
+            output
+        };
+
+        assert_eq!(
+            "    1  echo a\n    2  pwd\n    3  history\n",
+            run_history(&[])
+        );
+
+        // Keep the numbers of the last entries.
+        assert_eq!("    2  pwd\n    3  history\n", run_history(&["2"]));
+        assert_eq!("", run_history(&["0"]));
+
+        // Clear the entries.
+        assert_eq!("", run_history(&["-c"]));
+        assert_eq!("", run_history(&[]));
+    }
+
+    #[test]
+    fn it_kills_jobs_and_processes() {
+        let mut state = ShellState::new();
+
+        let run_kill = |args: &[&str], state: &mut ShellState| {
+            let (status, errors) = run_builtin(BuiltInCommand::Kill, args, state);
+            (status.unwrap(), errors)
+        };
+
+        let pid = Command::new("sleep").arg("100").spawn().unwrap().id();
+        state.jobs_mut().add(vec![pid], "sleep 100".to_owned());
+        let mut child = Command::new("sleep").arg("100").spawn().unwrap();
+
+        assert_eq!(0, run_kill(&["%1"], &mut state).0);
+        assert_eq!(
+            0,
+            run_kill(&["-s", "KILL", &child.id().to_string()], &mut state).0
+        );
+        assert_eq!(Some(libc::SIGKILL), child.wait().unwrap().signal());
+
+        // The job terminates with the default signal.
+        while state.jobs_mut().poll()[0].to_string().contains("Running") {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(
+            format!("[1]  {pid}  Exit 143  sleep 100"),
+            state.jobs_mut().poll()[0].to_string()
+        );
+
+        assert_eq!(
+            (1, "kill: FOO: invalid signal specification\n".to_owned()),
+            run_kill(&["-FOO", "%1"], &mut state)
+        );
+        assert_eq!(
+            (
+                1,
+                "kill: %2: no such job\n\
+                 kill: abc: arguments must be process or job IDs\n"
+                    .to_owned()
+            ),
+            run_kill(&["-9", "%2", "abc"], &mut state)
+        );
+    }
+
+    #[test]
+    fn it_resumes_jobs_in_the_background_or_the_foreground() {
+        let mut state = ShellState::new();
+
+        let run = |builtin: BuiltInCommand, args: &[&str], state: &mut ShellState| {
+            let (status, output) = run_builtin(builtin, args, state);
+            (status.unwrap(), output)
+        };
+
+        let pid = Command::new("sleep")
+            .arg("0.2")
+            .process_group(0)
+            .spawn()
+            .unwrap()
+            .id();
+        unsafe { libc::kill(pid as libc::pid_t, libc::SIGSTOP) };
+        state.jobs_mut().add(vec![pid], "sleep 0.2".to_owned());
+
+        // Wait for the job to be stopped.
+        while state.jobs_mut().poll()[0].state() != JobState::Stopped {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        assert_eq!(
+            (0, "[1] sleep 0.2 &\n".to_owned()),
+            run(BuiltInCommand::Background, &[], &mut state)
+        );
+        assert_eq!(
+            (0, "bg: job 1 already in background\n".to_owned()),
+            run(BuiltInCommand::Background, &["%1"], &mut state)
+        );
+        assert_eq!(
+            (0, "sleep 0.2\n".to_owned()),
+            run(BuiltInCommand::Foreground, &["1"], &mut state)
+        );
+        assert!(matches!(
+            run_builtin(BuiltInCommand::Foreground, &[], &mut state).0,
+            Err(BuiltInCommandError::JobNotFound(_, id)) if id == "current"
+        ));
+
+        // End the output with a new line when the job is interrupted, e.g. by Ctrl+C.
+        let pid = Command::new("sleep")
+            .arg("5")
+            .process_group(0)
+            .spawn()
+            .unwrap()
+            .id();
+        unsafe { libc::kill(pid as libc::pid_t, libc::SIGINT) };
+        state.jobs_mut().add(vec![pid], "sleep 5".to_owned());
+
+        assert_eq!(
+            (128 + libc::SIGINT, "sleep 5\n\n".to_owned()),
+            run(BuiltInCommand::Foreground, &[], &mut state)
+        );
+    }
+
+    #[test]
+    fn it_locates_executables_with_which() {
+        let (status, output) = run_builtin(
+            BuiltInCommand::Which,
+            &["sh", "codecrafters_shell_missing"],
+            &mut ShellState::new(),
+        );
+
+        // Fail if any argument is missing, but still print the other ones.
+        assert_eq!(1, status.unwrap());
+        let (location, errors) = output.split_once('\n').unwrap();
+        assert!(location.ends_with("/sh"));
+        assert_eq!("codecrafters_shell_missing: not found\n", errors);
+    }
+
+    #[test]
+    fn it_reports_aliases_before_builtins_with_type() {
+        let mut state = ShellState::new();
+        state
+            .aliases_mut()
+            .insert("echo".to_owned(), "printf '%s'".to_owned());
+
+        for args in [vec!["echo"], vec!["-a", "echo"]] {
+            let (status, output) = run_builtin(BuiltInCommand::Type, &args, &mut state);
+
+            assert_eq!(0, status.unwrap());
+            let mut lines = output.lines();
+            assert_eq!(Some("echo is aliased to `printf '%s''"), lines.next());
+            assert_eq!(
+                args.len() > 1,
+                lines.next() == Some("echo is a shell builtin")
+            );
+        }
+    }
+
+    #[test]
+    fn it_lists_every_match_with_type_all() {
+        let directory = std::env::temp_dir().join("codecrafters_shell_builtin_type_all");
+        let _ = fs::remove_dir_all(&directory);
+        let path_dirs = [directory.join("first"), directory.join("second")];
+        for dir in &path_dirs {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("echo"), "").unwrap();
+            fs::set_permissions(dir.join("echo"), fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        // Change the PATH in a copy of the process only.
+        run_in_subshell(|| {
+            std::env::set_var("PATH", std::env::join_paths(&path_dirs).unwrap());
+            let mut state = ShellState::new();
+            state
+                .aliases_mut()
+                .insert("echo".to_owned(), "printf '%s'".to_owned());
+
+            let (status, output) = run_builtin(BuiltInCommand::Type, &["-a", "echo"], &mut state);
+
+            assert_eq!(0, status.unwrap());
+            assert_eq!(
+                format!(
+                    "echo is aliased to `printf '%s''\n\
+                     echo is a shell builtin\n\
+                     echo is {}\n\
+                     echo is {}\n",
+                    path_dirs[0].join("echo").display(),
+                    path_dirs[1].join("echo").display()
+                ),
+                output
+            );
+        });
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn it_skips_files_which_cant_run_with_type() {
+        let directory = std::env::temp_dir().join("codecrafters_shell_builtin_type_mode");
+        let _ = fs::remove_dir_all(&directory);
+        let path_dirs = [directory.join("first"), directory.join("second")];
+        for (dir, mode) in path_dirs.iter().zip([0o644, 0o755]) {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("tool"), "").unwrap();
+            fs::set_permissions(dir.join("tool"), fs::Permissions::from_mode(mode)).unwrap();
+        }
+
+        // Change the PATH in a copy of the process only.
+        run_in_subshell(|| {
+            std::env::set_var("PATH", std::env::join_paths(&path_dirs).unwrap());
+            let expected = format!("tool is {}\n", path_dirs[1].join("tool").display());
+
+            for args in [vec!["tool"], vec!["-a", "tool"]] {
+                let (status, output) =
+                    run_builtin(BuiltInCommand::Type, &args, &mut ShellState::new());
+
+                assert_eq!(0, status.unwrap());
+                assert_eq!(expected, output);
+            }
+        });
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn it_toggles_options() {
+        let mut state = ShellState::new();
+        let mut run_set = |args: &[&str]| run_builtin(BuiltInCommand::Set, args, &mut state).0;
+
+        run_set(&["-o", "noclobber", "-ex"]).unwrap();
+        run_set(&["+C", "+o", "errexit"]).unwrap();
+        assert!(run_set(&["-o", "unknown"]).is_err());
+        assert!(run_set(&["-q"]).is_err());
+        assert!(run_set(&["noclobber"]).is_err());
+
+        assert!(!state.is_enabled(ShellOption::Noclobber));
+        assert!(!state.is_enabled(ShellOption::Errexit));
+        assert!(state.is_enabled(ShellOption::Xtrace));
+    }
+
+    #[test]
+    fn it_reads_lines_into_variables() {
+        let run_read = |args: &[&str], input: &str| {
+            let (status, _) =
+                run_builtin_with_input(BuiltInCommand::Read, args, input, &mut ShellState::new());
+            status.unwrap()
+        };
+
+        // The last variable gets the rest of the line, and escaped newlines continue it.
+        assert_eq!(
+            0,
+            run_read(
+                &["CODECRAFTERS_A", "CODECRAFTERS_B"],
+                "  first  second \\\n third\\x\nlast line\n"
+            )
+        );
+        assert_eq!("first", std::env::var("CODECRAFTERS_A").unwrap());
+        assert_eq!("second  thirdx", std::env::var("CODECRAFTERS_B").unwrap());
+
+        assert_eq!(0, run_read(&["-r"], "last line\n"));
+        assert_eq!("last line", std::env::var("REPLY").unwrap());
+
+        // Fail at the end of the input.
+        assert_eq!(1, run_read(&["CODECRAFTERS_A"], ""));
+
+        // Fail with a usage error when the prompt is missing.
+        let (status, errors) = run_builtin(BuiltInCommand::Read, &["-p"], &mut ShellState::new());
+        assert_eq!(
+            (2, "read: -p: option requires an argument\n".to_owned()),
+            (status.unwrap(), errors)
+        );
+    }
+
+    #[test]
+    fn it_prints_environment_variables() {
+        std::env::set_var("CODECRAFTERS_PRINTENV", "value");
+
+        let run_printenv = |args: &[&str]| {
+            let (status, output) =
+                run_builtin(BuiltInCommand::Printenv, args, &mut ShellState::new());
+            (status.unwrap(), output)
+        };
+
+        let (status, listing) = run_printenv(&[]);
+        assert_eq!(0, status);
+        assert!(listing
+            .lines()
+            .any(|line| line == "CODECRAFTERS_PRINTENV=value"));
+
+        assert_eq!(
+            (1, "value\n".to_owned()),
+            run_printenv(&["CODECRAFTERS_PRINTENV", "CODECRAFTERS_UNSET"])
+        );
+    }
+}
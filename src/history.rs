@@ -0,0 +1,301 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub(crate) enum HistoryError {
+    #[error("Failed to read the history file {0}: {1}")]
+    ReadFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("Failed to write to the history file {0}: {1}")]
+    WriteFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("{0}: event not found")]
+    EventNotFound(String),
+}
+
+/// The characters ending the prefix of a `!string` reference, e.g. `!ls;`.
+const REFERENCE_TERMINATORS: [char; 9] = [';', '&', '|', '(', ')', '<', '>', '\'', '"'];
+
+/// Which commands are left out of the history, according to the `:`-separated values of
+/// `$HISTCONTROL`: `ignoredups` (the same as the previous entry), `ignorespace` (starting with a
+/// space), or `ignoreboth`.
+#[derive(Clone, Copy)]
+struct HistoryControl {
+    ignore_dups: bool,
+    ignore_space: bool,
+}
+
+impl HistoryControl {
+    /// Reads `$HISTCONTROL`, ignoring duplicates by default.
+    fn from_env() -> Self {
+        std::env::var("HISTCONTROL")
+            .map_or_else(|_| Self::parse("ignoredups"), |value| Self::parse(&value))
+    }
+
+    /// Parses the values, ignoring unknown ones.
+    fn parse(value: &str) -> Self {
+        let values: Vec<_> = value.split(':').collect();
+        let is_set = |name| values.contains(&name) || values.contains(&"ignoreboth");
+
+        Self {
+            ignore_dups: is_set("ignoredups"),
+            ignore_space: is_set("ignorespace"),
+        }
+    }
+}
+
+/// The commands previously entered by the user, oldest first.
+#[derive(Default)]
+pub(crate) struct History {
+    entries: Vec<String>,
+
+    /// The file the history is persisted to, if any.
+    file: Option<PathBuf>,
+}
+
+impl History {
+    pub(crate) fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Loads the entries from the file, which new entries will then be appended to.
+    /// A missing file is not an error, as it gets created when the first entry is pushed.
+    pub(crate) fn load(&mut self, file: PathBuf) -> Result<(), HistoryError> {
+        match File::open(&file) {
+            Ok(handle) => {
+                for line in BufReader::new(handle).lines() {
+                    let line = line.map_err(|e| HistoryError::ReadFailed(file.clone(), e))?;
+
+                    if !line.is_empty() {
+                        self.entries.push(line);
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(HistoryError::ReadFailed(file, e)),
+        }
+
+        self.file = Some(file);
+
+        Ok(())
+    }
+
+    /// Forgets the entries of the current session, without affecting the history file.
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Replaces the references to previous entries in the input, e.g. `!!` for the last one, `!42`
+    /// for the 42nd one, `!-2` for the one before the last, or `!ls` for the last one starting with
+    /// `ls`.
+    ///
+    /// References within single quotes or escaped are kept literally, like a `!` followed by a
+    /// space, `=` or `(`.
+    pub(crate) fn expand(&self, input: &str) -> Result<String, HistoryError> {
+        let mut expanded = String::new();
+
+        let mut is_within_quotes = false;
+        let mut is_within_double_quotes = false;
+        let mut chars = input.chars().peekable();
+        while let Some(char) = chars.next() {
+            match char {
+                '\\' if !is_within_quotes => {
+                    // Keep the escaped character as is, e.g. `\!`.
+                    expanded.push(char);
+                    expanded.extend(chars.next());
+                    continue;
+                }
+                '\'' if !is_within_double_quotes => is_within_quotes = !is_within_quotes,
+                '"' if !is_within_quotes => is_within_double_quotes = !is_within_double_quotes,
+                '!' if !is_within_quotes => {
+                    if let Some(entry) = self.take_reference(&mut chars)? {
+                        expanded.push_str(entry);
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+
+            expanded.push(char);
+        }
+
+        Ok(expanded)
+    }
+
+    /// Takes the reference following a `!` from the characters, returning the entry it refers to, or
+    /// nothing if it isn't a reference.
+    fn take_reference(
+        &self,
+        chars: &mut std::iter::Peekable<std::str::Chars>,
+    ) -> Result<Option<&str>, HistoryError> {
+        let mut reference = String::new();
+        if chars.next_if_eq(&'!').is_some() {
+            reference.push('!');
+        } else {
+            if let Some(sign) = chars.next_if_eq(&'-') {
+                reference.push(sign);
+            }
+            while let Some(char) = chars.next_if(|next| {
+                !next.is_whitespace() && !REFERENCE_TERMINATORS.contains(next) && *next != '='
+            }) {
+                reference.push(char);
+            }
+        }
+
+        let entry = match reference.as_str() {
+            "" => return Ok(None),
+            "!" => self.entries.last(),
+            _ => match reference.parse::<isize>() {
+                // Entries are numbered from 1, negative numbers counting back from the last one.
+                Ok(number) if number > 0 => self.entries.get(number as usize - 1),
+                Ok(number) if number < 0 => self
+                    .entries
+                    .len()
+                    .checked_sub(number.unsigned_abs())
+                    .and_then(|index| self.entries.get(index)),
+                Ok(_) => None,
+                Err(_) => self
+                    .entries
+                    .iter()
+                    .rev()
+                    .find(|entry| entry.starts_with(&reference)),
+            },
+        };
+
+        match entry {
+            Some(entry) => Ok(Some(entry)),
+            None => Err(HistoryError::EventNotFound(format!("!{reference}"))),
+        }
+    }
+
+    /// Records a new entry, and appends it to the history file, unless `$HISTCONTROL` leaves it out.
+    pub(crate) fn push(&mut self, entry: &str) -> Result<(), HistoryError> {
+        self.push_with(entry, HistoryControl::from_env())
+    }
+
+    fn push_with(&mut self, entry: &str, control: HistoryControl) -> Result<(), HistoryError> {
+        // Blank lines are not worth recalling.
+        if entry.trim().is_empty() {
+            return Ok(());
+        }
+
+        if control.ignore_space && entry.starts_with(' ')
+            || control.ignore_dups && self.entries.last().is_some_and(|last| last == entry)
+        {
+            return Ok(());
+        }
+
+        self.entries.push(entry.to_owned());
+
+        if let Some(file) = &self.file {
+            File::options()
+                .create(true)
+                .append(true)
+                .open(file)
+                .and_then(|mut handle| writeln!(handle, "{entry}"))
+                .map_err(|e| HistoryError::WriteFailed(file.clone(), e))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The file to persist the history to: `$HISTFILE`, or `~/.shell_history` by default.
+pub(crate) fn default_history_file() -> Option<PathBuf> {
+    if let Ok(file) = std::env::var("HISTFILE") {
+        return Some(PathBuf::from(file));
+    }
+
+    std::env::var("HOME")
+        .ok()
+        .map(|home| PathBuf::from(home).join(".shell_history"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{History, HistoryControl};
+    use std::fs;
+
+    #[test]
+    fn it_persists_entries_across_sessions() {
+        let file = std::env::temp_dir().join("codecrafters_shell_history.txt");
+        let _ = fs::remove_file(&file);
+
+        // Start with a missing file.
+        let mut history = History::default();
+        history.load(file.clone()).unwrap();
+        assert!(history.entries().is_empty());
+
+        history.push("echo hello").unwrap();
+        history.push("").unwrap();
+        history.push(" \t").unwrap();
+        history.push("pwd").unwrap();
+        assert_eq!(vec!["echo hello", "pwd"], history.entries());
+
+        // Load the entries in a new session.
+        let mut history = History::default();
+        history.load(file.clone()).unwrap();
+        assert_eq!(vec!["echo hello", "pwd"], history.entries());
+
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn it_leaves_out_entries_according_to_the_control() {
+        let mut history = History::default();
+        let control = HistoryControl::parse("ignoredups");
+        for entry in ["ls", "ls", " pwd", "ls", "pwd", "pwd"] {
+            history.push_with(entry, control).unwrap();
+        }
+        assert_eq!(vec!["ls", " pwd", "ls", "pwd"], history.entries());
+
+        let mut history = History::default();
+        let control = HistoryControl::parse("ignorespace");
+        for entry in ["ls", "ls", " pwd", "pwd"] {
+            history.push_with(entry, control).unwrap();
+        }
+        assert_eq!(vec!["ls", "ls", "pwd"], history.entries());
+
+        let mut history = History::default();
+        let control = HistoryControl::parse("ignoreboth");
+        for entry in ["ls", "ls", " pwd", "pwd"] {
+            history.push_with(entry, control).unwrap();
+        }
+        assert_eq!(vec!["ls", "pwd"], history.entries());
+
+        // Keep everything when empty.
+        let mut history = History::default();
+        let control = HistoryControl::parse("");
+        for entry in ["ls", "ls", " pwd"] {
+            history.push_with(entry, control).unwrap();
+        }
+        assert_eq!(vec!["ls", "ls", " pwd"], history.entries());
+    }
+
+    #[test]
+    fn it_expands_references_to_previous_entries() {
+        let mut history = History::default();
+        for entry in ["ls -la", "echo a", "cat file"] {
+            history.push(entry).unwrap();
+        }
+
+        let expand = |input: &str| history.expand(input).map_err(|e| e.to_string());
+
+        assert_eq!(Ok("cat file | wc".to_owned()), expand("!! | wc"));
+        assert_eq!(Ok("echo a;ls -la".to_owned()), expand("!2;!-3"));
+        assert_eq!(Ok("ls -la /; x".to_owned()), expand("!l /; x"));
+        assert_eq!(Ok(r#"echo "echo a""#.to_owned()), expand(r#"echo "!echo""#));
+
+        // Quoted, escaped, and lone `!` are kept literally.
+        assert_eq!(
+            Ok(r"echo '!!' \!! ! a!=b !(x)".to_owned()),
+            expand(r"echo '!!' \!! ! a!=b !(x)")
+        );
+
+        assert_eq!(Err("!foo: event not found".to_owned()), expand("!foo"));
+        assert_eq!(Err("!4: event not found".to_owned()), expand("echo !4"));
+        assert_eq!(Err("!-4: event not found".to_owned()), expand("!-4"));
+    }
+}
@@ -1,9 +1,17 @@
-use crate::autocomplete::{Autocomplete, AutocompleteError};
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
-use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+use crate::autocomplete::{completion_word, Autocomplete, AutocompleteError};
+use crate::history::History;
+use crate::parser::is_incomplete_input;
+use crossterm::cursor::{MoveDown, MoveTo, MoveToColumn, MoveUp};
+use crossterm::event::{
+    self, DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode, KeyEvent, KeyModifiers,
+};
+use crossterm::terminal::{self, disable_raw_mode, enable_raw_mode, Clear, ClearType};
+use crossterm::{execute, queue};
 use std::fmt::Arguments;
 use std::io::{StdoutLock, Write};
 use thiserror::Error;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+use users::{get_current_uid, get_current_username};
 
 #[derive(Error, Debug)]
 pub(crate) enum InputError {
@@ -20,86 +28,280 @@ pub(crate) enum InputError {
     Aborted,
 }
 
+/// The width assumed when the terminal doesn't report its size.
+const DEFAULT_TERMINAL_WIDTH: usize = 80;
+
 /// Takes control of the terminal to capture the input.
 /// Note: this puts the terminal in raw mode and handles every keystroke.
-pub(crate) fn capture_input(autocomplete: &impl Autocomplete) -> Result<String, InputError> {
+pub(crate) fn capture_input(
+    autocomplete: &impl Autocomplete,
+    history: &History,
+) -> Result<String, InputError> {
     // Lock stdout for more repeated writing.
     let mut stdout = std::io::stdout().lock();
 
-    // Prevent the terminal from buffering input, and capture control characters.
-    enable_raw_mode().map_err(InputError::SetupFailed)?;
+    // Prevent the terminal from buffering input, and capture control characters, until returning.
+    let raw_mode = RawMode::enable()?;
 
     // Print the prompt.
-    write(&mut stdout, build_prompt())?;
+    let mut prompt = build_prompt();
+    write(&mut stdout, format_args!("{prompt}"))?;
+
+    // Keep track of the rows taken by the line, as it wraps past the width of the terminal.
+    let mut view = LineView::new();
 
-    // Handles double-presses of TAB to display multiple autocompletes.
+    // Handles double-presses of TAB to cycle through multiple autocompletes.
     let mut multi_autocomplete_on = false;
 
+    // The completions being cycled through on repeated presses of TAB, if any.
+    let mut completion_cycle: Option<CompletionCycle> = None;
+
+    // The history entry being recalled with the arrows, if any.
+    let mut history_navigation = HistoryNavigation::default();
+
     let mut input = String::new();
 
+    // The lines entered so far when the input spans multiple lines, e.g. within quotes.
+    let mut previous_lines = String::new();
+
+    // The byte position of the cursor within the input, always on a char boundary.
+    let mut cursor = 0;
+
+    // The incremental history search in progress, if any.
+    let mut reverse_search: Option<ReverseSearch> = None;
+
     while let Ok(event) = event::read() {
+        // Redraw the line for the new width, assuming that the terminal rewrapped it.
+        if let Event::Resize(columns, _) = event {
+            if let Some(search) = &reverse_search {
+                let line = search.line(history.entries());
+                view.resize(columns, &line, line.len());
+                draw_reverse_search(&mut stdout, &mut view, search, history.entries())?;
+            } else {
+                view.resize(columns, &format!("{prompt}{input}"), prompt.len() + cursor);
+                replace_line(&mut stdout, &mut view, &prompt, &input, cursor)?;
+            }
+            continue;
+        }
+
+        // Insert pasted text as a whole, newlines included, rather than submitting it line by line.
+        if let Event::Paste(text) = event {
+            // Accept the history match and the completion, like any other key.
+            if let Some(index) = reverse_search.take().and_then(|search| search.index) {
+                input = history.entries()[index].clone();
+                cursor = input.len();
+            }
+            completion_cycle = None;
+            multi_autocomplete_on = false;
+
+            // Terminals send the newlines of pasted text as carriage returns.
+            let text = text.replace("\r\n", "\n").replace('\r', "\n");
+            input.insert_str(cursor, &text);
+            cursor += text.len();
+
+            replace_line(&mut stdout, &mut view, &prompt, &input, cursor)?;
+            continue;
+        }
+
         if let Event::Key(KeyEvent {
             code, modifiers, ..
         }) = event
         {
+            // Handle Ctrl+J similarly to `Enter`.
+            let code = match (modifiers, code) {
+                (KeyModifiers::CONTROL, KeyCode::Char('j')) => KeyCode::Enter,
+                _ => code,
+            };
+
             // Disengage multi-autocomplete if any other key than TAB is pressed.
             if code != KeyCode::Tab {
                 multi_autocomplete_on = false;
+
+                // Accept the current candidate, Escape doing nothing else.
+                if completion_cycle.take().is_some() && code == KeyCode::Esc {
+                    continue;
+                }
             }
 
-            match code {
-                KeyCode::Tab => {
-                    let original_input_len = input.len();
+            // Capture the keystrokes while searching through the history.
+            if let Some(search) = &mut reverse_search {
+                let entries = history.entries();
 
-                    // Look for completions for the input.
-                    let mut completions: Vec<_> =
-                        autocomplete.completions(&input)?.into_iter().collect();
+                match (modifiers, code) {
+                    (KeyModifiers::CONTROL, KeyCode::Char('r')) => {
+                        // Jump to the next older match.
+                        let before = search.index.unwrap_or(entries.len());
+                        search.update(find_older_match(entries, &search.query, before));
+                    }
+                    (KeyModifiers::CONTROL, KeyCode::Char('g')) => {
+                        // Cancel the search, restoring the input.
+                        reverse_search = None;
+                        replace_line(&mut stdout, &mut view, &prompt, &input, cursor)?;
+                        continue;
+                    }
+                    (KeyModifiers::NONE | KeyModifiers::SHIFT, KeyCode::Char(character)) => {
+                        // Narrow the search, starting from the current match.
+                        search.query.push(character);
+                        let before = search.index.map_or(entries.len(), |index| index + 1);
+                        search.update(find_older_match(entries, &search.query, before));
+                    }
+                    (_, KeyCode::Backspace) => {
+                        // Widen the search, starting over from the most recent entry.
+                        search.query.pop();
+                        search.update(find_older_match(entries, &search.query, entries.len()));
+                    }
+                    _ => {
+                        // Accept the match on any other key, and handle the key normally.
+                        if let Some(index) = search.index {
+                            input = entries[index].clone();
+                            cursor = input.len();
+                        }
+                        reverse_search = None;
+                        replace_line(&mut stdout, &mut view, &prompt, &input, cursor)?;
 
-                    if !completions.is_empty() {
-                        let longest_prefix = longest_prefix(&completions);
+                        // Escape only leaves the search, to edit the match.
+                        if code == KeyCode::Esc {
+                            continue;
+                        }
+                    }
+                }
 
-                        // Partially autocomplete to the longest common completions prefix.
-                        input.push_str(&longest_prefix[original_input_len..]);
+                if let Some(search) = &reverse_search {
+                    draw_reverse_search(&mut stdout, &mut view, search, entries)?;
+                    continue;
+                }
+            }
 
-                        // Update the terminal accordingly.
-                        write(
-                            &mut stdout,
-                            format_args!("{}", &input[original_input_len..]),
-                        )?;
-                    }
+            // Replace the completed word with the next candidate while cycling.
+            if let Some(cycle) = &mut completion_cycle {
+                let word_start = cycle.word_start;
+                let candidate = cycle.next();
+                input.replace_range(word_start..cursor, candidate);
+                cursor = word_start + candidate.len();
 
-                    if completions.len() == 1 {
-                        // If exactly 1 completion was found, append a space after the command.
-                        input.push(' ');
+                replace_line(&mut stdout, &mut view, &prompt, &input, cursor)?;
+                continue;
+            }
+
+            match code {
+                KeyCode::Tab => {
+                    // Complete the word under the cursor, up to the cursor.
+                    let word = completion_word(&input[..cursor]);
+                    let word_start = word.start;
 
-                        // Update the terminal accordingly.
-                        write(&mut stdout, format_args!(" "))?;
+                    // Look for completions for the word, given the input before it.
+                    let mut completions: Vec<_> = autocomplete
+                        .completions(&input[..cursor])?
+                        .into_iter()
+                        .collect();
+
+                    let longest_prefix = longest_prefix(&completions);
+                    if !completions.is_empty()
+                        && longest_prefix.chars().count() >= word.value.chars().count()
+                    {
+                        // Partially autocomplete to the longest common completions prefix, which
+                        // may be cased differently than the word when ignoring case. The quote
+                        // left open is closed once a file is completed.
+                        let is_final = completions.len() == 1 && !longest_prefix.ends_with('/');
+                        let replacement = word.quote(&longest_prefix, is_final);
+                        input.replace_range(word_start..cursor, &replacement);
+                        cursor = word_start + replacement.len();
+                    }
+
+                    if completions.len() == 1 && completions[0].ends_with('/') {
+                        // Let the user keep completing within a directory.
+                    } else if completions.len() == 1 {
+                        // If exactly 1 completion was found, move past the space after the word,
+                        // inserting it if missing.
+                        if !input[cursor..].starts_with(' ') {
+                            input.insert(cursor, ' ');
+                        }
+                        cursor += 1;
                     } else if completions.len() > 1 && multi_autocomplete_on {
-                        // Print all completions if multiple were found and TAB was pressed twice.
+                        // Cycle through the completions if multiple were found and TAB was pressed
+                        // twice, starting with the first one.
                         completions.sort();
 
-                        // Print a new line below the current one, print all the completions, then
-                        // print the prompt and current input again.
-                        write(
-                            &mut stdout,
-                            format_args!(
-                                "\r\n{}\r\n{}{}",
-                                completions.join("  "),
-                                build_prompt(),
-                                input
-                            ),
-                        )?;
+                        let mut cycle = CompletionCycle {
+                            word_start,
+                            candidates: completions
+                                .iter()
+                                .map(|completion| {
+                                    word.quote(completion, !completion.ends_with('/'))
+                                })
+                                .collect(),
+                            index: None,
+                        };
+                        let candidate = cycle.next();
+                        input.replace_range(word_start..cursor, candidate);
+                        cursor = word_start + candidate.len();
+
+                        completion_cycle = Some(cycle);
                     } else {
                         // No completion found or multiple completions but pressed TAB only once.
                         ring_terminal_bell(&mut stdout)?;
                     }
 
+                    // Update the terminal accordingly.
+                    replace_line(&mut stdout, &mut view, &prompt, &input, cursor)?;
+
                     // Toggle multi-autocompletion, or disable it if len <= 1.
                     multi_autocomplete_on = completions.len() > 1 && !multi_autocomplete_on;
                 }
+                KeyCode::Up => {
+                    // Recall the previous entry, stopping at the oldest one.
+                    if let Some(entry) = history_navigation.previous(history.entries(), &input) {
+                        input = entry.to_owned();
+                        cursor = input.len();
+
+                        replace_line(&mut stdout, &mut view, &prompt, &input, cursor)?;
+                    } else {
+                        ring_terminal_bell(&mut stdout)?;
+                    }
+                }
+                KeyCode::Down => {
+                    // Recall the next entry, or restore the typed line past the newest one.
+                    if let Some(entry) = history_navigation.next(history.entries()) {
+                        input = entry;
+                        cursor = input.len();
+
+                        replace_line(&mut stdout, &mut view, &prompt, &input, cursor)?;
+                    } else {
+                        ring_terminal_bell(&mut stdout)?;
+                    }
+                }
+                KeyCode::Left => {
+                    // Move the cursor one char to the left, if not at the start already.
+                    if let Some(char) = input[..cursor].chars().next_back() {
+                        cursor -= char.len_utf8();
+                        move_cursor(&mut stdout, &mut view, &prompt, &input, cursor)?;
+                    }
+                }
+                KeyCode::Right => {
+                    // Move the cursor one char to the right, if not at the end already.
+                    if let Some(char) = input[cursor..].chars().next() {
+                        cursor += char.len_utf8();
+                        move_cursor(&mut stdout, &mut view, &prompt, &input, cursor)?;
+                    }
+                }
                 KeyCode::Enter => {
-                    // Print a carriage return and a new line.
-                    write(&mut stdout, format_args!("\r\n"))?;
+                    // Move to a new line below the input.
+                    view.move_below(&mut stdout, &format!("{prompt}{input}"))?;
+
+                    // Keep capturing on a new line while the input is incomplete, e.g. `echo 'a`.
+                    if is_incomplete_input(&format!("{previous_lines}{input}")) {
+                        previous_lines.push_str(&input);
+                        previous_lines.push('\n');
+
+                        input.clear();
+                        cursor = 0;
+                        history_navigation = HistoryNavigation::default();
+
+                        prompt = build_continuation_prompt();
+                        write(&mut stdout, format_args!("{prompt}"))?;
+
+                        continue;
+                    }
 
                     // Stop capture.
                     break;
@@ -107,23 +309,94 @@ pub(crate) fn capture_input(autocomplete: &impl Autocomplete) -> Result<String,
                 KeyCode::Char(character) => {
                     match (modifiers, character) {
                         (KeyModifiers::CONTROL, 'c') => {
-                            // Print a carriage return and a new line.
-                            write(&mut stdout, format_args!("\r\n"))?;
+                            // Move to a new line below the input.
+                            view.move_below(&mut stdout, &format!("{prompt}{input}"))?;
 
                             // Handle Ctrl+C to abort the current repl input.
                             return Err(InputError::Aborted);
                         }
-                        (KeyModifiers::CONTROL, 'j') => {
-                            // Print a carriage return and a new line.
-                            write(&mut stdout, format_args!("\r\n"))?;
+                        (KeyModifiers::CONTROL, 'r') => {
+                            // Handle Ctrl+R to search through the history.
+                            let search = ReverseSearch::default();
+                            draw_reverse_search(
+                                &mut stdout,
+                                &mut view,
+                                &search,
+                                history.entries(),
+                            )?;
+                            reverse_search = Some(search);
+                        }
+                        (KeyModifiers::ALT, '=' | '?') => {
+                            // Handle Alt+= to print all the completions of the word under the cursor.
+                            let mut completions: Vec<_> = autocomplete
+                                .completions(&input[..cursor])?
+                                .into_iter()
+                                .collect();
+                            if completions.is_empty() {
+                                ring_terminal_bell(&mut stdout)?;
+                                continue;
+                            }
+                            completions.sort();
+                            completions.dedup();
+
+                            // Print a new line below the current one, print all the completions in
+                            // columns, then print the prompt and current input again.
+                            view.move_below(&mut stdout, &format!("{prompt}{input}"))?;
+                            for row in layout_columns(&completions, view.width) {
+                                write(&mut stdout, format_args!("{row}\r\n"))?;
+                            }
+                            view.cursor_row = 0;
+                            replace_line(&mut stdout, &mut view, &prompt, &input, cursor)?;
+                        }
+                        (KeyModifiers::CONTROL, 'l') => {
+                            // Handle Ctrl+L to clear the screen, keeping the current input.
+                            queue!(stdout, Clear(ClearType::All), MoveTo(0, 0))
+                                .map_err(InputError::WriteStdoutFailed)?;
+                            view.cursor_row = 0;
+
+                            replace_line(&mut stdout, &mut view, &prompt, &input, cursor)?;
+                        }
+                        (KeyModifiers::CONTROL, 'a') => {
+                            // Handle Ctrl+A to move the cursor to the start of the line.
+                            cursor = 0;
+                            move_cursor(&mut stdout, &mut view, &prompt, &input, cursor)?;
+                        }
+                        (KeyModifiers::CONTROL, 'e') => {
+                            // Handle Ctrl+E to move the cursor to the end of the line.
+                            cursor = input.len();
+                            move_cursor(&mut stdout, &mut view, &prompt, &input, cursor)?;
+                        }
+                        (KeyModifiers::CONTROL, 'u') => {
+                            // Handle Ctrl+U to delete from the cursor to the start of the line.
+                            input.drain(..cursor);
+                            cursor = 0;
 
-                            // Handle Ctrl+J similarly to `Enter`.
-                            break;
+                            // Redraw the line, clearing the removed chars from the screen.
+                            replace_line(&mut stdout, &mut view, &prompt, &input, cursor)?;
+                        }
+                        (KeyModifiers::CONTROL, 'k') => {
+                            // Handle Ctrl+K to delete from the cursor to the end of the line.
+                            input.truncate(cursor);
+
+                            // Redraw the line, clearing the removed chars from the screen.
+                            replace_line(&mut stdout, &mut view, &prompt, &input, cursor)?;
+                        }
+                        (KeyModifiers::CONTROL, 'w') => {
+                            // Handle Ctrl+W to delete the word before the cursor.
+                            let word_start = previous_word_start(&input, cursor);
+                            input.drain(word_start..cursor);
+                            cursor = word_start;
+
+                            // Redraw the line, clearing the removed chars from the screen.
+                            replace_line(&mut stdout, &mut view, &prompt, &input, cursor)?;
                         }
                         (KeyModifiers::NONE | KeyModifiers::SHIFT, _) => {
-                            // Add the char to the input string buffer and print it to the terminal.
-                            input.push(character);
-                            write(&mut stdout, format_args!("{character}"))?;
+                            // Insert the char in the input string buffer at the cursor position.
+                            input.insert(cursor, character);
+                            cursor += character.len_utf8();
+
+                            // Redraw the line, shifting the rest of the input.
+                            replace_line(&mut stdout, &mut view, &prompt, &input, cursor)?;
                         }
                         _ => {
                             // Ignore unknown sequences.
@@ -131,33 +404,20 @@ pub(crate) fn capture_input(autocomplete: &impl Autocomplete) -> Result<String,
                     }
                 }
                 KeyCode::Backspace => {
-                    let original_input_len = input.len();
                     if modifiers == KeyModifiers::CONTROL {
                         // Clear the input completely.
                         // TODO: This branch is never hit as some sequences are badly handled by
                         //       crossterm: https://github.com/crossterm-rs/crossterm/issues/685
                         input.clear();
-                    } else {
-                        // Remove one char from the end of the input.
-                        let _ = input.pop();
+                        cursor = 0;
+                    } else if let Some(char) = input[..cursor].chars().next_back() {
+                        // Remove the char before the cursor.
+                        cursor -= char.len_utf8();
+                        input.remove(cursor);
                     }
 
-                    let prompt = build_prompt();
-                    let removed_chars = original_input_len - input.len();
-
-                    // Manually clear the removed char(s) from the screen by printing spaces.
-                    // Print the prompt and the input twice to avoid flashing.
-                    write(
-                        &mut stdout,
-                        format_args!(
-                            "\r{}{}{}\r{}{}",
-                            prompt,
-                            input,
-                            " ".repeat(removed_chars),
-                            prompt,
-                            input
-                        ),
-                    )?;
+                    // Redraw the line, clearing the removed chars from the screen.
+                    replace_line(&mut stdout, &mut view, &prompt, &input, cursor)?;
                 }
                 _ => {
                     // Nothing else is supported for now...
@@ -166,11 +426,177 @@ pub(crate) fn capture_input(autocomplete: &impl Autocomplete) -> Result<String,
         }
     }
 
-    disable_raw_mode().map_err(InputError::SetupFailed)?;
+    raw_mode.disable()?;
+
+    input.insert_str(0, &previous_lines);
 
     Ok(input)
 }
 
+/// Keeps the terminal in raw mode with bracketed paste while alive, restoring it when dropped, e.g.
+/// on errors.
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> Result<Self, InputError> {
+        enable_raw_mode().map_err(InputError::SetupFailed)?;
+        let raw_mode = Self;
+
+        // Receive pasted text at once, to tell its newlines apart from the Enter key.
+        execute!(std::io::stdout(), EnableBracketedPaste).map_err(InputError::SetupFailed)?;
+
+        Ok(raw_mode)
+    }
+
+    /// Restores the terminal, reporting any failure unlike dropping.
+    fn disable(self) -> Result<(), InputError> {
+        std::mem::forget(self);
+        execute!(std::io::stdout(), DisableBracketedPaste).map_err(InputError::SetupFailed)?;
+        disable_raw_mode().map_err(InputError::SetupFailed)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // Nothing more can be done if the terminal can't be restored.
+        let _ = execute!(std::io::stdout(), DisableBracketedPaste);
+        let _ = disable_raw_mode();
+    }
+}
+
+/// Leaves raw mode before reporting any panic, so a crash doesn't leave the terminal unusable.
+pub(crate) fn restore_terminal_on_panic() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = execute!(std::io::stdout(), DisableBracketedPaste);
+        let _ = disable_raw_mode();
+        default_hook(info);
+    }));
+}
+
+/// The completions of a word, cycled through on repeated presses of TAB.
+struct CompletionCycle {
+    /// The byte position of the completed word within the input.
+    word_start: usize,
+
+    candidates: Vec<String>,
+
+    /// The index of the current candidate, if any was inserted yet.
+    index: Option<usize>,
+}
+
+impl CompletionCycle {
+    /// Moves to the next candidate, going back to the first one after the last one.
+    fn next(&mut self) -> &str {
+        let index = self
+            .index
+            .map_or(0, |index| (index + 1) % self.candidates.len());
+        self.index = Some(index);
+
+        &self.candidates[index]
+    }
+}
+
+/// The recall of history entries with the Up and Down arrows, which leaves the entries untouched
+/// when editing the recalled line.
+#[derive(Default)]
+struct HistoryNavigation {
+    /// The index of the entry being recalled, if any.
+    index: Option<usize>,
+
+    /// The line typed before recalling entries, restored when going back down past the newest one.
+    typed_input: String,
+}
+
+impl HistoryNavigation {
+    /// Recalls the entry before the current one, stopping at the oldest one, or returns `None` if
+    /// the history is empty.
+    fn previous<'a>(&mut self, entries: &'a [String], input: &str) -> Option<&'a str> {
+        let index = match self.index {
+            Some(index) => index.saturating_sub(1),
+            None if !entries.is_empty() => {
+                // Keep the typed line aside, to restore it when going back down.
+                self.typed_input = input.to_owned();
+                entries.len() - 1
+            }
+            None => return None,
+        };
+        self.index = Some(index);
+
+        Some(&entries[index])
+    }
+
+    /// Recalls the entry after the current one, or the typed line past the newest one, or returns
+    /// `None` if no entry is being recalled.
+    fn next(&mut self, entries: &[String]) -> Option<String> {
+        let index = self.index? + 1;
+        if index < entries.len() {
+            self.index = Some(index);
+            return Some(entries[index].clone());
+        }
+
+        self.index = None;
+        Some(std::mem::take(&mut self.typed_input))
+    }
+}
+
+/// An incremental search through the history, from the most recent entry to the oldest one.
+#[derive(Default)]
+struct ReverseSearch {
+    query: String,
+
+    /// The index of the history entry matching the query, if any.
+    index: Option<usize>,
+
+    /// Whether the last search didn't find any match.
+    failed: bool,
+}
+
+impl ReverseSearch {
+    /// Moves to the entry found by the last search, or keeps the current one if none was found.
+    fn update(&mut self, found: Option<usize>) {
+        self.failed = found.is_none() && !self.query.is_empty();
+
+        if found.is_some() || self.query.is_empty() {
+            self.index = found;
+        }
+    }
+
+    /// The search prompt followed by the matching entry, if any.
+    fn line(&self, entries: &[String]) -> String {
+        let matched = self.index.map_or("", |index| entries[index].as_str());
+
+        format!(
+            "({}reverse-i-search)`{}': {}",
+            if self.failed { "failed " } else { "" },
+            self.query,
+            matched
+        )
+    }
+}
+
+/// Finds the most recent history entry containing the query, older than the given index.
+fn find_older_match(entries: &[String], query: &str, before: usize) -> Option<usize> {
+    if query.is_empty() {
+        return None;
+    }
+
+    entries[..before.min(entries.len())]
+        .iter()
+        .rposition(|entry| entry.contains(query))
+}
+
+/// Replaces the current line of the terminal with the search prompt and the matching entry.
+fn draw_reverse_search(
+    stdout: &mut StdoutLock,
+    view: &mut LineView,
+    search: &ReverseSearch,
+    entries: &[String],
+) -> Result<(), InputError> {
+    let line = search.line(entries);
+    view.draw(stdout, &line, line.len())
+}
+
 fn longest_prefix(completions: &[String]) -> String {
     let first_completion = completions
         .first()
@@ -183,10 +609,11 @@ fn longest_prefix(completions: &[String]) -> String {
         .map(|completion| completion.chars())
         .collect::<Vec<_>>();
 
-    for (index, char) in first_completion.chars().enumerate() {
+    // Iterate over byte offsets of chars, so the prefix is always cut on a char boundary.
+    for (offset, char) in first_completion.char_indices() {
         for completion in &mut other_completions {
             if completion.next().is_none_or(|c| c != char) {
-                return first_completion.chars().take(index).collect();
+                return first_completion[..offset].to_owned();
             }
         }
     }
@@ -194,9 +621,297 @@ fn longest_prefix(completions: &[String]) -> String {
     first_completion
 }
 
-/// Builds the prompt.
-fn build_prompt() -> Arguments<'static> {
-    format_args!("$ ")
+/// Lays the items out in rows of aligned columns fitting the terminal width, filling the columns
+/// from top to bottom like `ls`.
+fn layout_columns(items: &[String], terminal_width: usize) -> Vec<String> {
+    const SEPARATOR_WIDTH: usize = 2;
+
+    let column_width = items.iter().map(|item| item.width()).max().unwrap_or(0) + SEPARATOR_WIDTH;
+    let columns_count = (terminal_width / column_width).max(1);
+    let rows_count = items.len().div_ceil(columns_count);
+
+    (0..rows_count)
+        .map(|row| {
+            let cells: Vec<&String> = items.iter().skip(row).step_by(rows_count).collect();
+
+            // Pad every cell but the last one of the row to the column width.
+            let mut line = String::new();
+            for (index, cell) in cells.iter().enumerate() {
+                line.push_str(cell);
+                if index + 1 < cells.len() {
+                    line.push_str(&" ".repeat(column_width - cell.width()));
+                }
+            }
+
+            line
+        })
+        .collect()
+}
+
+/// Finds the byte position where the whitespace-delimited word before the cursor starts, including
+/// the whitespaces between the word and the cursor.
+fn previous_word_start(input: &str, cursor: usize) -> usize {
+    let before_cursor = input[..cursor].trim_end();
+
+    before_cursor
+        .char_indices()
+        .rev()
+        .find(|(_, char)| char.is_whitespace())
+        .map_or(0, |(offset, char)| offset + char.len_utf8())
+}
+
+/// Builds the prompt from `$PS1`, or `$ ` by default.
+fn build_prompt() -> String {
+    std::env::var("PS1").map_or_else(|_| "$ ".to_owned(), |template| render_prompt(&template))
+}
+
+/// Builds the prompt of the continuation lines from `$PS2`, or `> ` by default.
+fn build_continuation_prompt() -> String {
+    std::env::var("PS2").map_or_else(|_| "> ".to_owned(), |template| render_prompt(&template))
+}
+
+/// Renders the escapes of the prompt template: `\w` (working directory), `\W` (its last
+/// component), `\u` (user name), `\h` (host name), `\$` (`#` for root, `$` otherwise), and `\\`.
+fn render_prompt(template: &str) -> String {
+    let mut prompt = String::new();
+
+    let mut chars = template.chars();
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            prompt.push(char);
+            continue;
+        }
+
+        match chars.next() {
+            Some('w') => prompt.push_str(&working_directory()),
+            Some('W') => {
+                let directory = working_directory();
+                let basename = match directory.rsplit_once('/') {
+                    Some((_, basename)) if !basename.is_empty() => basename,
+                    _ => &directory,
+                };
+                prompt.push_str(basename);
+            }
+            Some('u') => prompt.push_str(
+                &get_current_username()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+            ),
+            Some('h') => prompt.push_str(hostname().split('.').next().unwrap_or_default()),
+            Some('$') => prompt.push(if get_current_uid() == 0 { '#' } else { '$' }),
+            Some('\\') => prompt.push('\\'),
+            // Keep unknown escapes as they are.
+            Some(other) => {
+                prompt.push(char);
+                prompt.push(other);
+            }
+            None => prompt.push(char),
+        }
+    }
+
+    prompt
+}
+
+/// The current working directory, abbreviating the home directory with `~`.
+fn working_directory() -> String {
+    let cwd = std::env::current_dir()
+        .map(|cwd| cwd.display().to_string())
+        .unwrap_or_default();
+
+    match std::env::var("HOME") {
+        Ok(home) if !home.is_empty() && cwd.starts_with(&home) => {
+            match cwd[home.len()..].chars().next() {
+                None | Some('/') => format!("~{}", &cwd[home.len()..]),
+                _ => cwd,
+            }
+        }
+        _ => cwd,
+    }
+}
+
+/// The name of the machine, or an empty string if it can't be determined.
+fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+
+    // Safe as the length of the buffer is passed along with it.
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) };
+    if result != 0 {
+        return String::new();
+    }
+
+    let length = buffer
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..length]).into_owned()
+}
+
+/// Replaces the line of the terminal with the prompt and the input, then moves the cursor to its
+/// position within the input.
+fn replace_line(
+    stdout: &mut StdoutLock,
+    view: &mut LineView,
+    prompt: &str,
+    input: &str,
+    cursor: usize,
+) -> Result<(), InputError> {
+    view.draw(stdout, &format!("{prompt}{input}"), prompt.len() + cursor)
+}
+
+/// Moves the cursor to its position within the input, which is already drawn after the prompt.
+fn move_cursor(
+    stdout: &mut StdoutLock,
+    view: &mut LineView,
+    prompt: &str,
+    input: &str,
+    cursor: usize,
+) -> Result<(), InputError> {
+    view.move_cursor(stdout, &format!("{prompt}{input}"), prompt.len() + cursor)
+}
+
+/// Where the line is drawn on the terminal, as it wraps over several rows past the terminal width.
+struct LineView {
+    /// The number of columns of the terminal.
+    width: usize,
+
+    /// The row of the cursor, counting from the first row of the line.
+    cursor_row: usize,
+}
+
+impl LineView {
+    fn new() -> Self {
+        let width = match terminal::size() {
+            Ok((columns, _)) if columns > 0 => columns.into(),
+            _ => DEFAULT_TERMINAL_WIDTH,
+        };
+
+        Self {
+            width,
+            cursor_row: 0,
+        }
+    }
+
+    /// Updates the width of the terminal, given the text of the line and the byte position of the
+    /// cursor within it.
+    fn resize(&mut self, columns: u16, text: &str, cursor: usize) {
+        self.width = usize::from(columns).max(1);
+        self.cursor_row = locate_cursor(text, cursor, self.width).0;
+    }
+
+    /// Draws the text over the rows of the current line, then moves the cursor to the given byte
+    /// position within the text.
+    fn draw(
+        &mut self,
+        stdout: &mut StdoutLock,
+        text: &str,
+        cursor: usize,
+    ) -> Result<(), InputError> {
+        // Go back to the first row of the line, and clear it along with the rows below.
+        if self.cursor_row > 0 {
+            let rows = u16::try_from(self.cursor_row).unwrap_or(u16::MAX);
+            queue!(stdout, MoveUp(rows)).map_err(InputError::WriteStdoutFailed)?;
+        }
+        queue!(stdout, MoveToColumn(0), Clear(ClearType::FromCursorDown))
+            .map_err(InputError::WriteStdoutFailed)?;
+        // Return to the start of the row after the newlines, e.g. of pasted text.
+        let lines = text.replace('\n', "\r\n");
+        write!(stdout, "{lines}").map_err(InputError::WriteStdoutFailed)?;
+
+        // Terminals only wrap before the next char, so move to the next row once the last is full.
+        let (end_row, end_column) = locate(text, self.width);
+        if end_column == 0 && end_row > 0 && !text.ends_with('\n') {
+            write!(stdout, "\r\n").map_err(InputError::WriteStdoutFailed)?;
+        }
+
+        // Move back up to the cursor.
+        self.cursor_row = end_row;
+        self.move_cursor(stdout, text, cursor)
+    }
+
+    /// Moves the cursor to the given byte position within the text drawn over the line.
+    fn move_cursor(
+        &mut self,
+        stdout: &mut StdoutLock,
+        text: &str,
+        cursor: usize,
+    ) -> Result<(), InputError> {
+        let (row, column) = locate_cursor(text, cursor, self.width);
+        if row < self.cursor_row {
+            let rows = u16::try_from(self.cursor_row - row).unwrap_or(u16::MAX);
+            queue!(stdout, MoveUp(rows)).map_err(InputError::WriteStdoutFailed)?;
+        } else if row > self.cursor_row {
+            let rows = u16::try_from(row - self.cursor_row).unwrap_or(u16::MAX);
+            queue!(stdout, MoveDown(rows)).map_err(InputError::WriteStdoutFailed)?;
+        }
+        let column = u16::try_from(column).unwrap_or(u16::MAX);
+        queue!(stdout, MoveToColumn(column)).map_err(InputError::WriteStdoutFailed)?;
+        self.cursor_row = row;
+
+        stdout.flush().map_err(InputError::WriteStdoutFailed)
+    }
+
+    /// Moves the cursor to the start of the row below the line drawn with the text, e.g. to print
+    /// the output of a command.
+    fn move_below(&mut self, stdout: &mut StdoutLock, text: &str) -> Result<(), InputError> {
+        // A full last row is followed by the empty row the cursor moved to.
+        let (end_row, end_column) = locate(text, self.width);
+        let last_row = if end_column == 0 {
+            end_row.saturating_sub(1)
+        } else {
+            end_row
+        };
+
+        if self.cursor_row > last_row {
+            write!(stdout, "\r").map_err(InputError::WriteStdoutFailed)?;
+        } else {
+            if last_row > self.cursor_row {
+                let rows = u16::try_from(last_row - self.cursor_row).unwrap_or(u16::MAX);
+                queue!(stdout, MoveDown(rows)).map_err(InputError::WriteStdoutFailed)?;
+            }
+            write!(stdout, "\r\n").map_err(InputError::WriteStdoutFailed)?;
+        }
+        self.cursor_row = 0;
+
+        stdout.flush().map_err(InputError::WriteStdoutFailed)
+    }
+}
+
+/// Finds the row and column where the text drawn from the start of a row ends, given the width of
+/// the terminal, moving to the next row once a row is full or after a newline.
+fn locate(text: &str, width: usize) -> (usize, usize) {
+    let (mut row, mut column) = (0, 0);
+    for char in text.chars() {
+        if char == '\n' {
+            row += 1;
+            column = 0;
+            continue;
+        }
+
+        // Wide chars which don't fit at the end of a row are drawn on the next one.
+        let char_width = char.width().unwrap_or_default();
+        if column + char_width > width {
+            row += 1;
+            column = 0;
+        }
+        column += char_width;
+    }
+
+    if column >= width {
+        (row + 1, 0)
+    } else {
+        (row, column)
+    }
+}
+
+/// Finds the row and column of the cursor at the byte position within the text, like [`locate`].
+fn locate_cursor(text: &str, cursor: usize, width: usize) -> (usize, usize) {
+    let (row, column) = locate(&text[..cursor], width);
+
+    // Sit on the next row when the following wide char doesn't fit on the current one.
+    match text[cursor..].chars().next() {
+        Some(char) if column + char.width().unwrap_or_default() > width => (row + 1, 0),
+        _ => (row, column),
+    }
 }
 
 /// Rings the terminal bell.
@@ -216,7 +931,78 @@ fn write(stdout: &mut StdoutLock, text: Arguments) -> Result<(), InputError> {
 
 #[cfg(test)]
 mod tests {
-    use crate::input::longest_prefix;
+    use crate::input::{
+        find_older_match, layout_columns, locate, locate_cursor, longest_prefix,
+        previous_word_start, render_prompt, CompletionCycle, HistoryNavigation, InputError,
+        RawMode,
+    };
+    use crate::path::run_subshell;
+    use std::collections::HashMap;
+
+    /// Runs the test in a copy of the process, whose input and output are a new pseudo-terminal.
+    fn run_in_terminal(test: impl FnOnce()) {
+        let status = run_subshell(HashMap::new(), || {
+            let (mut master, mut slave) = (0, 0);
+            // SAFETY: the descriptors are valid pointers, and the optional settings are null.
+            let result = unsafe {
+                libc::openpty(
+                    &mut master,
+                    &mut slave,
+                    std::ptr::null_mut(),
+                    std::ptr::null(),
+                    std::ptr::null(),
+                )
+            };
+            assert_eq!(0, result);
+
+            // Make it the controlling terminal of a new session, like for a login shell.
+            // SAFETY: these calls only take descriptors and don't access memory.
+            unsafe {
+                libc::setsid();
+                libc::ioctl(slave, libc::TIOCSCTTY, 0);
+                libc::dup2(slave, libc::STDIN_FILENO);
+                libc::dup2(slave, libc::STDOUT_FILENO);
+            }
+
+            test();
+            0
+        });
+
+        assert_eq!(0, status);
+    }
+
+    /// Whether the terminal of the standard input is in raw mode, i.e. doesn't buffer lines.
+    fn is_raw_mode() -> bool {
+        // SAFETY: the settings are plain data, filled in by the call.
+        let mut settings: libc::termios = unsafe { std::mem::zeroed() };
+        assert_eq!(0, unsafe {
+            libc::tcgetattr(libc::STDIN_FILENO, &mut settings)
+        });
+
+        settings.c_lflag & libc::ICANON == 0
+    }
+
+    #[test]
+    fn it_leaves_raw_mode_on_errors() {
+        run_in_terminal(|| {
+            let fail = || -> Result<(), InputError> {
+                let _raw_mode = RawMode::enable()?;
+                assert!(is_raw_mode());
+
+                Err(InputError::Aborted)
+            };
+
+            // Restore the terminal when returning early, e.g. with `?`.
+            assert!(matches!(fail(), Err(InputError::Aborted)));
+            assert!(!is_raw_mode());
+
+            // Likewise when disabling raw mode explicitly.
+            let raw_mode = RawMode::enable().unwrap();
+            assert!(is_raw_mode());
+            raw_mode.disable().unwrap();
+            assert!(!is_raw_mode());
+        });
+    }
 
     #[test]
     fn it_finds_longest_prefix() {
@@ -249,5 +1035,154 @@ mod tests {
             "a⚠️c",
             longest_prefix(&["a⚠️cdef", "a⚠️c👨‍👩‍👧"].map(ToOwned::to_owned))
         );
+        assert_eq!(
+            "café",
+            longest_prefix(&["café", "cafétière"].map(ToOwned::to_owned))
+        );
+        assert_eq!(
+            "caf",
+            longest_prefix(&["café", "cafè"].map(ToOwned::to_owned))
+        );
+    }
+
+    #[test]
+    fn it_cycles_through_completions() {
+        let mut cycle = CompletionCycle {
+            word_start: 0,
+            candidates: ["echo", "exit"].map(ToOwned::to_owned).to_vec(),
+            index: None,
+        };
+
+        // Start with the first candidate, and go back to it after the last one.
+        assert_eq!("echo", cycle.next());
+        assert_eq!("exit", cycle.next());
+        assert_eq!("echo", cycle.next());
+    }
+
+    #[test]
+    fn it_lays_out_completions_in_columns() {
+        let items = ["a", "bbb", "cc", "d", "eeee", "f", "g"].map(ToOwned::to_owned);
+
+        // Fill the columns from top to bottom, aligned on the widest item.
+        assert_eq!(
+            vec!["a     d     g", "bbb   eeee", "cc    f"],
+            layout_columns(&items, 20)
+        );
+
+        // Use a single column if the terminal is too narrow.
+        assert_eq!(vec!["a", "bbb"], layout_columns(&items[..2], 3));
+        assert_eq!(Vec::<String>::new(), layout_columns(&[], 80));
+
+        // Align wide characters on their displayed width.
+        assert_eq!(
+            vec!["日本  x", "ab"],
+            layout_columns(&["日本", "ab", "x"].map(ToOwned::to_owned), 12)
+        );
+    }
+
+    #[test]
+    fn it_locates_the_end_of_wrapped_text() {
+        assert_eq!((0, 0), locate("", 10));
+        assert_eq!((0, 6), locate("$ echo", 10));
+
+        // Full rows move to the start of the next one.
+        assert_eq!((1, 0), locate("$ echo abc", 10));
+        assert_eq!((2, 2), locate("$ echo abcdefghijklmno", 10));
+
+        // Newlines start a new row.
+        assert_eq!((1, 6), locate("$ a\necho b", 10));
+        assert_eq!((1, 0), locate("$ a\n", 10));
+
+        // Wide chars not fitting at the end of a row move to the next one.
+        assert_eq!((0, 9), locate("$ echo 漢", 10));
+        assert_eq!((1, 4), locate("$ echo 漢字字", 10));
+        assert_eq!((1, 0), locate("$ echo a漢", 10));
+
+        // The cursor sits before a wide char moved to the next row.
+        assert_eq!((0, 9), locate_cursor("$ echo ab", 9, 10));
+        assert_eq!((1, 0), locate_cursor("$ echo ab漢", 9, 10));
+    }
+
+    #[test]
+    fn it_finds_the_previous_word_start() {
+        // Nothing to delete.
+        assert_eq!(0, previous_word_start("", 0));
+        assert_eq!(0, previous_word_start("echo", 0));
+
+        // Delete the word before the cursor, including trailing whitespaces.
+        assert_eq!(5, previous_word_start("echo hello", 10));
+        assert_eq!(5, previous_word_start("echo hello   ", 13));
+        assert_eq!(3, previous_word_start("   echo", 7));
+        assert_eq!(0, previous_word_start("echo hello", 4));
+
+        // Delete up to the cursor only.
+        assert_eq!(5, previous_word_start("echo hello", 8));
+
+        // Including some multibyte characters.
+        assert_eq!(6, previous_word_start("café ⚠️x", 13));
+    }
+
+    #[test]
+    fn it_restores_the_typed_line_past_the_newest_entry() {
+        let entries = vec!["echo a".to_owned(), "echo b".to_owned()];
+        let mut navigation = HistoryNavigation::default();
+
+        // Nothing to go down to before recalling entries.
+        assert_eq!(None, navigation.next(&entries));
+
+        // Go up to the oldest entry, and stay there.
+        assert_eq!(Some("echo b"), navigation.previous(&entries, "foo"));
+        assert_eq!(Some("echo a"), navigation.previous(&entries, "echo b"));
+        assert_eq!(
+            Some("echo a"),
+            navigation.previous(&entries, "echo a edited")
+        );
+
+        // Go back down to the typed line, once only.
+        assert_eq!(Some("echo b".to_owned()), navigation.next(&entries));
+        assert_eq!(Some("foo".to_owned()), navigation.next(&entries));
+        assert_eq!(None, navigation.next(&entries));
+
+        // Nothing to recall from an empty history.
+        assert_eq!(None, HistoryNavigation::default().previous(&[], "foo"));
+    }
+
+    #[test]
+    fn it_finds_older_history_matches() {
+        let entries = ["echo hello", "pwd", "echo world", "ls"].map(ToOwned::to_owned);
+
+        // Find the most recent match first.
+        assert_eq!(Some(2), find_older_match(&entries, "echo", entries.len()));
+
+        // Then older ones.
+        assert_eq!(Some(0), find_older_match(&entries, "echo", 2));
+        assert_eq!(None, find_older_match(&entries, "echo", 0));
+
+        // No match, or nothing to search.
+        assert_eq!(None, find_older_match(&entries, "cd", entries.len()));
+        assert_eq!(None, find_older_match(&entries, "", entries.len()));
+        assert_eq!(None, find_older_match(&[], "echo", 0));
+    }
+
+    #[test]
+    fn it_renders_prompt_escapes() {
+        let symbol = if users::get_current_uid() == 0 {
+            "#"
+        } else {
+            "$"
+        };
+
+        assert_eq!(format!("{symbol} "), render_prompt(r"\$ "));
+        assert_eq!(r"a\b \x \", render_prompt(r"a\\b \x \"));
+
+        // Render the working directory, relative to the home directory if within it.
+        let cwd = std::env::current_dir().unwrap();
+        let rendered = render_prompt(r"\w");
+        assert!(rendered.starts_with('~') || rendered == cwd.display().to_string());
+        assert!(rendered.ends_with(&cwd.file_name().unwrap().to_string_lossy().into_owned()));
+        assert_eq!(
+            cwd.file_name().unwrap().to_string_lossy(),
+            render_prompt(r"\W")
+        );
     }
 }
@@ -1,7 +1,10 @@
-use crate::parser::{Descriptor, Redirect, RedirectTo};
+use crate::parser::{expand_chunk, Descriptor, ExpansionError, Redirect, RedirectMode, RedirectTo};
+use crate::state::{ShellOption, ShellState};
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::{stderr, stdout, Stderr, Stdout, Write};
+use std::io::{stderr, stdout, PipeReader, PipeWriter, Read, Stderr, Stdout, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::Path;
 use std::process::Stdio;
 use thiserror::Error;
 
@@ -10,16 +13,30 @@ pub(crate) enum IoError {
     #[error("IO error occurred: {0}")]
     StdIo(#[from] std::io::Error),
 
-    #[error("Descriptor {0} is not supported")]
-    UnsupportedDescriptor(u8),
+    #[error("{0}: {1}")]
+    OpenFailed(String, #[source] std::io::Error),
+
+    #[error(transparent)]
+    Expansion(#[from] ExpansionError),
+
+    #[error("{0}: cannot overwrite existing file")]
+    Clobber(String),
+
+    #[error("{0}: Bad file descriptor")]
+    BadDescriptor(u8),
 }
 
+pub(crate) const NULL_DEVICE: &str = "/dev/null";
+
 //TODO: Is an enum really useful here? an opaque struct hiding the Stdout and Stderr would be better.
 pub(crate) enum FileDescriptor {
     Stdout(Stdout),
     Stderr(Stderr),
     //TODO: a BufWriter would be efficient for writing, but cannot be converted into Stdio required by process::Command
     File(File),
+    InputFile(File),
+    PipeReader(PipeReader),
+    PipeWriter(PipeWriter),
 }
 
 impl FileDescriptor {
@@ -37,10 +54,65 @@ impl FileDescriptor {
             .write(true)
             .append(append)
             .truncate(!append)
-            .open(filename)?;
+            .open(filename)
+            .map_err(|e| IoError::OpenFailed(filename.to_owned(), e))?;
 
         Ok(FileDescriptor::File(file))
     }
+
+    pub(crate) fn input_file(filename: &str) -> Result<Self, IoError> {
+        let file = File::open(filename).map_err(|e| IoError::OpenFailed(filename.to_owned(), e))?;
+
+        Ok(FileDescriptor::InputFile(file))
+    }
+
+    /// Feeds the text to a pipe from another thread, so it can be read without blocking the shell
+    /// on large texts.
+    pub(crate) fn text(text: String) -> Result<Self, IoError> {
+        let (reader, mut writer) = std::io::pipe()?;
+        std::thread::spawn(move || {
+            // The reader may stop reading early, e.g. `head`, which is not an error.
+            let _ = writer.write_all(text.as_bytes());
+        });
+
+        Ok(FileDescriptor::PipeReader(reader))
+    }
+
+    /// Wraps a pipe writer into a pipe which never blocks its writers, buffering everything written
+    /// to it from another thread, then forwarding it to the writer once all its writers are closed.
+    ///
+    /// This lets the shell write into a pipeline before the next commands are started, e.g.
+    /// builtins, whereas writing more than the capacity of the pipe would block it forever.
+    pub(crate) fn buffered(self) -> Result<Self, IoError> {
+        let FileDescriptor::PipeWriter(mut destination) = self else {
+            return Ok(self);
+        };
+
+        let (mut reader, writer) = std::io::pipe()?;
+        std::thread::spawn(move || {
+            let mut buffer = vec![];
+            if reader.read_to_end(&mut buffer).is_ok() {
+                // The next command may stop reading early, e.g. `head`, which is not an error.
+                let _ = destination.write_all(&buffer);
+            }
+        });
+
+        Ok(FileDescriptor::PipeWriter(writer))
+    }
+
+    /// Duplicates the descriptor, so it refers to the same file or pipe.
+    pub(crate) fn try_clone(&self) -> Result<Self, IoError> {
+        let descriptor = match self {
+            FileDescriptor::Stdout(_) => FileDescriptor::stdout(),
+            FileDescriptor::Stderr(_) => FileDescriptor::stderr(),
+            FileDescriptor::File(file) => FileDescriptor::File(file.try_clone()?),
+            FileDescriptor::InputFile(file) => FileDescriptor::InputFile(file.try_clone()?),
+            FileDescriptor::PipeReader(reader) => FileDescriptor::PipeReader(reader.try_clone()?),
+            FileDescriptor::PipeWriter(writer) => FileDescriptor::PipeWriter(writer.try_clone()?),
+        };
+
+        Ok(descriptor)
+    }
 }
 
 impl From<FileDescriptor> for Stdio {
@@ -49,7 +121,21 @@ impl From<FileDescriptor> for Stdio {
             //TODO: might need to wrap in a Lock to allow cloning and having multiple writers?
             FileDescriptor::Stdout(stdout) => stdout.into(),
             FileDescriptor::Stderr(stderr) => stderr.into(),
-            FileDescriptor::File(file) => file.into(),
+            FileDescriptor::File(file) | FileDescriptor::InputFile(file) => file.into(),
+            FileDescriptor::PipeReader(reader) => reader.into(),
+            FileDescriptor::PipeWriter(writer) => writer.into(),
+        }
+    }
+}
+
+impl AsRawFd for FileDescriptor {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            FileDescriptor::Stdout(stdout) => stdout.as_raw_fd(),
+            FileDescriptor::Stderr(stderr) => stderr.as_raw_fd(),
+            FileDescriptor::File(file) | FileDescriptor::InputFile(file) => file.as_raw_fd(),
+            FileDescriptor::PipeReader(reader) => reader.as_raw_fd(),
+            FileDescriptor::PipeWriter(writer) => writer.as_raw_fd(),
         }
     }
 }
@@ -60,6 +146,8 @@ impl Write for FileDescriptor {
             FileDescriptor::Stdout(stdout) => stdout.write(buf),
             FileDescriptor::Stderr(stderr) => stderr.write(buf),
             FileDescriptor::File(file) => file.write(buf),
+            FileDescriptor::InputFile(_) | FileDescriptor::PipeReader(_) => Err(read_only_error()),
+            FileDescriptor::PipeWriter(writer) => writer.write(buf),
         }
     }
 
@@ -68,33 +156,169 @@ impl Write for FileDescriptor {
             FileDescriptor::Stdout(stdout) => stdout.flush(),
             FileDescriptor::Stderr(stderr) => stderr.flush(),
             FileDescriptor::File(file) => file.flush(),
+            FileDescriptor::InputFile(_) | FileDescriptor::PipeReader(_) => Err(read_only_error()),
+            FileDescriptor::PipeWriter(writer) => writer.flush(),
         }
     }
 }
 
-pub(crate) fn resolve_redirects(
-    redirects: &[Redirect],
-) -> Result<HashMap<Descriptor, FileDescriptor>, IoError> {
-    //TODO: Before actually opening files, resolve which RedirectTo 1 and 2 go to after going through all redirections, then there's just 2 files to open
+impl Read for FileDescriptor {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            FileDescriptor::File(file) | FileDescriptor::InputFile(file) => file.read(buf),
+            FileDescriptor::PipeReader(reader) => reader.read(buf),
+            FileDescriptor::Stdout(_)
+            | FileDescriptor::Stderr(_)
+            | FileDescriptor::PipeWriter(_) => Err(write_only_error()),
+        }
+    }
+}
 
-    let mut descriptors: HashMap<Descriptor, FileDescriptor> = HashMap::new();
+fn write_only_error() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "cannot read from an output descriptor",
+    )
+}
 
+fn read_only_error() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "cannot write to an input descriptor",
+    )
+}
+
+/// Applies the redirections from left to right on top of the descriptors, e.g. the pipes.
+///
+/// Duplicating a descriptor copies where it points at that point in time, so `2> out.txt 1>&2`
+/// writes stdout to the file whereas `1>&2 2> out.txt` writes it to the previous stderr.
+pub(crate) fn resolve_redirects(
+    redirects: &[Redirect],
+    descriptors: &mut HashMap<Descriptor, FileDescriptor>,
+    state: &mut ShellState,
+) -> Result<(), IoError> {
     for redirect in redirects {
         let destination = match redirect.to() {
-            RedirectTo::Descriptor(Descriptor(to)) => match to {
-                1 => FileDescriptor::stdout(),
-                2 => FileDescriptor::stderr(),
-                _ => return Err(IoError::UnsupportedDescriptor(to)),
+            RedirectTo::Descriptor(to) => match descriptors.get(&to) {
+                Some(descriptor) => descriptor.try_clone()?,
+                None if to == Descriptor::stdout() => FileDescriptor::stdout(),
+                None if to == Descriptor::stderr() => FileDescriptor::stderr(),
+                None => return Err(IoError::BadDescriptor(to.0)),
             },
-            RedirectTo::File(filename) => FileDescriptor::file(&filename, redirect.append())?,
+            RedirectTo::File(filename) => {
+                let filename = expand_chunk(&filename, state)?;
+
+                match redirect.mode() {
+                    RedirectMode::Read => FileDescriptor::input_file(&filename)?,
+                    // Only overwrite regular files with noclobber, e.g. `/dev/null` is fine.
+                    RedirectMode::Truncate
+                        if state.is_enabled(ShellOption::Noclobber)
+                            && Path::new(&filename).is_file() =>
+                    {
+                        return Err(IoError::Clobber(filename));
+                    }
+                    RedirectMode::Truncate | RedirectMode::Clobber => {
+                        FileDescriptor::file(&filename, false)?
+                    }
+                    RedirectMode::Append => FileDescriptor::file(&filename, true)?,
+                }
+            }
+            // Route closed descriptors to nowhere, so commands don't fail writing to them.
+            RedirectTo::Closed if redirect.mode() == RedirectMode::Read => {
+                FileDescriptor::input_file(NULL_DEVICE)?
+            }
+            RedirectTo::Closed => FileDescriptor::file(NULL_DEVICE, false)?,
+            RedirectTo::Text(text) => FileDescriptor::text(expand_chunk(&text, state)?)?,
         };
 
         descriptors.insert(redirect.from(), destination);
     }
 
-    Ok(descriptors)
+    Ok(())
 }
 
-//TODO: test this:
-// -  echo hello '|' world 2> out.txt 1>&2 : writes to out.txt
-// -  echo hello '|' world 1>&2 2> out.txt : writes to stdout, because 1>&2 writes to stderr before the redirection is set up
+#[cfg(test)]
+mod tests {
+    use super::{resolve_redirects, FileDescriptor, IoError};
+    use crate::parser::{parse_input, Descriptor};
+    use crate::state::{ShellOption, ShellState};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io::Write;
+
+    /// Writes to stdout after applying the redirections of the command, with stderr initially
+    /// going to the given file.
+    fn write_stdout_with_redirects(input: &str, stderr: &str) {
+        let mut state = ShellState::new();
+        let lists = parse_input(input, &state).unwrap();
+
+        let mut descriptors = HashMap::new();
+        descriptors.insert(
+            Descriptor::stderr(),
+            FileDescriptor::file(stderr, false).unwrap(),
+        );
+
+        resolve_redirects(
+            lists[0].first()[0].redirects(),
+            &mut descriptors,
+            &mut state,
+        )
+        .unwrap();
+
+        write!(descriptors.get_mut(&Descriptor::stdout()).unwrap(), "hello").unwrap();
+    }
+
+    #[test]
+    fn it_applies_redirects_from_left_to_right() {
+        let stderr = std::env::temp_dir().join("codecrafters_shell_io_stderr.txt");
+        let output = std::env::temp_dir().join("codecrafters_shell_io_out.txt");
+
+        // Stderr is redirected to the file before stdout is duplicated from it.
+        write_stdout_with_redirects(
+            &format!("echo hello 2> {} 1>&2", output.display()),
+            &stderr.display().to_string(),
+        );
+
+        assert_eq!("hello", fs::read_to_string(&output).unwrap());
+        assert_eq!("", fs::read_to_string(&stderr).unwrap());
+
+        // Stdout is duplicated from the previous stderr, before stderr is redirected to the file.
+        write_stdout_with_redirects(
+            &format!("echo hello 1>&2 2> {}", output.display()),
+            &stderr.display().to_string(),
+        );
+
+        assert_eq!("", fs::read_to_string(&output).unwrap());
+        assert_eq!("hello", fs::read_to_string(&stderr).unwrap());
+
+        fs::remove_file(stderr).unwrap();
+        fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn it_refuses_to_clobber_files_with_noclobber() {
+        let output = std::env::temp_dir().join("codecrafters_shell_io_noclobber.txt");
+        fs::write(&output, "kept").unwrap();
+
+        let mut state = ShellState::new();
+        state.set_option(ShellOption::Noclobber, true);
+        let mut resolve = |input: &str| {
+            let lists = parse_input(&format!("{input} {}", output.display()), &state).unwrap();
+            resolve_redirects(
+                lists[0].first()[0].redirects(),
+                &mut HashMap::new(),
+                &mut state,
+            )
+        };
+
+        assert!(matches!(resolve("echo >"), Err(IoError::Clobber(_))));
+        assert_eq!("kept", fs::read_to_string(&output).unwrap());
+
+        // Appending and forcing are still allowed.
+        resolve("echo >>").unwrap();
+        resolve("echo >|").unwrap();
+        assert_eq!("", fs::read_to_string(&output).unwrap());
+
+        fs::remove_file(output).unwrap();
+    }
+}
@@ -0,0 +1,471 @@
+use crate::runner::exit_code;
+use std::fmt::{Display, Formatter};
+use std::io::{ErrorKind, IsTerminal};
+use std::os::fd::{AsFd, AsRawFd, OwnedFd, RawFd};
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+/// The signals which can be referred to by name, e.g. `TERM` or `SIGTERM`.
+const SIGNALS: [(&str, i32); 19] = [
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("ILL", libc::SIGILL),
+    ("TRAP", libc::SIGTRAP),
+    ("ABRT", libc::SIGABRT),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("SEGV", libc::SIGSEGV),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CHLD", libc::SIGCHLD),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("TSTP", libc::SIGTSTP),
+    ("TTIN", libc::SIGTTIN),
+    ("TTOU", libc::SIGTTOU),
+];
+
+/// Parses a signal given by number or name, with or without the `SIG` prefix, ignoring case.
+pub(crate) fn parse_signal(signal: &str) -> Option<i32> {
+    if let Ok(number) = signal.parse::<i32>() {
+        // Signal 0 only checks whether the process exists.
+        return (0..=libc::SIGRTMAX()).contains(&number).then_some(number);
+    }
+
+    let name = signal.to_ascii_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+
+    SIGNALS
+        .iter()
+        .find(|(signal_name, _)| *signal_name == name)
+        .map(|(_, number)| *number)
+}
+
+/// The state of a process of a job.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ProcessState {
+    Running,
+    Stopped,
+
+    /// The process finished, with its exit status.
+    Exited(i32),
+}
+
+/// A process started as part of a job.
+struct Process {
+    pid: u32,
+    state: ProcessState,
+}
+
+/// The state of a job, as a whole.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum JobState {
+    Running,
+
+    /// At least one process was stopped, e.g. with Ctrl+Z.
+    Stopped,
+
+    /// All the processes finished, with the exit status of the last one.
+    Done(i32),
+}
+
+/// A pipeline running in the background.
+pub(crate) struct Job {
+    id: usize,
+
+    /// The processes of the pipeline, in order.
+    processes: Vec<Process>,
+
+    /// The process group of the pipeline, i.e. the process id of its first command.
+    process_group: u32,
+
+    /// The command line of the pipeline.
+    command: String,
+}
+
+impl Job {
+    pub(crate) fn id(&self) -> usize {
+        self.id
+    }
+
+    pub(crate) fn command(&self) -> &str {
+        &self.command
+    }
+
+    pub(crate) fn process_group(&self) -> u32 {
+        self.process_group
+    }
+
+    /// The process ids of all the commands of the pipeline.
+    pub(crate) fn pids(&self) -> Vec<u32> {
+        self.processes.iter().map(|process| process.pid).collect()
+    }
+
+    /// The process id of the last command of the pipeline, if any.
+    pub(crate) fn pid(&self) -> Option<u32> {
+        self.processes.last().map(|process| process.pid)
+    }
+
+    pub(crate) fn state(&self) -> JobState {
+        let states: Vec<_> = self.processes.iter().map(|process| process.state).collect();
+
+        match states.last() {
+            None => JobState::Done(0),
+            Some(ProcessState::Exited(status)) if states.iter().all(is_exited) => {
+                JobState::Done(*status)
+            }
+            _ if states.contains(&ProcessState::Stopped) => JobState::Stopped,
+            _ => JobState::Running,
+        }
+    }
+
+    /// Checks whether the processes have finished or stopped, without blocking.
+    fn poll(&mut self) -> std::io::Result<()> {
+        // Check every process, so the finished ones get reaped.
+        for process in &mut self.processes {
+            while !is_exited(&process.state) {
+                match wait_for_change(process.pid, false)? {
+                    Some(state) => process.state = state,
+                    None => break,
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Waits for all the processes to finish, or for any of them to stop.
+    pub(crate) fn wait(&mut self) -> std::io::Result<()> {
+        for process in &mut self.processes {
+            while process.state == ProcessState::Running {
+                match wait_for_change(process.pid, true) {
+                    Ok(state) => process.state = state.unwrap_or(process.state),
+                    Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                    Err(error) => return Err(error),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Resumes the stopped processes, e.g. after Ctrl+Z.
+    pub(crate) fn resume(&mut self) -> std::io::Result<()> {
+        // Signal the processes rather than their group, which is the shell's one in scripts.
+        for process in &mut self.processes {
+            if process.state != ProcessState::Stopped {
+                continue;
+            }
+
+            // SAFETY: sending a signal has no memory safety requirements.
+            if unsafe { libc::kill(process.pid as libc::pid_t, libc::SIGCONT) } < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            process.state = ProcessState::Running;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Job {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let state = match self.state() {
+            JobState::Running => "Running".to_owned(),
+            JobState::Stopped => "Stopped".to_owned(),
+            JobState::Done(0) => "Done".to_owned(),
+            JobState::Done(status) => format!("Exit {status}"),
+        };
+
+        write!(
+            f,
+            "[{}]  {}  {state:<10}{}",
+            self.id,
+            self.pid().unwrap_or_default(),
+            self.command
+        )
+    }
+}
+
+fn is_exited(state: &ProcessState) -> bool {
+    matches!(state, ProcessState::Exited(_))
+}
+
+/// Waits for the process to finish, stop or continue, or only checks whether it did without
+/// blocking, in which case `None` is returned if nothing changed.
+fn wait_for_change(pid: u32, blocking: bool) -> std::io::Result<Option<ProcessState>> {
+    let mut options = libc::WUNTRACED | libc::WCONTINUED;
+    if !blocking {
+        options |= libc::WNOHANG;
+    }
+
+    let mut status = 0;
+    // SAFETY: the status points to a valid integer.
+    let pid = unsafe { libc::waitpid(pid as libc::pid_t, &mut status, options) };
+    if pid < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if pid == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(if libc::WIFSTOPPED(status) {
+        ProcessState::Stopped
+    } else if libc::WIFCONTINUED(status) {
+        ProcessState::Running
+    } else {
+        ProcessState::Exited(exit_code(ExitStatus::from_raw(status)))
+    }))
+}
+
+/// The process group to start a command of a pipeline in.
+#[derive(Clone, Copy)]
+pub(crate) enum ProcessGroup {
+    /// A new group led by the command, which takes the terminal if it runs in the foreground.
+    New { foreground: bool },
+
+    /// The group of the first command of the pipeline.
+    Join(u32),
+}
+
+/// Whether job control is disabled, e.g. in subshells.
+static IS_JOB_CONTROL_DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Disables job control in the current process, e.g. in subshells, so their commands stay in their
+/// process group and get interrupted along with them, like in non-interactive shells.
+pub(crate) fn disable_job_control() {
+    IS_JOB_CONTROL_DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// A duplicate of the terminal input of the shell, if it is interactive and job control is enabled.
+///
+/// Children can still use it right before running their binary, even if their input is
+/// redirected, as it is only closed then.
+pub(crate) fn terminal() -> Option<RawFd> {
+    static TERMINAL: OnceLock<Option<OwnedFd>> = OnceLock::new();
+
+    if IS_JOB_CONTROL_DISABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    TERMINAL
+        .get_or_init(|| {
+            let stdin = std::io::stdin();
+            if !stdin.is_terminal() {
+                return None;
+            }
+
+            stdin.as_fd().try_clone_to_owned().ok()
+        })
+        .as_ref()
+        .map(AsRawFd::as_raw_fd)
+}
+
+/// Makes the process group the foreground one of the terminal, so it receives the keyboard input
+/// and signals, e.g. Ctrl+C or Ctrl+Z.
+///
+/// Only calls async-signal-safe functions, so children can take the terminal before running their
+/// binary.
+pub(crate) fn set_foreground_process_group(terminal: RawFd, process_group: libc::pid_t) {
+    // Block SIGTTOU while doing so, which would otherwise stop processes outside the foreground.
+    // SAFETY: the signal sets are initialised before being used.
+    unsafe {
+        let mut signals = std::mem::zeroed();
+        let mut previous_signals = std::mem::zeroed();
+        libc::sigemptyset(&mut signals);
+        libc::sigaddset(&mut signals, libc::SIGTTOU);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &signals, &mut previous_signals);
+
+        libc::tcsetpgrp(terminal, process_group);
+
+        libc::pthread_sigmask(libc::SIG_SETMASK, &previous_signals, std::ptr::null_mut());
+    }
+}
+
+/// Gives the terminal to the process group, if the shell is interactive.
+pub(crate) fn give_terminal_to(process_group: u32) {
+    if let Some(terminal) = terminal() {
+        set_foreground_process_group(terminal, process_group as libc::pid_t);
+    }
+}
+
+/// Gives the terminal back to the shell.
+pub(crate) fn take_terminal_back() {
+    // SAFETY: getting the process group has no memory safety requirements.
+    give_terminal_to(unsafe { libc::getpgrp() } as u32);
+}
+
+/// The jobs running in the background, by increasing id.
+#[derive(Default)]
+pub(crate) struct Jobs {
+    jobs: Vec<Job>,
+}
+
+impl Jobs {
+    /// Records the processes of a pipeline started in its own process group as a new job.
+    pub(crate) fn add(&mut self, pids: Vec<u32>, command: String) -> &mut Job {
+        // Number jobs after the most recent one, starting over once they have all finished.
+        let id = self.jobs.last().map_or(1, |job| job.id + 1);
+
+        let processes: Vec<_> = pids
+            .into_iter()
+            .map(|pid| Process {
+                pid,
+                state: ProcessState::Running,
+            })
+            .collect();
+
+        self.jobs.push(Job {
+            id,
+            process_group: processes.first().map_or(0, |process| process.pid),
+            processes,
+            command,
+        });
+
+        let index = self.jobs.len() - 1;
+        &mut self.jobs[index]
+    }
+
+    /// Finds the job given as `%id` or `id`, or the most recent one by default.
+    pub(crate) fn find_mut(&mut self, id: Option<&str>) -> Option<&mut Job> {
+        match id {
+            None => self.jobs.last_mut(),
+            Some(id) => {
+                let id: usize = id.strip_prefix('%').unwrap_or(id).parse().ok()?;
+                self.jobs.iter_mut().find(|job| job.id == id)
+            }
+        }
+    }
+
+    /// Forgets the job, e.g. once it finished in the foreground.
+    pub(crate) fn remove(&mut self, id: usize) {
+        self.jobs.retain(|job| job.id != id);
+    }
+
+    /// Updates the state of the jobs, and returns all of them.
+    pub(crate) fn poll(&mut self) -> &[Job] {
+        for job in &mut self.jobs {
+            // Jobs which can't be waited for anymore are considered as still running.
+            let _ = job.poll();
+        }
+
+        &self.jobs
+    }
+
+    /// Forgets the finished jobs, so they are only reported once, and returns them.
+    pub(crate) fn remove_finished(&mut self) -> Vec<Job> {
+        let (finished, running) = std::mem::take(&mut self.jobs)
+            .into_iter()
+            .partition(|job| matches!(job.state(), JobState::Done(_)));
+
+        self.jobs = running;
+
+        finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_signal, JobState, Jobs};
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    #[test]
+    fn it_parses_signals() {
+        assert_eq!(Some(libc::SIGKILL), parse_signal("9"));
+        assert_eq!(Some(0), parse_signal("0"));
+        assert_eq!(Some(libc::SIGTERM), parse_signal("TERM"));
+        assert_eq!(Some(libc::SIGTERM), parse_signal("sigterm"));
+        assert_eq!(Some(libc::SIGHUP), parse_signal("SIGHUP"));
+        assert_eq!(None, parse_signal("-1"));
+        assert_eq!(None, parse_signal("FOO"));
+    }
+
+    #[test]
+    fn it_tracks_background_jobs_until_they_finish() {
+        let mut jobs = Jobs::default();
+
+        let pids = spawn(&[&["false"]]);
+        let pid = pids[0];
+        assert_eq!(1, jobs.add(pids, "false".to_owned()).id());
+
+        assert_eq!(
+            2,
+            jobs.add(spawn(&[&["sleep", "5"]]), "sleep 5".to_owned())
+                .id()
+        );
+
+        // Wait for the first job to finish.
+        while jobs.poll()[0].state() == JobState::Running {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        assert_eq!(
+            format!("[1]  {pid}  Exit 1    false"),
+            jobs.poll()[0].to_string()
+        );
+        assert!(jobs.poll()[1].to_string().contains("Running   sleep 5"));
+
+        // Report the finished jobs only once.
+        let finished = jobs.remove_finished();
+        assert_eq!(1, finished.len());
+        assert_eq!(1, finished[0].id());
+        assert!(jobs.remove_finished().is_empty());
+
+        // Number new jobs after the remaining ones.
+        assert_eq!(3, jobs.add(spawn(&[&["true"]]), "true".to_owned()).id());
+
+        for job in jobs.jobs.iter_mut() {
+            unsafe { libc::killpg(job.process_group() as libc::pid_t, libc::SIGKILL) };
+            job.wait().unwrap();
+        }
+    }
+
+    #[test]
+    fn it_stops_and_resumes_jobs() {
+        let mut jobs = Jobs::default();
+        jobs.add(
+            spawn(&[&["sleep", "5"], &["sleep", "5"]]),
+            "sleep 5 | sleep 5".to_owned(),
+        );
+        let job = jobs.find_mut(Some("%1")).unwrap();
+        let process_group = job.process_group() as libc::pid_t;
+
+        // Stopping any process stops the job.
+        unsafe { libc::killpg(process_group, libc::SIGSTOP) };
+        job.wait().unwrap();
+        assert_eq!(JobState::Stopped, job.state());
+
+        job.resume().unwrap();
+        assert_eq!(JobState::Running, job.state());
+
+        unsafe { libc::killpg(process_group, libc::SIGTERM) };
+        job.wait().unwrap();
+        assert_eq!(JobState::Done(143), job.state());
+
+        jobs.remove(1);
+        assert!(jobs.find_mut(None).is_none());
+    }
+
+    /// Spawns the commands in a new process group, like a background pipeline.
+    fn spawn(commands: &[&[&str]]) -> Vec<u32> {
+        let mut pids: Vec<u32> = vec![];
+        for command in commands {
+            let process_group = pids.first().map_or(0, |pid| *pid as i32);
+            let pid = Command::new(command[0])
+                .args(&command[1..])
+                .process_group(process_group)
+                .spawn()
+                .unwrap()
+                .id();
+            pids.push(pid);
+        }
+
+        pids
+    }
+}
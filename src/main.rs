@@ -1,17 +1,27 @@
 mod autocomplete;
 mod builtin;
+mod history;
 mod input;
 mod io;
+mod jobs;
 mod parser;
 mod path;
 mod runner;
+mod state;
 
 use crate::autocomplete::CompositeAutocomplete;
-use crate::builtin::BuiltInCommandError;
-use crate::input::{capture_input, InputError};
+use crate::builtin::{describe_io_error, BuiltInCommandError};
+use crate::history::{default_history_file, HistoryError};
+use crate::input::{capture_input, restore_terminal_on_panic, InputError};
+use crate::jobs::disable_job_control;
 use crate::parser::{parse_input, ParsingError};
-use crate::runner::{run_commands, RunnerError};
+use crate::runner::{run_commands, run_script, RunnerError};
+use crate::state::ShellState;
+use signal_hook::consts::{SIGINT, SIGTSTP};
+use std::path::PathBuf;
 use std::process::exit;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -19,6 +29,9 @@ enum ShellError {
     #[error(transparent)]
     Autocomplete(#[from] InputError),
 
+    #[error(transparent)]
+    History(#[from] HistoryError),
+
     #[error(transparent)]
     Parsing(#[from] ParsingError),
 
@@ -27,8 +40,43 @@ enum ShellError {
 }
 
 fn main() {
+    // Run the command string or the script non-interactively if given, e.g. `-c 'echo hi'`.
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(status) = run_non_interactively(&args) {
+        exit(status);
+    }
+
+    // Don't leave the terminal in raw mode if the input capture crashes.
+    restore_terminal_on_panic();
+
+    // Keep the state of the shell across REPL iterations.
+    let mut state = ShellState::new();
+
+    // Recall the commands from previous sessions, starting afresh if the history can't be read.
+    if let Some(file) = default_history_file() {
+        if let Err(error) = state.history_mut().load(file) {
+            eprintln!("{error}");
+        }
+    }
+
+    // Survive Ctrl+C while commands run: the terminal sends SIGINT to the foreground commands as
+    // well, and they get the default behaviour back when they start, so they are interrupted.
+    if let Err(error) = signal_hook::flag::register(SIGINT, Arc::new(AtomicBool::new(false))) {
+        eprintln!("Failed to handle interruptions: {error}");
+    }
+
+    // Likewise, don't get suspended by Ctrl+Z while builtins read the terminal, e.g. `read`.
+    if let Err(error) = signal_hook::flag::register(SIGTSTP, Arc::new(AtomicBool::new(false))) {
+        eprintln!("Failed to handle suspensions: {error}");
+    }
+
+    // Set up the aliases and exports of the user, unless disabled, e.g. for testing.
+    if std::env::var_os("SHELL_NO_RC").is_none() {
+        load_rc_file(&mut state);
+    }
+
     loop {
-        if let Err(error) = repl() {
+        if let Err(error) = repl(&mut state) {
             match error {
                 ShellError::Runner(RunnerError::BuiltInCommand(BuiltInCommandError::Exit(
                     code,
@@ -40,24 +88,213 @@ fn main() {
     }
 }
 
-fn repl() -> Result<(), ShellError> {
-    // Initialise autocompletion.
-    let autocomplete = CompositeAutocomplete::new();
+/// Runs the commands of `~/.shellrc` if it exists, warning about failures without aborting.
+fn load_rc_file(state: &mut ShellState) {
+    let Some(file) = std::env::var("HOME")
+        .ok()
+        .map(|home| PathBuf::from(home).join(".shellrc"))
+        .filter(|file| file.exists())
+    else {
+        return;
+    };
+
+    match std::fs::read_to_string(&file) {
+        Ok(script) => {
+            if let Err(error) = run_script(&script, state) {
+                match error {
+                    RunnerError::BuiltInCommand(BuiltInCommandError::Exit(code)) => exit(code),
+                    error => eprintln!("{error}"),
+                }
+            }
+        }
+        Err(error) => eprintln!("Failed to read {}: {error}", file.display()),
+    }
+}
+
+/// Runs the command string or the script file given in the arguments of the shell, returning the
+/// status to exit with, or `None` if there is nothing to run non-interactively.
+fn run_non_interactively(args: &[String]) -> Option<i32> {
+    match args.get(1).map(String::as_str) {
+        Some("-c") => {
+            let Some(input) = args.get(2) else {
+                eprintln!("-c: option requires an argument");
+                return Some(2);
+            };
+
+            Some(run_command_string(input, &args[3..]))
+        }
+        Some(script) => Some(run_script_file(script, &args[2..])),
+        None => None,
+    }
+}
 
-    // Capture the user input.
-    let input = match capture_input(&autocomplete) {
-        // Start a new repl iteration on abortion.
-        Err(InputError::Aborted) => return Ok(()),
-        res => res?,
+/// Runs the commands of the string once, without job control nor user configuration, returning the
+/// status to exit with.
+fn run_command_string(input: &str, parameters: &[String]) -> i32 {
+    disable_job_control();
+    let mut state = ShellState::new();
+
+    // The next arguments are `$0`, `$1`, etc., e.g. for `shell -c 'echo $1' name a`.
+    if let Some((name, arguments)) = parameters.split_first() {
+        state.set_script(name.clone(), arguments.to_vec());
+    }
+
+    let result = parse_input(input, &state)
+        .map_err(RunnerError::from)
+        .and_then(|lists| run_commands(lists, &mut state));
+
+    exit_status(result, &state)
+}
+
+/// Runs the commands of the script file like [`run_command_string`], with the given arguments as
+/// `$1`, `$2`, etc.
+fn run_script_file(file: &str, arguments: &[String]) -> i32 {
+    let script = match std::fs::read_to_string(file) {
+        Ok(script) => script,
+        Err(error) => {
+            eprintln!("{file}: {}", describe_io_error(&error));
+            return 127;
+        }
     };
 
-    // Parse the commands.
-    let commands = parse_input(&input)?;
-    if commands.is_empty() {
+    disable_job_control();
+    let mut state = ShellState::new();
+    state.set_script(file.to_owned(), arguments.to_vec());
+
+    let result = run_script(&script, &mut state);
+
+    exit_status(result, &state)
+}
+
+/// The status to exit with once the commands ran, reporting their failure if any.
+fn exit_status(result: Result<(), RunnerError>, state: &ShellState) -> i32 {
+    match result {
+        Ok(()) => state.last_status(),
+        Err(RunnerError::BuiltInCommand(BuiltInCommandError::Exit(code))) => code,
+        Err(error) => {
+            eprintln!("{error}");
+
+            // Commands which can't be parsed don't run at all, like syntax errors in bash, and
+            // aborting errors fail the shell, e.g. `${VAR:?}`.
+            if matches!(error, RunnerError::Parsing(_)) {
+                2
+            } else if error.is_aborting() {
+                state.last_status().max(1)
+            } else {
+                state.last_status()
+            }
+        }
+    }
+}
+
+fn repl(state: &mut ShellState) -> Result<(), ShellError> {
+    // Report the background jobs which finished since the last prompt.
+    state.jobs_mut().poll();
+    for job in state.jobs_mut().remove_finished() {
+        eprintln!("{job}");
+    }
+
+    // Capture the user input, with autocompletion.
+    let input = {
+        let autocomplete = CompositeAutocomplete::new(state.path_cache());
+
+        match capture_input(&autocomplete, state.history()) {
+            // Start a new repl iteration on abortion.
+            Err(InputError::Aborted) => return Ok(()),
+            res => res?,
+        }
+    };
+
+    // Prompt again after blank lines, without recording them.
+    if input.trim().is_empty() {
         return Ok(());
     }
 
-    run_commands(commands)?;
+    // Replace the references to previous commands, printing the result like bash.
+    let expanded = state.history().expand(&input)?;
+    if expanded != input {
+        println!("{expanded}");
+    }
+    let input = expanded;
+
+    // Record the input for later recall.
+    state.history_mut().push(&input)?;
+
+    // Parse the commands, syntax errors having status 2 like bash.
+    let lists = parse_input(&input, state).inspect_err(|_| state.set_last_status(2))?;
+
+    run_commands(lists, state)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::run_non_interactively;
+    use crate::io::FileDescriptor;
+    use crate::parser::Descriptor;
+    use crate::path::run_subshell;
+    use std::collections::HashMap;
+    use std::fs;
+
+    /// Runs the shell with the arguments in a copy of the process, as running commands disables
+    /// job control, and returns its status along with what it wrote to its standard output.
+    fn run_shell(args: &[&str]) -> (i32, String) {
+        let output = std::env::temp_dir().join(format!(
+            "codecrafters_shell_main_{}.txt",
+            args.join("_").replace(|c: char| !c.is_alphanumeric(), "")
+        ));
+        let descriptors = HashMap::from([(
+            Descriptor::stdout(),
+            FileDescriptor::file(&output.display().to_string(), false).unwrap(),
+        )]);
+        let args: Vec<_> = ["shell"]
+            .iter()
+            .chain(args)
+            .map(|arg| arg.to_string())
+            .collect();
+
+        let status = run_subshell(descriptors, || run_non_interactively(&args).unwrap());
+
+        let written = fs::read_to_string(&output).unwrap();
+        fs::remove_file(output).unwrap();
+
+        (status, written)
+    }
+
+    #[test]
+    fn it_runs_command_strings() {
+        assert_eq!(
+            (3, "a\n".to_owned()),
+            run_shell(&["-c", "echo a; exit 3; echo b"])
+        );
+
+        // The next arguments are the positional parameters.
+        assert_eq!(
+            (0, "name a b\n".to_owned()),
+            run_shell(&["-c", "echo $0 $@", "name", "a", "b"])
+        );
+
+        // Unset parameters with `${VAR:?}` stop the shell.
+        assert_eq!(
+            (1, String::new()),
+            run_shell(&["-c", ": ${X:?no}; echo after"])
+        );
+        assert_eq!(
+            (1, "before\n".to_owned()),
+            run_shell(&["-c", "echo before\n: ${X:?no}\necho after"])
+        );
+
+        // Syntax errors don't run anything.
+        assert_eq!((2, String::new()), run_shell(&["-c", "echo a; echo b |"]));
+    }
+
+    #[test]
+    fn it_requires_a_command_string_after_c() {
+        assert_eq!(
+            Some(2),
+            run_non_interactively(&["shell".to_owned(), "-c".to_owned()])
+        );
+        assert_eq!(None, run_non_interactively(&["shell".to_owned()]));
+    }
+}
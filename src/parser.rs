@@ -1,14 +1,31 @@
 use crate::parser::quoting::QuotingError;
+use crate::state::ShellState;
+use std::fmt::{Display, Formatter};
+use strum_macros::Display;
 use thiserror::Error;
 
+mod aliasing;
+mod arithmetic;
+mod bracing;
+mod expansion;
+mod globbing;
+mod here_documents;
 mod quoting;
 mod splitting;
 
+pub(crate) use expansion::{
+    expand_chunk, expand_words, field_separators, split_fields, ExpansionError,
+};
+pub(crate) use quoting::{is_variable_name, InputChunk};
+
 #[derive(Error, Debug)]
 pub(crate) enum ParsingError {
     #[error(transparent)]
     Quoting(#[from] QuotingError),
 
+    #[error(transparent)]
+    HereDocument(#[from] here_documents::HereDocumentError),
+
     #[error(transparent)]
     CommandSplittingError(#[from] splitting::SplittingError),
 }
@@ -19,6 +36,10 @@ pub(crate) enum ParsingError {
 pub(crate) struct Descriptor(pub(crate) u8);
 
 impl Descriptor {
+    pub(crate) fn stdin() -> Self {
+        Self(0)
+    }
+
     pub(crate) fn stdout() -> Self {
         Self(1)
     }
@@ -28,20 +49,91 @@ impl Descriptor {
     }
 }
 
+/// Pipelines chained with `&&` and `||`, where each pipeline runs depending on the exit status of
+/// the previous one.
+pub(crate) struct AndOrList {
+    first: Vec<Command>,
+    rest: Vec<(ListOperator, Vec<Command>)>,
+
+    /// Whether the list runs in the background, i.e. it was terminated with `&`.
+    background: bool,
+}
+
+impl AndOrList {
+    pub(crate) fn first(&self) -> &[Command] {
+        &self.first
+    }
+
+    pub(crate) fn rest(&self) -> &[(ListOperator, Vec<Command>)] {
+        &self.rest
+    }
+
+    pub(crate) fn is_background(&self) -> bool {
+        self.background
+    }
+}
+
+/// The operator chaining two pipelines of an [`AndOrList`].
+#[derive(Clone, Copy, PartialEq, Display)]
+#[cfg_attr(test, derive(Debug))]
+pub(crate) enum ListOperator {
+    /// Run the next pipeline if the previous one succeeded, i.e. `&&`.
+    #[strum(serialize = "&&")]
+    And,
+    /// Run the next pipeline if the previous one failed, i.e. `||`.
+    #[strum(serialize = "||")]
+    Or,
+}
+
 /// A command with its arguments and redirections in the order they were specified.
+///
+/// The assignment values, program, arguments, and redirection destinations are expanded when
+/// running the command.
 pub(crate) struct Command {
-    program: String,
-    arguments: Vec<String>,
+    /// The variables assigned before the program, e.g. `FOO=bar env`, only for that program, or
+    /// for the shell if there is no program.
+    assignments: Vec<Assignment>,
+    program: Option<InputChunk>,
+
+    /// The lists grouped in place of a program, e.g. `(cd /tmp && pwd)`.
+    compound: Option<CompoundCommand>,
+    arguments: Vec<InputChunk>,
     redirects: Vec<Redirect>,
 }
 
+/// Lists grouped into a single command, which can be piped or redirected as a whole.
+pub(crate) enum CompoundCommand {
+    /// Lists run in a subshell, whose changes to the state don't affect the shell, i.e. `( ... )`.
+    Subshell(Vec<AndOrList>),
+
+    /// Lists run in the current shell, e.g. to redirect their output at once, i.e. `{ ...; }`.
+    BraceGroup(Vec<AndOrList>),
+}
+
+/// A variable assignment, e.g. `FOO=bar`.
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub(crate) struct Assignment {
+    name: String,
+    value: InputChunk,
+}
+
+impl Assignment {
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn value(&self) -> &InputChunk {
+        &self.value
+    }
+}
+
 /// An IO redirection.
 pub(crate) struct Redirect {
     /// The IO descriptor.
-    /// 0: input (unsupported), 1: output, 2: error
+    /// 0: input, 1: output, 2: error
     from: Descriptor,
     to: RedirectTo,
-    append: bool,
+    mode: RedirectMode,
 }
 
 impl Redirect {
@@ -53,33 +145,67 @@ impl Redirect {
         self.to.clone()
     }
 
-    pub(crate) fn append(&self) -> bool {
-        self.append
+    pub(crate) fn mode(&self) -> RedirectMode {
+        self.mode
     }
 }
 
+/// How the destination file of an IO redirection is opened.
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub(crate) enum RedirectMode {
+    /// Read from the file, e.g. `<`.
+    Read,
+    /// Write to the file, truncating it first, e.g. `>`.
+    Truncate,
+    /// Write to the end of the file, e.g. `>>`.
+    Append,
+    /// Write to the file, truncating it first even if `noclobber` is set, e.g. `>|`.
+    Clobber,
+}
+
 /// The destination of an IO redirection.
 #[derive(Clone)]
 #[cfg_attr(test, derive(PartialEq, Debug))]
 pub(crate) enum RedirectTo {
     Descriptor(Descriptor),
-    File(String),
+    File(InputChunk),
+    /// Text fed to the input, e.g. the body of a here-document.
+    Text(InputChunk),
+    /// The descriptor is closed, e.g. `2>&-`.
+    Closed,
 }
 
 impl Command {
-    fn new(program: String, arguments: Vec<String>, redirects: Vec<Redirect>) -> Self {
+    fn new(
+        assignments: Vec<Assignment>,
+        program: Option<InputChunk>,
+        compound: Option<CompoundCommand>,
+        arguments: Vec<InputChunk>,
+        redirects: Vec<Redirect>,
+    ) -> Self {
         Self {
+            assignments,
             program,
+            compound,
             arguments,
             redirects,
         }
     }
 
-    pub(crate) fn program(&self) -> &str {
-        &self.program
+    pub(crate) fn assignments(&self) -> &[Assignment] {
+        &self.assignments
+    }
+
+    pub(crate) fn program(&self) -> Option<&InputChunk> {
+        self.program.as_ref()
+    }
+
+    pub(crate) fn compound(&self) -> Option<&CompoundCommand> {
+        self.compound.as_ref()
     }
 
-    pub(crate) fn arguments(&self) -> &[String] {
+    pub(crate) fn arguments(&self) -> &[InputChunk] {
         &self.arguments
     }
 
@@ -88,10 +214,183 @@ impl Command {
     }
 }
 
-pub(crate) fn parse_input(input: &str) -> Result<Vec<Command>, ParsingError> {
-    let values = quoting::chunk_quoted_string(input)?;
+impl Display for Command {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let assignments = self
+            .assignments
+            .iter()
+            .map(|assignment| format!("{}={}", assignment.name, assignment.value));
+        let words = assignments
+            .chain(self.program.iter().map(ToString::to_string))
+            .chain(self.compound.iter().map(ToString::to_string));
+        write!(f, "{}", words.collect::<Vec<_>>().join(" "))?;
 
-    let commands = splitting::split_commands(values)?;
+        for argument in &self.arguments {
+            write!(f, " {argument}")?;
+        }
 
-    Ok(commands)
+        for redirect in &self.redirects {
+            write!(f, " {redirect}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for CompoundCommand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompoundCommand::Subshell(lists) => write!(f, "({})", display_lists(lists)),
+            CompoundCommand::BraceGroup(lists) => {
+                // The last list must be terminated, unless it runs in the background.
+                let terminator = match lists.last() {
+                    Some(list) if list.background => "",
+                    _ => ";",
+                };
+                write!(f, "{{ {}{terminator} }}", display_lists(lists))
+            }
+        }
+    }
+}
+
+impl Display for AndOrList {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", display_pipeline(&self.first))?;
+        for (operator, pipeline) in &self.rest {
+            write!(f, " {operator} {}", display_pipeline(pipeline))?;
+        }
+
+        if self.background {
+            write!(f, " &")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Displays the pipeline as it could have been typed, e.g. `echo a | cat`.
+pub(crate) fn display_pipeline(commands: &[Command]) -> String {
+    commands
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+/// Displays the lists as they could have been typed, separating them with `;` unless they run in
+/// the background, e.g. `sleep 1 & echo a; echo b`.
+fn display_lists(lists: &[AndOrList]) -> String {
+    let mut text = String::new();
+    for (index, list) in lists.iter().enumerate() {
+        text.push_str(&list.to_string());
+        if index + 1 < lists.len() {
+            text.push_str(if list.background { " " } else { "; " });
+        }
+    }
+
+    text
+}
+
+impl Display for Redirect {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let operator = match self.mode {
+            RedirectMode::Read => "<",
+            RedirectMode::Truncate => ">",
+            RedirectMode::Append => ">>",
+            RedirectMode::Clobber => ">|",
+        };
+
+        // Omit the default descriptors, e.g. `> out.txt` rather than `1> out.txt`.
+        let default = if self.mode == RedirectMode::Read {
+            0
+        } else {
+            1
+        };
+        if self.from.0 != default {
+            write!(f, "{}", self.from.0)?;
+        }
+
+        match &self.to {
+            RedirectTo::Descriptor(to) => write!(f, "{operator}&{}", to.0),
+            RedirectTo::File(filename) => write!(f, "{operator} {filename}"),
+            RedirectTo::Text(text) => write!(f, "<<< {text}"),
+            RedirectTo::Closed => write!(f, "{operator}&-"),
+        }
+    }
+}
+
+/// Parses the input into the lists of pipelines to run one after the other, expanding the aliases
+/// defined in the state.
+pub(crate) fn parse_input(input: &str, state: &ShellState) -> Result<Vec<AndOrList>, ParsingError> {
+    let (input, bodies) = here_documents::extract_here_documents(input)?;
+    let values = quoting::chunk_quoted_string(&input)?;
+    let values = aliasing::expand_aliases(values, state.aliases())?;
+    let values = bracing::expand_braces(values);
+
+    let lists = splitting::split_lists(values, bodies)?;
+
+    Ok(lists)
+}
+
+/// Whether the input is missing its end, e.g. within quotes, after a pipe, or before the delimiter
+/// of a here-document, so more lines should be captured before parsing it.
+pub(crate) fn is_incomplete_input(input: &str) -> bool {
+    let input = match here_documents::extract_here_documents(input) {
+        Ok((commands, _)) => commands,
+        Err(here_documents::HereDocumentError::Unterminated(_)) => return true,
+        Err(here_documents::HereDocumentError::Quoting(_)) => return false,
+    };
+
+    match quoting::chunk_quoted_string(&input) {
+        Err(
+            QuotingError::DanglingSingleQuote(_)
+            | QuotingError::DanglingDoubleQuote(_)
+            | QuotingError::UnterminatedSubstitution,
+        ) => true,
+        Ok(chunks) => {
+            // Trailing newlines don't complete a pipe, e.g. `echo a |` followed by an empty line.
+            let ends_with_operator = chunks
+                .iter()
+                .rev()
+                .find(|chunk| chunk.as_unquoted() != Some("\n"))
+                .and_then(InputChunk::as_unquoted)
+                .is_some_and(|text| ["|", "&&", "||"].contains(&text));
+
+            ends_with_operator || splitting::count_open_groups(&chunks) > 0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_incomplete_input;
+
+    #[test]
+    fn it_detects_incomplete_inputs() {
+        assert!(is_incomplete_input("echo 'hello"));
+        assert!(is_incomplete_input("echo \"hello\nworld"));
+        assert!(is_incomplete_input("echo $(pwd"));
+        assert!(is_incomplete_input("echo hello |"));
+        assert!(is_incomplete_input("true &&\n"));
+        assert!(is_incomplete_input("false ||"));
+        assert!(is_incomplete_input("cat <<EOF\nhello"));
+        assert!(is_incomplete_input("(echo a"));
+        assert!(is_incomplete_input("(cd /; (ls)\n"));
+        assert!(is_incomplete_input("{ echo a;"));
+        assert!(is_incomplete_input("{ echo a\necho }"));
+        assert!(is_incomplete_input("{ echo a }"));
+        assert!(is_incomplete_input("({ echo a; }"));
+        assert!(is_incomplete_input("echo a |\n\n"));
+
+        assert!(!is_incomplete_input(""));
+        assert!(!is_incomplete_input("echo 'hello\nworld'"));
+        assert!(!is_incomplete_input("echo hello | cat"));
+        assert!(!is_incomplete_input("echo '|'"));
+        assert!(!is_incomplete_input("sleep 1 &"));
+        assert!(!is_incomplete_input("cat <<EOF\nhello\nEOF"));
+        assert!(!is_incomplete_input("(echo a)"));
+        assert!(!is_incomplete_input("{ echo a; }"));
+        assert!(!is_incomplete_input("{ (echo a) }\n"));
+        assert!(!is_incomplete_input("echo { '(' \\( \"$(pwd)\""));
+    }
 }
@@ -0,0 +1,122 @@
+use crate::parser::quoting::{chunk_quoted_string, InputChunk, QuotingError};
+use std::collections::BTreeMap;
+
+/// The unquoted chunks after which a new command starts.
+const COMMAND_SEPARATORS: [&str; 7] = ["|", "&&", "||", ";", "&", "\n", "("];
+
+/// Replaces the first word of each command with the value of its alias, if any.
+///
+/// The values are chunked with the same quoting rules as the input, and their first words are
+/// expanded in turn, apart from the aliases being expanded to avoid infinite recursion.
+pub(crate) fn expand_aliases(
+    chunks: Vec<InputChunk>,
+    aliases: &BTreeMap<String, String>,
+) -> Result<Vec<InputChunk>, QuotingError> {
+    expand_aliases_except(chunks, aliases, &mut vec![])
+}
+
+fn expand_aliases_except(
+    chunks: Vec<InputChunk>,
+    aliases: &BTreeMap<String, String>,
+    expanding: &mut Vec<String>,
+) -> Result<Vec<InputChunk>, QuotingError> {
+    let mut expanded: Vec<InputChunk> = vec![];
+
+    for chunk in chunks {
+        // Only unquoted words starting a command are aliases.
+        let is_command_start = expanded.last().is_none_or(is_command_separator);
+        let alias = chunk
+            .as_unquoted()
+            .filter(|name| is_command_start && !expanding.iter().any(|n| n == name))
+            .and_then(|name| aliases.get_key_value(name));
+
+        if let Some((name, value)) = alias {
+            expanding.push(name.clone());
+            let value = expand_aliases_except(chunk_quoted_string(value)?, aliases, expanding);
+            expanding.pop();
+
+            expanded.extend(value?);
+        } else {
+            expanded.push(chunk);
+        }
+    }
+
+    Ok(expanded)
+}
+
+fn is_command_separator(chunk: &InputChunk) -> bool {
+    chunk
+        .as_unquoted()
+        .is_some_and(|text| COMMAND_SEPARATORS.contains(&text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::expand_aliases;
+    use crate::parser::quoting::chunk_quoted_string;
+    use std::collections::BTreeMap;
+
+    fn expand(input: &str, aliases: &[(&str, &str)]) -> Vec<Option<String>> {
+        let aliases: BTreeMap<_, _> = aliases
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+
+        expand_aliases(chunk_quoted_string(input).unwrap(), &aliases)
+            .unwrap()
+            .iter()
+            .map(|chunk| chunk.as_unquoted().map(ToOwned::to_owned))
+            .collect()
+    }
+
+    fn words(words: &[&str]) -> Vec<Option<String>> {
+        words.iter().map(|word| Some(word.to_string())).collect()
+    }
+
+    #[test]
+    fn it_expands_the_first_word_of_commands() {
+        let aliases = [("ll", "ls -la"), ("hi", "echo hi |")];
+
+        assert_eq!(words(&["ls", "-la", "ll"]), expand("ll ll", &aliases));
+        assert_eq!(
+            words(&["true", "&&", "ls", "-la", ";", "ls", "-la", "|", "ls", "-la"]),
+            expand("true && ll ; ll | ll", &aliases)
+        );
+        assert_eq!(
+            words(&["(", "ls", "-la", ")", "\n", "ls", "-la"]),
+            expand("(ll)\nll", &aliases)
+        );
+
+        // Expand the words following a value ending with a separator.
+        assert_eq!(
+            words(&["echo", "hi", "|", "ls", "-la"]),
+            expand("hi ll", &aliases)
+        );
+
+        // Quoted words are not aliases.
+        assert_eq!(vec![None], expand("'ll'", &aliases));
+    }
+
+    #[test]
+    fn it_expands_nested_aliases_without_recursing() {
+        let aliases = [
+            ("ls", "ls --color"),
+            ("ll", "ls -l"),
+            ("a", "b"),
+            ("b", "a"),
+        ];
+
+        assert_eq!(words(&["ls", "--color", "-l"]), expand("ll", &aliases));
+        assert_eq!(words(&["a"]), expand("a", &aliases));
+    }
+
+    #[test]
+    fn it_chunks_alias_values_with_quoting_rules() {
+        let aliases = [("greet", r#"echo "hello world""#)];
+
+        assert_eq!(
+            vec![Some("echo".to_owned()), None, Some("me".to_owned())],
+            expand("greet me", &aliases)
+        );
+    }
+}
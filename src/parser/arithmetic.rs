@@ -0,0 +1,233 @@
+use crate::parser::quoting::is_variable_name;
+use std::iter::Peekable;
+use std::str::Chars;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub(crate) enum ArithmeticError {
+    #[error("division by 0")]
+    DivisionByZero,
+
+    #[error("syntax error: operand expected")]
+    OperandExpected,
+
+    #[error("syntax error in expression (error token is \"{0}\")")]
+    UnexpectedToken(String),
+
+    #[error("{0}: invalid number in variable")]
+    InvalidVariable(String),
+}
+
+/// A token of an arithmetic expression.
+#[derive(Clone)]
+enum Token {
+    Number(i64),
+    Variable(String),
+    Operator(char),
+}
+
+impl Token {
+    fn text(&self) -> String {
+        match self {
+            Token::Number(number) => number.to_string(),
+            Token::Variable(name) => name.clone(),
+            Token::Operator(operator) => operator.to_string(),
+        }
+    }
+}
+
+const OPERATORS: [char; 7] = ['+', '-', '*', '/', '%', '(', ')'];
+
+/// Evaluates the integer expression of an arithmetic expansion, e.g. `1 + x * 3`.
+///
+/// Supports `+`, `-`, `*`, `/`, `%`, parentheses, and variables with or without `$`, where unset
+/// variables count as 0. Overflows wrap around, like bash.
+pub(crate) fn evaluate(expression: &str) -> Result<i64, ArithmeticError> {
+    let tokens = tokenize(expression)?;
+    let mut tokens = tokens.into_iter().peekable();
+
+    let value = evaluate_sum(&mut tokens)?;
+
+    match tokens.next() {
+        Some(token) => Err(ArithmeticError::UnexpectedToken(token.text())),
+        None => Ok(value),
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, ArithmeticError> {
+    let mut tokens = vec![];
+
+    let mut chars = expression.chars().peekable();
+    while let Some(char) = chars.next() {
+        if char.is_whitespace() {
+            continue;
+        }
+
+        let token = if OPERATORS.contains(&char) {
+            Token::Operator(char)
+        } else if char.is_ascii_digit() {
+            let digits = capture_while(char, &mut chars, |next| next.is_ascii_alphanumeric());
+            let number = digits
+                .parse()
+                .map_err(|_| ArithmeticError::UnexpectedToken(digits))?;
+            Token::Number(number)
+        } else if char == '$' || is_variable_name(&char.to_string()) {
+            // Variables may be referenced with a leading `$`, e.g. `$x + 1`.
+            let name = capture_while(char, &mut chars, |next| {
+                next.is_ascii_alphanumeric() || next == '_'
+            });
+            let name = name.strip_prefix('$').unwrap_or(&name);
+            if !is_variable_name(name) {
+                return Err(ArithmeticError::UnexpectedToken(char.to_string()));
+            }
+            Token::Variable(name.to_owned())
+        } else {
+            return Err(ArithmeticError::UnexpectedToken(
+                std::iter::once(char).chain(chars).collect(),
+            ));
+        };
+
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+/// Captures the current character, and the next ones while they satisfy the predicate.
+fn capture_while(
+    current: char,
+    chars: &mut Peekable<Chars>,
+    predicate: fn(char) -> bool,
+) -> String {
+    let mut text = current.to_string();
+    while let Some(char) = chars.next_if(|next| predicate(*next)) {
+        text.push(char);
+    }
+
+    text
+}
+
+type Tokens = Peekable<std::vec::IntoIter<Token>>;
+
+/// Evaluates additions and subtractions, which have the lowest precedence.
+fn evaluate_sum(tokens: &mut Tokens) -> Result<i64, ArithmeticError> {
+    let mut value = evaluate_product(tokens)?;
+
+    while let Some(Token::Operator(operator @ ('+' | '-'))) = tokens.peek().cloned() {
+        tokens.next();
+        let operand = evaluate_product(tokens)?;
+
+        value = if operator == '+' {
+            value.wrapping_add(operand)
+        } else {
+            value.wrapping_sub(operand)
+        };
+    }
+
+    Ok(value)
+}
+
+/// Evaluates multiplications, divisions and remainders.
+fn evaluate_product(tokens: &mut Tokens) -> Result<i64, ArithmeticError> {
+    let mut value = evaluate_unary(tokens)?;
+
+    while let Some(Token::Operator(operator @ ('*' | '/' | '%'))) = tokens.peek().cloned() {
+        tokens.next();
+        let operand = evaluate_unary(tokens)?;
+
+        value = match operator {
+            '*' => value.wrapping_mul(operand),
+            _ if operand == 0 => return Err(ArithmeticError::DivisionByZero),
+            '/' => value.wrapping_div(operand),
+            _ => value.wrapping_rem(operand),
+        };
+    }
+
+    Ok(value)
+}
+
+/// Evaluates signed operands, e.g. `-x`.
+fn evaluate_unary(tokens: &mut Tokens) -> Result<i64, ArithmeticError> {
+    match tokens.next() {
+        Some(Token::Operator('+')) => evaluate_unary(tokens),
+        Some(Token::Operator('-')) => Ok(evaluate_unary(tokens)?.wrapping_neg()),
+        Some(Token::Operator('(')) => {
+            let value = evaluate_sum(tokens)?;
+
+            match tokens.next() {
+                Some(Token::Operator(')')) => Ok(value),
+                Some(token) => Err(ArithmeticError::UnexpectedToken(token.text())),
+                None => Err(ArithmeticError::UnexpectedToken("(".to_owned())),
+            }
+        }
+        Some(Token::Number(number)) => Ok(number),
+        Some(Token::Variable(name)) => read_variable(&name),
+        Some(token) => Err(ArithmeticError::UnexpectedToken(token.text())),
+        None => Err(ArithmeticError::OperandExpected),
+    }
+}
+
+/// Reads the integer value of the variable, unset and empty variables counting as 0.
+fn read_variable(name: &str) -> Result<i64, ArithmeticError> {
+    let value = std::env::var(name).unwrap_or_default();
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(0);
+    }
+
+    value
+        .parse()
+        .map_err(|_| ArithmeticError::InvalidVariable(value.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{evaluate, ArithmeticError};
+
+    #[test]
+    fn it_evaluates_expressions() {
+        assert_eq!(7, evaluate(" 1 + 2 * 3 ").unwrap());
+        assert_eq!(9, evaluate("(1 + 2) * 3").unwrap());
+        assert_eq!(1, evaluate("10 - 4 - 5").unwrap());
+        assert_eq!(-3, evaluate("-7 / 2").unwrap());
+        assert_eq!(1, evaluate("7 % -2 * +1").unwrap());
+        assert_eq!(6, evaluate("--6").unwrap());
+    }
+
+    #[test]
+    fn it_reads_variables() {
+        std::env::set_var("CODECRAFTERS_ARITHMETIC", " 4 ");
+
+        assert_eq!(5, evaluate("CODECRAFTERS_ARITHMETIC+1").unwrap());
+        assert_eq!(8, evaluate("$CODECRAFTERS_ARITHMETIC * 2").unwrap());
+        assert_eq!(0, evaluate("CODECRAFTERS_ARITHMETIC_UNSET").unwrap());
+    }
+
+    #[test]
+    fn it_rejects_invalid_expressions() {
+        assert!(matches!(
+            evaluate("1 / (2 - 2)"),
+            Err(ArithmeticError::DivisionByZero)
+        ));
+        assert!(matches!(
+            evaluate("1 +"),
+            Err(ArithmeticError::OperandExpected)
+        ));
+        assert!(matches!(
+            evaluate(""),
+            Err(ArithmeticError::OperandExpected)
+        ));
+        assert!(matches!(
+            evaluate("(1 + 2"),
+            Err(ArithmeticError::UnexpectedToken(_))
+        ));
+        assert!(matches!(
+            evaluate("1 2"),
+            Err(ArithmeticError::UnexpectedToken(token)) if token == "2"
+        ));
+        assert!(matches!(
+            evaluate("1 & 2"),
+            Err(ArithmeticError::UnexpectedToken(token)) if token == "& 2"
+        ));
+    }
+}
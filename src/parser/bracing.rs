@@ -0,0 +1,250 @@
+use crate::parser::quoting::{ChunkPart, InputChunk};
+
+/// An element of a chunk for brace expansion, where only unquoted characters can be braces.
+#[derive(Clone)]
+enum Atom {
+    Char(char),
+    Part(ChunkPart),
+}
+
+const OPENING_BRACE: char = '{';
+const CLOSING_BRACE: char = '}';
+const SEPARATOR: char = ',';
+const SEQUENCE_SEPARATOR: &str = "..";
+
+/// Expands the unquoted braces of each chunk into several chunks, e.g. `pre{a,b}post` into
+/// `preapost prebpost`, or `{1..3}` into `1 2 3`.
+///
+/// Braces without a comma or a valid sequence are kept literally, e.g. `{a}`.
+pub(crate) fn expand_braces(chunks: Vec<InputChunk>) -> Vec<InputChunk> {
+    chunks
+        .into_iter()
+        .flat_map(|chunk| {
+            // Skip chunks without braces, which are most of them.
+            let has_braces = chunk.parts().iter().any(
+                |part| matches!(part, ChunkPart::Unquoted(text) if text.contains(OPENING_BRACE)),
+            );
+            if !has_braces {
+                return vec![chunk];
+            }
+
+            expand_atoms(&to_atoms(&chunk))
+                .iter()
+                .map(|atoms| to_chunk(atoms))
+                .collect()
+        })
+        .collect()
+}
+
+fn to_atoms(chunk: &InputChunk) -> Vec<Atom> {
+    chunk
+        .parts()
+        .iter()
+        .flat_map(|part| match part {
+            ChunkPart::Unquoted(text) => text.chars().map(Atom::Char).collect(),
+            part => vec![Atom::Part(part.clone())],
+        })
+        .collect()
+}
+
+fn to_chunk(atoms: &[Atom]) -> InputChunk {
+    let mut chunk = InputChunk::default();
+    for atom in atoms {
+        match atom {
+            Atom::Char(char) => chunk.push_part(ChunkPart::Unquoted(char.to_string())),
+            Atom::Part(part) => chunk.push_part(part.clone()),
+        }
+    }
+
+    chunk
+}
+
+/// Expands the first valid brace expression, then the alternatives and the rest in turn.
+fn expand_atoms(atoms: &[Atom]) -> Vec<Vec<Atom>> {
+    let openings = atoms
+        .iter()
+        .enumerate()
+        .filter(|(_, atom)| matches!(atom, Atom::Char(OPENING_BRACE)));
+
+    for (start, _) in openings {
+        let Some(end) = find_closing_brace(atoms, start) else {
+            continue;
+        };
+
+        let Some(alternatives) = split_alternatives(&atoms[start + 1..end]) else {
+            continue;
+        };
+
+        let prefix = &atoms[..start];
+        let suffixes = expand_atoms(&atoms[end + 1..]);
+
+        let mut expansions = vec![];
+        for alternative in alternatives {
+            for expanded in expand_atoms(&alternative) {
+                for suffix in &suffixes {
+                    expansions.push([prefix, &expanded, suffix].concat());
+                }
+            }
+        }
+
+        return expansions;
+    }
+
+    vec![atoms.to_vec()]
+}
+
+/// Finds the brace closing the one at the start, skipping nested ones.
+fn find_closing_brace(atoms: &[Atom], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (index, atom) in atoms.iter().enumerate().skip(start) {
+        match atom {
+            Atom::Char(OPENING_BRACE) => depth += 1,
+            Atom::Char(CLOSING_BRACE) => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Splits the content of braces into its alternatives, at the commas outside nested braces, or
+/// into the items of a sequence.
+fn split_alternatives(content: &[Atom]) -> Option<Vec<Vec<Atom>>> {
+    let mut alternatives = vec![vec![]];
+    let mut depth = 0;
+    for atom in content {
+        match atom {
+            Atom::Char(OPENING_BRACE) => depth += 1,
+            Atom::Char(CLOSING_BRACE) => depth -= 1,
+            Atom::Char(SEPARATOR) if depth == 0 => {
+                alternatives.push(vec![]);
+                continue;
+            }
+            _ => {}
+        }
+
+        if let Some(alternative) = alternatives.last_mut() {
+            alternative.push(atom.clone());
+        }
+    }
+
+    if alternatives.len() > 1 {
+        return Some(alternatives);
+    }
+
+    // Sequences are made of unquoted characters only.
+    let text = content
+        .iter()
+        .map(|atom| match atom {
+            Atom::Char(char) => Some(*char),
+            Atom::Part(_) => None,
+        })
+        .collect::<Option<String>>()?;
+
+    let items = expand_sequence(&text)?;
+    Some(
+        items
+            .into_iter()
+            .map(|item| item.chars().map(Atom::Char).collect())
+            .collect(),
+    )
+}
+
+/// Expands a sequence of integers or letters, e.g. `1..5`, `10..0..5`, or `a..e`.
+fn expand_sequence(text: &str) -> Option<Vec<String>> {
+    let bounds: Vec<&str> = text.split(SEQUENCE_SEPARATOR).collect();
+    let (start, end, step) = match bounds.as_slice() {
+        [start, end] => (*start, *end, 1),
+        [start, end, step] => (
+            *start,
+            *end,
+            step.parse::<i64>().ok()?.unsigned_abs().max(1),
+        ),
+        _ => return None,
+    };
+
+    if let (Ok(start), Ok(end)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        return Some(
+            range(start, end, step)
+                .into_iter()
+                .map(|value| value.to_string())
+                .collect(),
+        );
+    }
+
+    let (mut start_chars, mut end_chars) = (start.chars(), end.chars());
+    match (
+        start_chars.next(),
+        start_chars.next(),
+        end_chars.next(),
+        end_chars.next(),
+    ) {
+        (Some(start), None, Some(end), None)
+            if start.is_ascii_alphabetic() && end.is_ascii_alphabetic() =>
+        {
+            Some(
+                range(i64::from(start as u8), i64::from(end as u8), step)
+                    .into_iter()
+                    .map(|value| char::from(value as u8).to_string())
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// The values from start to end inclusive, going down if end is lower than start.
+fn range(start: i64, end: i64, step: u64) -> Vec<i64> {
+    let step = step as usize;
+    if start <= end {
+        (start..=end).step_by(step).collect()
+    } else {
+        (end..=start).rev().step_by(step).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::expand_braces;
+    use crate::parser::quoting::chunk_quoted_string;
+
+    fn expand(input: &str) -> Vec<String> {
+        expand_braces(chunk_quoted_string(input).unwrap())
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn it_expands_alternatives() {
+        assert_eq!(vec!["echo", "a", "b", "c"], expand("echo {a,b,c}"));
+        assert_eq!(vec!["preapost", "prebpost"], expand("pre{a,b}post"));
+        assert_eq!(vec!["a1", "a2", "b1", "b2"], expand("{a,b}{1,2}"));
+        assert_eq!(vec!["a", "b1", "b2", "c"], expand("{a,b{1,2},c}"));
+        assert_eq!(vec!["x", "xy"], expand("x{,y}"));
+        assert_eq!(vec!["'a b'c", "dc"], expand("{'a b',d}c"));
+    }
+
+    #[test]
+    fn it_expands_sequences() {
+        assert_eq!(vec!["1", "2", "3"], expand("{1..3}"));
+        assert_eq!(vec!["3", "1", "-1"], expand("{3..-1..2}"));
+        assert_eq!(vec!["a", "b", "c"], expand("{a..c}"));
+        assert_eq!(vec!["f1", "f2"], expand("f{1..2}"));
+    }
+
+    #[test]
+    fn it_keeps_invalid_braces() {
+        assert_eq!(
+            vec!["{a}", "{}", "{a,b", "{", "}"],
+            expand("{a} {} {a,b { }")
+        );
+        assert_eq!(vec!["'{a,b}'", "{ab}", "{ac}"], expand("'{a,b}' {a{b,c}}"));
+        assert_eq!(vec!["{1..b}", "${x}"], expand("{1..b} ${x}"));
+    }
+}
@@ -0,0 +1,844 @@
+use crate::parser::arithmetic::{evaluate, ArithmeticError};
+use crate::parser::globbing::{
+    escape_glob_characters, expand_glob, has_glob_characters, matches_glob,
+};
+use crate::parser::parse_input;
+use crate::parser::quoting::{
+    is_positional_parameter, is_variable_name, ChunkPart, InputChunk, ParameterOperation,
+};
+use crate::parser::ParsingError;
+use crate::runner::{capture_output, RunnerError};
+use crate::state::ShellState;
+use thiserror::Error;
+use users::os::unix::UserExt;
+use users::{get_current_uid, get_user_by_name, get_user_by_uid};
+
+#[derive(Error, Debug)]
+pub(crate) enum ExpansionError {
+    #[error(transparent)]
+    Arithmetic(#[from] ArithmeticError),
+
+    #[error(transparent)]
+    SubstitutionParsing(Box<ParsingError>),
+
+    #[error(transparent)]
+    SubstitutionRunner(Box<RunnerError>),
+
+    #[error("expansion nested too deeply")]
+    NestedTooDeeply,
+
+    #[error("{0}: {1}")]
+    ParameterNotSet(String, String),
+
+    #[error("${0}: cannot assign in this way")]
+    CannotAssign(String),
+
+    #[error("{0}: substring expression < 0")]
+    NegativeSubstring(String),
+}
+
+/// Expands the parameters and command substitutions of the chunk, and concatenates its parts into
+/// the final value.
+pub(crate) fn expand_chunk(
+    chunk: &InputChunk,
+    state: &mut ShellState,
+) -> Result<String, ExpansionError> {
+    let fields = expand_fields(chunk, None, state)?;
+
+    Ok(fields.into_iter().map(|field| field.value).collect())
+}
+
+/// Expands the chunk into a glob pattern, where unquoted text and expansions are matched as
+/// patterns, e.g. `$PATTERN`, whereas quoted ones are matched literally.
+fn expand_pattern(chunk: &InputChunk, state: &mut ShellState) -> Result<String, ExpansionError> {
+    let mut pattern = String::new();
+
+    for part in chunk.parts() {
+        match part {
+            ChunkPart::Unquoted(text) => pattern.push_str(text),
+            ChunkPart::Quoted(_) | ChunkPart::DoubleQuoted(_) | ChunkPart::Tilde(_) => {
+                pattern.push_str(&escape_glob_characters(&expand_part(part, state)?));
+            }
+            part => pattern.push_str(&expand_part(part, state)?),
+        }
+    }
+
+    Ok(pattern)
+}
+
+/// Expands the chunk like [`expand_chunk`], splitting the results of unquoted expansions into
+/// fields at the characters of `$IFS`, then expands the unquoted glob patterns of each field into
+/// the matching paths. Patterns matching nothing are left as they are, like bash does.
+///
+/// Chunks whose unquoted expansions are empty are removed, e.g. `$UNSET`, unlike `""`.
+pub(crate) fn expand_words(
+    chunk: &InputChunk,
+    state: &mut ShellState,
+) -> Result<Vec<String>, ExpansionError> {
+    let separators = field_separators();
+    let fields = expand_fields(chunk, Some(&separators), state)?;
+
+    let mut words = vec![];
+    for field in fields {
+        if field.is_glob {
+            let paths = expand_glob(&field.pattern);
+            if !paths.is_empty() {
+                words.extend(paths);
+                continue;
+            }
+        }
+
+        words.push(field.value);
+    }
+
+    Ok(words)
+}
+
+/// An expanded field of a chunk, along with the glob pattern it forms.
+#[derive(Default)]
+struct Field {
+    value: String,
+
+    /// The value, where the glob characters not coming from unquoted text are escaped.
+    pattern: String,
+
+    /// Whether unquoted text contains glob characters.
+    is_glob: bool,
+
+    /// Whether the field exists even if empty, e.g. because of quotes.
+    is_set: bool,
+}
+
+impl Field {
+    /// Appends text, which is a glob pattern if unquoted, or matched literally otherwise.
+    fn push(&mut self, text: &str, is_pattern: bool) {
+        self.value.push_str(text);
+        if is_pattern {
+            self.pattern.push_str(text);
+            self.is_glob |= has_glob_characters(text);
+        } else {
+            self.pattern.push_str(&escape_glob_characters(text));
+        }
+        self.is_set = true;
+    }
+}
+
+/// Expands the parts of the chunk, splitting the results of unquoted expansions at the separators
+/// if any.
+///
+/// Whitespace separators are merged and trimmed, whereas each other separator delimits a field, so
+/// `a::b` has an empty field with `:` as separator. Literal text is never split.
+fn expand_fields(
+    chunk: &InputChunk,
+    separators: Option<&str>,
+    state: &mut ShellState,
+) -> Result<Vec<Field>, ExpansionError> {
+    let mut fields = vec![];
+    let mut field = Field::default();
+    expand_parts(
+        chunk.parts(),
+        separators,
+        false,
+        state,
+        &mut fields,
+        &mut field,
+    )?;
+
+    // Without splitting, the chunk always forms a single field, even if empty.
+    if field.is_set || separators.is_none() {
+        fields.push(field);
+    }
+
+    Ok(fields)
+}
+
+/// Expands the parts into the fields, leaving the last field open for the following parts.
+///
+/// The literal text of a parameter operation's word is split like expansions, e.g. `${X:-a b}`.
+fn expand_parts(
+    parts: &[ChunkPart],
+    separators: Option<&str>,
+    is_word: bool,
+    state: &mut ShellState,
+    fields: &mut Vec<Field>,
+    field: &mut Field,
+) -> Result<(), ExpansionError> {
+    for part in parts {
+        let (text, is_pattern) = match part {
+            ChunkPart::Unquoted(text) if is_word => (text.clone(), true),
+            ChunkPart::Unquoted(text) => {
+                field.push(text, true);
+                continue;
+            }
+            ChunkPart::Quoted(text) => {
+                field.push(text, false);
+                continue;
+            }
+            // Home directories are taken literally.
+            ChunkPart::Tilde(user) => {
+                field.push(&expand_tilde(user), false);
+                continue;
+            }
+            // Each positional parameter forms its own field within double quotes, e.g. `"$@"`.
+            ChunkPart::DoubleQuoted(part)
+                if separators.is_some()
+                    && matches!(&**part, ChunkPart::Parameter(name) if name == "@") =>
+            {
+                for (index, argument) in state.arguments().iter().enumerate() {
+                    if index > 0 {
+                        fields.push(std::mem::take(field));
+                    }
+                    field.push(argument, false);
+                }
+                continue;
+            }
+            ChunkPart::DoubleQuoted(part) => {
+                field.push(&expand_part(part, state)?, false);
+                continue;
+            }
+            // The word replacing the parameter is expanded in place, e.g. `${X:-~/*.txt}`.
+            ChunkPart::ParameterOperation(name, operation) => {
+                match apply_operation(name, operation, state)? {
+                    OperationResult::Value(value) => (value, false),
+                    OperationResult::Word(word) => {
+                        expand_parts(word.parts(), separators, true, state, fields, field)?;
+                        continue;
+                    }
+                }
+            }
+            part => (expand_part(part, state)?, false),
+        };
+
+        let Some(separators) = separators.filter(|separators| !separators.is_empty()) else {
+            field.push(&text, is_pattern);
+            continue;
+        };
+
+        // Split the expanded text, each delimiter ending the current field.
+        let is_separator = |char: char| separators.contains(char);
+        let mut rest = text.as_str();
+        while !rest.is_empty() {
+            let end = rest.find(is_separator).unwrap_or(rest.len());
+            if end > 0 {
+                field.push(&rest[..end], is_pattern);
+            }
+            rest = &rest[end..];
+
+            if rest.is_empty() {
+                break;
+            }
+
+            // A delimiter is made of whitespaces around at most one other separator.
+            rest = rest.trim_start_matches(|char: char| is_separator(char) && char.is_whitespace());
+            let has_other_separator = rest.starts_with(is_separator);
+            if let Some(separator) = rest.chars().next().filter(|_| has_other_separator) {
+                rest = rest[separator.len_utf8()..]
+                    .trim_start_matches(|char: char| is_separator(char) && char.is_whitespace());
+            }
+
+            if field.is_set || has_other_separator {
+                fields.push(std::mem::take(field));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Expands a parameter, command substitution or arithmetic expression.
+fn expand_part(part: &ChunkPart, state: &mut ShellState) -> Result<String, ExpansionError> {
+    Ok(match part {
+        ChunkPart::Unquoted(text) | ChunkPart::Quoted(text) => text.clone(),
+        ChunkPart::Parameter(name) => expand_parameter(name, state),
+        ChunkPart::ParameterOperation(name, operation) => {
+            match apply_operation(name, operation, state)? {
+                OperationResult::Value(value) => value,
+                OperationResult::Word(word) => expand_chunk(word, state)?,
+            }
+        }
+        ChunkPart::Tilde(user) => expand_tilde(user),
+        ChunkPart::Command(source) => substitute_command(source, state)?,
+        ChunkPart::Arithmetic(expression) => evaluate(expression)?.to_string(),
+        ChunkPart::DoubleQuoted(part) => expand_part(part, state)?,
+    })
+}
+
+/// Expands a parameter, missing arguments expanding to an empty string like undefined variables.
+fn expand_parameter(name: &str, state: &ShellState) -> String {
+    parameter_value(name, state).unwrap_or_default()
+}
+
+/// Returns the value of a parameter, or `None` if it is unset.
+fn parameter_value(name: &str, state: &ShellState) -> Option<String> {
+    match name {
+        "?" => Some(state.last_status().to_string()),
+        "0" => Some(state.name().to_owned()),
+        "#" => Some(state.arguments().len().to_string()),
+        "@" => Some(state.arguments().join(" ")),
+        // The arguments are joined with the first field separator, e.g. for `"$*"`.
+        "*" => {
+            let separator = field_separators().chars().next().map(String::from);
+            Some(state.arguments().join(&separator.unwrap_or_default()))
+        }
+        _ if is_positional_parameter(name) => name
+            .parse::<usize>()
+            .ok()
+            .and_then(|number| state.arguments().get(number.checked_sub(1)?))
+            .cloned(),
+        _ => std::env::var(name).ok(),
+    }
+}
+
+/// The outcome of a parameter operation.
+enum OperationResult<'a> {
+    /// The final value, e.g. the one of the parameter.
+    Value(String),
+
+    /// The word to expand in place of the parameter.
+    Word(&'a InputChunk),
+}
+
+/// Applies the operation to the parameter, which counts as unset if empty.
+///
+/// The length and substrings of unset parameters are the ones of an empty value.
+fn apply_operation<'a>(
+    name: &str,
+    operation: &'a ParameterOperation,
+    state: &mut ShellState,
+) -> Result<OperationResult<'a>, ExpansionError> {
+    let value = parameter_value(name, state).filter(|value| !value.is_empty());
+
+    Ok(match (operation, value) {
+        (ParameterOperation::Length, value) => {
+            OperationResult::Value(value.unwrap_or_default().chars().count().to_string())
+        }
+        (ParameterOperation::Substring(offset, length), value) => {
+            let offset = expand_chunk(offset, state)?;
+            let length = length
+                .as_ref()
+                .map(|length| expand_chunk(length, state))
+                .transpose()?;
+            OperationResult::Value(substring(
+                &value.unwrap_or_default(),
+                &offset,
+                length.as_deref(),
+            )?)
+        }
+        (
+            ParameterOperation::RemovePrefix {
+                pattern,
+                is_longest,
+            },
+            value,
+        ) => {
+            let pattern = expand_pattern(pattern, state)?;
+            OperationResult::Value(remove_prefix(
+                &value.unwrap_or_default(),
+                &pattern,
+                *is_longest,
+            ))
+        }
+        (
+            ParameterOperation::RemoveSuffix {
+                pattern,
+                is_longest,
+            },
+            value,
+        ) => {
+            let pattern = expand_pattern(pattern, state)?;
+            OperationResult::Value(remove_suffix(
+                &value.unwrap_or_default(),
+                &pattern,
+                *is_longest,
+            ))
+        }
+        (ParameterOperation::Alternative(word), Some(_)) => OperationResult::Word(word),
+        (ParameterOperation::Alternative(_), None) => OperationResult::Value(String::new()),
+        (_, Some(value)) => OperationResult::Value(value),
+        (ParameterOperation::Default(word), None) => OperationResult::Word(word),
+        (ParameterOperation::Assign(word), None) => {
+            // Only variables can be assigned, unlike positional and special parameters.
+            if !is_variable_name(name) {
+                return Err(ExpansionError::CannotAssign(name.to_owned()));
+            }
+
+            let value = expand_chunk(word, state)?;
+            std::env::set_var(name, &value);
+            OperationResult::Value(value)
+        }
+        (ParameterOperation::Error(word), None) => {
+            let message = Some(expand_chunk(word, state)?)
+                .filter(|message| !message.is_empty())
+                .unwrap_or_else(|| "parameter null or not set".to_owned());
+            return Err(ExpansionError::ParameterNotSet(name.to_owned(), message));
+        }
+    })
+}
+
+/// Returns the characters of the value from the offset, and up to the length if any, negative
+/// values counting from the end like bash does, e.g. `${X: -3:-1}`.
+fn substring(value: &str, offset: &str, length: Option<&str>) -> Result<String, ExpansionError> {
+    let chars: Vec<_> = value.chars().collect();
+    let count = chars.len() as i64;
+
+    // Offsets out of the value give nothing.
+    let mut start = evaluate(offset)?;
+    if start < 0 {
+        start += count;
+    }
+    if !(0..=count).contains(&start) {
+        return Ok(String::new());
+    }
+
+    let end = match length {
+        Some(length) => match evaluate(length)? {
+            value if value < 0 && count + value < start => {
+                return Err(ExpansionError::NegativeSubstring(length.trim().to_owned()));
+            }
+            value if value < 0 => count + value,
+            value => start.saturating_add(value).min(count),
+        },
+        None => count,
+    };
+
+    Ok(chars[start as usize..end as usize].iter().collect())
+}
+
+/// Removes the shortest or longest prefix of the value matching the glob pattern, if any.
+fn remove_prefix(value: &str, pattern: &str, is_longest: bool) -> String {
+    let mut ends: Vec<_> = value.char_indices().map(|(index, _)| index).collect();
+    ends.push(value.len());
+    if is_longest {
+        ends.reverse();
+    }
+
+    ends.into_iter()
+        .find(|end| matches_glob(pattern, &value[..*end]))
+        .map_or_else(|| value.to_owned(), |end| value[end..].to_owned())
+}
+
+/// Removes the shortest or longest suffix of the value matching the glob pattern, if any.
+fn remove_suffix(value: &str, pattern: &str, is_longest: bool) -> String {
+    let mut starts: Vec<_> = value.char_indices().map(|(index, _)| index).collect();
+    starts.push(value.len());
+    if !is_longest {
+        starts.reverse();
+    }
+
+    starts
+        .into_iter()
+        .find(|start| matches_glob(pattern, &value[*start..]))
+        .map_or_else(|| value.to_owned(), |start| value[..start].to_owned())
+}
+
+/// Returns the home directory of the user, or of the current user if none is given. Unknown users
+/// are left as they are.
+fn expand_tilde(user: &str) -> String {
+    let home = if user.is_empty() {
+        std::env::var_os("HOME")
+            .or_else(|| get_user_by_uid(get_current_uid()).map(|user| user.home_dir().into()))
+    } else {
+        get_user_by_name(user).map(|user| user.home_dir().into())
+    };
+
+    home.map(|home| home.to_string_lossy().into_owned())
+        .unwrap_or_else(|| format!("~{user}"))
+}
+
+/// Runs the commands and returns their output, without the trailing newlines.
+/// The characters splitting fields when `$IFS` is unset.
+const DEFAULT_IFS: &str = " \t\n";
+
+/// The field separators of `$IFS`, or the default ones if unset.
+pub(crate) fn field_separators() -> String {
+    std::env::var("IFS").unwrap_or_else(|_| DEFAULT_IFS.to_owned())
+}
+
+/// Splits the text into at most `max_fields` fields at the separators, the last field holding the
+/// rest of the text, e.g. for `read`.
+///
+/// Whitespace separators are merged and trimmed around the fields, whereas each other separator
+/// delimits a field, so `a::b` has an empty field with `:` as separator.
+pub(crate) fn split_fields(text: &str, separators: &str, max_fields: usize) -> Vec<String> {
+    let is_separator = |char: char| separators.contains(char);
+    let is_whitespace = |char: char| is_separator(char) && char.is_whitespace();
+
+    let mut fields = vec![];
+    let mut rest = text.trim_matches(is_whitespace);
+    while !rest.is_empty() {
+        if fields.len() + 1 >= max_fields {
+            fields.push(rest.to_owned());
+            break;
+        }
+
+        let end = rest.find(is_separator).unwrap_or(rest.len());
+        fields.push(rest[..end].to_owned());
+
+        // Skip the whitespaces around at most one other separator.
+        rest = rest[end..].trim_start_matches(is_whitespace);
+        if let Some(separator) = rest.chars().next().filter(|char| is_separator(*char)) {
+            rest = rest[separator.len_utf8()..].trim_start_matches(is_whitespace);
+        }
+    }
+
+    fields
+}
+
+fn substitute_command(source: &str, state: &mut ShellState) -> Result<String, ExpansionError> {
+    let lists =
+        parse_input(source, state).map_err(|e| ExpansionError::SubstitutionParsing(Box::new(e)))?;
+
+    let output = state.nested(ExpansionError::NestedTooDeeply, |state| {
+        capture_output(lists, state).map_err(|e| ExpansionError::SubstitutionRunner(Box::new(e)))
+    })?;
+
+    Ok(output.trim_end_matches('\n').to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand_chunk, expand_fields, expand_words, split_fields};
+    use crate::parser::quoting::chunk_quoted_string;
+    use crate::state::ShellState;
+
+    #[test]
+    fn it_expands_the_last_status() {
+        let mut state = ShellState::new();
+        state.set_last_status(127);
+
+        let chunks = chunk_quoted_string(r#"$? "status: $?" '$?'"#).unwrap();
+
+        let values: Vec<_> = chunks
+            .iter()
+            .map(|chunk| expand_chunk(chunk, &mut state).unwrap())
+            .collect();
+
+        assert_eq!(vec!["127", "status: 127", "$?"], values);
+    }
+
+    #[test]
+    fn it_expands_positional_parameters() {
+        let mut state = ShellState::new();
+        let arguments = ('a'..='j').map(String::from).collect();
+        state.set_script("script.sh".to_owned(), arguments);
+
+        // Only braced numbers can have several digits.
+        let chunks = chunk_quoted_string(r#"$0 "$1" $10 ${10} ${11}x"#).unwrap();
+
+        let values: Vec<_> = chunks
+            .iter()
+            .map(|chunk| expand_chunk(chunk, &mut state).unwrap())
+            .collect();
+
+        assert_eq!(vec!["script.sh", "a", "a0", "j", "x"], values);
+    }
+
+    #[test]
+    fn it_expands_all_positional_parameters() {
+        let mut state = ShellState::new();
+        let arguments = vec!["a b".to_owned(), "c".to_owned()];
+        state.set_script("script.sh".to_owned(), arguments);
+
+        let chunks = chunk_quoted_string(r#"$# "$@" x"$@"y "$*" $@"#).unwrap();
+        let words: Vec<_> = chunks
+            .iter()
+            .flat_map(|chunk| expand_words(chunk, &mut state).unwrap())
+            .collect();
+
+        // Quoted `$@` keeps each argument whole, whereas `$*` joins them.
+        assert_eq!(
+            vec!["2", "a b", "c", "xa b", "cy", "a b c", "a", "b", "c"],
+            words
+        );
+
+        // Quoted `$@` expands to no field at all without arguments.
+        state.set_script("script.sh".to_owned(), vec![]);
+        let chunks = chunk_quoted_string(r#""$@" $# "$*""#).unwrap();
+        let words: Vec<_> = chunks
+            .iter()
+            .flat_map(|chunk| expand_words(chunk, &mut state).unwrap())
+            .collect();
+
+        assert_eq!(vec!["0", ""], words);
+    }
+
+    #[test]
+    fn it_expands_environment_variables() {
+        let mut state = ShellState::new();
+        std::env::set_var("CODECRAFTERS_SHELL_EXPANSION", "world");
+        std::env::remove_var("CODECRAFTERS_SHELL_UNDEFINED");
+
+        let chunks = chunk_quoted_string(
+            r#"hello_$CODECRAFTERS_SHELL_EXPANSION "${CODECRAFTERS_SHELL_EXPANSION}!" '$CODECRAFTERS_SHELL_EXPANSION' a${CODECRAFTERS_SHELL_UNDEFINED}b"#,
+        )
+        .unwrap();
+
+        let values: Vec<_> = chunks
+            .iter()
+            .map(|chunk| expand_chunk(chunk, &mut state).unwrap())
+            .collect();
+
+        assert_eq!(
+            vec![
+                "hello_world",
+                "world!",
+                "$CODECRAFTERS_SHELL_EXPANSION",
+                "ab"
+            ],
+            values
+        );
+    }
+
+    #[test]
+    fn it_substitutes_commands() {
+        let mut state = ShellState::new();
+
+        let chunks = chunk_quoted_string(
+            r#"$(echo hello) "[$(printf 'a\n\n')]" `echo back` "$(echo $(echo nested) | tr a-z A-Z)" '$(echo no)'"#,
+        )
+        .unwrap();
+
+        let values: Vec<_> = chunks
+            .iter()
+            .map(|chunk| expand_chunk(chunk, &mut state).unwrap())
+            .collect();
+
+        assert_eq!(vec!["hello", "[a]", "back", "NESTED", "$(echo no)"], values);
+    }
+
+    #[test]
+    fn it_captures_the_output_of_builtins() {
+        let mut state = ShellState::new();
+        let long_value = "a".repeat(100_000);
+        std::env::set_var("CODECRAFTERS_SHELL_CAPTURED", &long_value);
+
+        let chunks = chunk_quoted_string(
+            r#""[$(pwd)]" "$(type cd; echo done)" "$(echo $CODECRAFTERS_SHELL_CAPTURED)""#,
+        )
+        .unwrap();
+        let values: Vec<_> = chunks
+            .iter()
+            .map(|chunk| expand_chunk(chunk, &mut state).unwrap())
+            .collect();
+
+        // The current directory may be reached through symbolic links.
+        let directory = values[0].trim_start_matches('[').trim_end_matches(']');
+        assert_eq!(
+            std::fs::canonicalize(std::env::current_dir().unwrap()).unwrap(),
+            std::fs::canonicalize(directory).unwrap()
+        );
+
+        // Outputs larger than a pipe don't block the builtins.
+        assert_eq!("cd is a shell builtin\ndone", values[1]);
+        assert_eq!(long_value, values[2]);
+
+        std::env::remove_var("CODECRAFTERS_SHELL_CAPTURED");
+    }
+
+    #[test]
+    fn it_applies_parameter_operations() {
+        let mut state = ShellState::new();
+        std::env::set_var("CODECRAFTERS_SHELL_OPERATION_SET", "value");
+        std::env::set_var("CODECRAFTERS_SHELL_OPERATION_EMPTY", "");
+        std::env::remove_var("CODECRAFTERS_SHELL_OPERATION_UNSET");
+        std::env::remove_var("CODECRAFTERS_SHELL_OPERATION_ASSIGNED");
+
+        let chunks = chunk_quoted_string(
+            r#"${CODECRAFTERS_SHELL_OPERATION_SET:-a} ${CODECRAFTERS_SHELL_OPERATION_EMPTY:-"b c"} ${CODECRAFTERS_SHELL_OPERATION_UNSET:+d} ${CODECRAFTERS_SHELL_OPERATION_SET:+e  f} "${CODECRAFTERS_SHELL_OPERATION_UNSET:-g  h}" ${CODECRAFTERS_SHELL_OPERATION_ASSIGNED:=i} $CODECRAFTERS_SHELL_OPERATION_ASSIGNED"#,
+        )
+        .unwrap();
+        let words: Vec<_> = chunks
+            .iter()
+            .flat_map(|chunk| expand_words(chunk, &mut state).unwrap())
+            .collect();
+
+        // Unquoted words are split, but not their quoted text, and only `:=` assigns the variable.
+        assert_eq!(vec!["value", "b c", "e", "f", "g  h", "i", "i"], words);
+        assert_eq!(
+            Err(std::env::VarError::NotPresent),
+            std::env::var("CODECRAFTERS_SHELL_OPERATION_UNSET")
+        );
+
+        // Fail with the message if the parameter is unset, or when assigning other parameters.
+        for (input, error) in [
+            (
+                "${CODECRAFTERS_SHELL_OPERATION_UNSET:?is $((1 + 1))}",
+                "CODECRAFTERS_SHELL_OPERATION_UNSET: is 2",
+            ),
+            (
+                "${CODECRAFTERS_SHELL_OPERATION_EMPTY:?}",
+                "CODECRAFTERS_SHELL_OPERATION_EMPTY: parameter null or not set",
+            ),
+            ("${1:=a}", "$1: cannot assign in this way"),
+        ] {
+            let chunks = chunk_quoted_string(input).unwrap();
+            let result = expand_chunk(&chunks[0], &mut state);
+            assert_eq!(error, result.unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    fn it_expands_lengths_and_substrings() {
+        let mut state = ShellState::new();
+        std::env::set_var("CODECRAFTERS_SHELL_SUBSTRING", "hello");
+        std::env::remove_var("CODECRAFTERS_SHELL_SUBSTRING_UNSET");
+
+        let chunks = chunk_quoted_string(
+            r#"${#CODECRAFTERS_SHELL_SUBSTRING} ${#CODECRAFTERS_SHELL_SUBSTRING_UNSET} ${CODECRAFTERS_SHELL_SUBSTRING:1:3} ${CODECRAFTERS_SHELL_SUBSTRING: -3} ${CODECRAFTERS_SHELL_SUBSTRING:(-3):$((1 + 1))} ${CODECRAFTERS_SHELL_SUBSTRING:1:-1} ${CODECRAFTERS_SHELL_SUBSTRING:9}. ${CODECRAFTERS_SHELL_SUBSTRING_UNSET:1}."#,
+        )
+        .unwrap();
+        let values: Vec<_> = chunks
+            .iter()
+            .map(|chunk| expand_chunk(chunk, &mut state).unwrap())
+            .collect();
+
+        assert_eq!(vec!["5", "0", "ell", "llo", "ll", "ell", ".", "."], values);
+
+        // Fail if a negative length ends before the offset.
+        let chunks = chunk_quoted_string("${CODECRAFTERS_SHELL_SUBSTRING:3:-3}").unwrap();
+        let result = expand_chunk(&chunks[0], &mut state);
+        assert_eq!(
+            "-3: substring expression < 0",
+            result.unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn it_removes_matching_prefixes_and_suffixes() {
+        let mut state = ShellState::new();
+        std::env::set_var("CODECRAFTERS_SHELL_REMOVAL", "a.tar.gz");
+        std::env::set_var("CODECRAFTERS_SHELL_REMOVAL_PATTERN", "*.");
+
+        let chunks = chunk_quoted_string(
+            r#"${CODECRAFTERS_SHELL_REMOVAL%%.*} ${CODECRAFTERS_SHELL_REMOVAL%.*} ${CODECRAFTERS_SHELL_REMOVAL#*.} ${CODECRAFTERS_SHELL_REMOVAL##*.} ${CODECRAFTERS_SHELL_REMOVAL#"*."} ${CODECRAFTERS_SHELL_REMOVAL#$CODECRAFTERS_SHELL_REMOVAL_PATTERN} ${CODECRAFTERS_SHELL_REMOVAL#"$CODECRAFTERS_SHELL_REMOVAL_PATTERN"} ${CODECRAFTERS_SHELL_REMOVAL%[a-z]}"#,
+        )
+        .unwrap();
+        let values: Vec<_> = chunks
+            .iter()
+            .map(|chunk| expand_chunk(chunk, &mut state).unwrap())
+            .collect();
+
+        // Quoted patterns are matched literally, unlike unquoted expansions.
+        assert_eq!(
+            vec!["a", "a.tar", "tar.gz", "gz", "a.tar.gz", "tar.gz", "a.tar.gz", "a.tar.g"],
+            values
+        );
+    }
+
+    #[test]
+    fn it_expands_unquoted_glob_patterns() {
+        let mut state = ShellState::new();
+
+        let directory = std::env::temp_dir().join("codecrafters_shell_expansion_glob");
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        for file in ["a.txt", "b.txt", "*.txt"] {
+            std::fs::write(directory.join(file), "").unwrap();
+        }
+        let directory = directory.display().to_string();
+
+        let chunks = chunk_quoted_string(&format!(
+            r#"{directory}/*.txt {directory}/"*".txt {directory}/\*.txt {directory}/*.rs"#
+        ))
+        .unwrap();
+
+        let values: Vec<_> = chunks
+            .iter()
+            .map(|chunk| expand_words(chunk, &mut state).unwrap())
+            .collect();
+
+        assert_eq!(
+            vec![
+                vec![
+                    format!("{directory}/*.txt"),
+                    format!("{directory}/a.txt"),
+                    format!("{directory}/b.txt"),
+                ],
+                // Quoted glob characters are matched literally.
+                vec![format!("{directory}/*.txt")],
+                vec![format!("{directory}/*.txt")],
+                // Patterns matching nothing are preserved.
+                vec![format!("{directory}/*.rs")],
+            ],
+            values
+        );
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn it_expands_tilde_prefixes() {
+        let mut state = ShellState::new();
+        let home = std::env::var("HOME").unwrap();
+
+        let chunks = chunk_quoted_string(
+            "~ ~/file ~root/file ~codecrafters_shell_unknown_user/file a~b '~'",
+        )
+        .unwrap();
+
+        let values: Vec<_> = chunks
+            .iter()
+            .map(|chunk| expand_chunk(chunk, &mut state).unwrap())
+            .collect();
+
+        assert_eq!(
+            vec![
+                home.clone(),
+                format!("{home}/file"),
+                "/root/file".to_owned(),
+                "~codecrafters_shell_unknown_user/file".to_owned(),
+                "a~b".to_owned(),
+                "~".to_owned(),
+            ],
+            values
+        );
+    }
+
+    #[test]
+    fn it_splits_fields() {
+        assert_eq!(
+            vec!["a", "b", "c"],
+            split_fields("  a \t b\nc  ", " \t\n", usize::MAX)
+        );
+        assert_eq!(vec!["a", "b  c"], split_fields(" a b  c ", " \t\n", 2));
+        assert_eq!(vec!["a", "", "b"], split_fields("a::b", ":", usize::MAX));
+        assert_eq!(vec!["a", "b"], split_fields("a : b", ": ", usize::MAX));
+        assert!(split_fields("   ", " ", usize::MAX).is_empty());
+    }
+
+    #[test]
+    fn it_splits_unquoted_expansions() {
+        let mut state = ShellState::new();
+        std::env::set_var("CODECRAFTERS_SHELL_SPLIT", " a  b:c::d: ");
+        std::env::remove_var("CODECRAFTERS_SHELL_SPLIT_UNSET");
+
+        let chunks = chunk_quoted_string(
+            r#"x$CODECRAFTERS_SHELL_SPLIT"y" "$CODECRAFTERS_SHELL_SPLIT" $CODECRAFTERS_SHELL_SPLIT_UNSET a' 'b"#,
+        )
+        .unwrap();
+
+        let values: Vec<Vec<String>> = chunks
+            .iter()
+            .map(|chunk| {
+                let fields = expand_fields(chunk, Some(" :"), &mut state).unwrap();
+                fields.into_iter().map(|field| field.value).collect()
+            })
+            .collect();
+
+        assert_eq!(
+            vec![
+                vec!["x", "a", "b", "c", "", "d", "y"],
+                vec![" a  b:c::d: "],
+                vec![],
+                vec!["a b"],
+            ],
+            values
+        );
+
+        // Without splitting, expansions are concatenated as they are.
+        assert_eq!(
+            "x a  b:c::d: y",
+            expand_chunk(&chunks[0], &mut state).unwrap()
+        );
+    }
+}
@@ -0,0 +1,301 @@
+use std::path::Path;
+
+/// The characters which turn a word into a glob pattern.
+const GLOB_CHARACTERS: [char; 3] = ['*', '?', '['];
+const ESCAPE_CHARACTER: char = '\\';
+
+/// Whether the unquoted text contains glob characters.
+pub(crate) fn has_glob_characters(text: &str) -> bool {
+    text.contains(GLOB_CHARACTERS)
+}
+
+/// Escapes the glob characters of the text, so it is matched literally.
+pub(crate) fn escape_glob_characters(text: &str) -> String {
+    let mut escaped = String::new();
+
+    for char in text.chars() {
+        if GLOB_CHARACTERS.contains(&char) || char == ']' || char == ESCAPE_CHARACTER {
+            escaped.push(ESCAPE_CHARACTER);
+        }
+        escaped.push(char);
+    }
+
+    escaped
+}
+
+/// Lists the paths matching the pattern, sorted.
+///
+/// Like bash, wildcards don't match a leading `.` of file names unless the pattern explicitly
+/// starts with one, and a pattern ending with `/` only matches directories.
+pub(crate) fn expand_glob(pattern: &str) -> Vec<String> {
+    let (mut paths, components) = match pattern.strip_prefix('/') {
+        Some(relative) => (vec!["/".to_owned()], relative),
+        None => (vec![String::new()], pattern),
+    };
+
+    for component in components.split('/').filter(|c| !c.is_empty()) {
+        paths = paths
+            .into_iter()
+            .flat_map(|path| expand_component(&path, component))
+            .collect();
+    }
+
+    if pattern.ends_with('/') {
+        paths = paths
+            .into_iter()
+            .filter(|path| Path::new(path).is_dir())
+            .map(|path| format!("{path}/"))
+            .collect();
+    }
+
+    paths.sort();
+
+    paths
+}
+
+/// Lists the paths within the directory whose name matches the pattern component.
+fn expand_component(directory: &str, component: &str) -> Vec<String> {
+    let join = |name: &str| {
+        if directory.is_empty() || directory.ends_with('/') {
+            format!("{directory}{name}")
+        } else {
+            format!("{directory}/{name}")
+        }
+    };
+
+    // Components without wildcards match a single path, if it exists.
+    if !has_unescaped_glob_characters(component) {
+        let path = join(&unescape(component));
+
+        return if Path::new(&path).symlink_metadata().is_ok() {
+            vec![path]
+        } else {
+            vec![]
+        };
+    }
+
+    let directory_path = Path::new(if directory.is_empty() { "." } else { directory });
+    let Ok(entries) = directory_path.read_dir() else {
+        // Ignore missing directories, permissions, ...
+        return vec![];
+    };
+
+    entries
+        // Ignore file errors.
+        .filter_map(Result::ok)
+        // Ignore invalid UTF-8 filenames.
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        // Only match hidden files if the pattern explicitly starts with a dot.
+        .filter(|name| !name.starts_with('.') || component.starts_with('.'))
+        .filter(|name| matches_glob(component, name))
+        .map(|name| join(&name))
+        .collect()
+}
+
+fn has_unescaped_glob_characters(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+
+    while let Some(char) = chars.next() {
+        if char == ESCAPE_CHARACTER {
+            chars.next();
+        } else if GLOB_CHARACTERS.contains(&char) {
+            return true;
+        }
+    }
+
+    false
+}
+
+fn unescape(pattern: &str) -> String {
+    let mut text = String::new();
+
+    let mut chars = pattern.chars();
+    while let Some(char) = chars.next() {
+        if char == ESCAPE_CHARACTER {
+            text.extend(chars.next());
+        } else {
+            text.push(char);
+        }
+    }
+
+    text
+}
+
+/// Whether the whole text matches the glob pattern, where `*` matches any string, `?` any char,
+/// `[...]` any char of the class (negated with `[!...]` or `[^...]`), and `\` escapes a char.
+pub(crate) fn matches_glob(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let mut pattern_index = 0;
+    let mut text_index = 0;
+
+    // The position of the last `*`, and of the text when it was reached, to backtrack to.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while text_index < text.len() {
+        let matched_len = match pattern.get(pattern_index) {
+            Some('*') => {
+                // Match an empty string first, and extend it on mismatch.
+                backtrack = Some((pattern_index, text_index));
+                pattern_index += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match_class(&pattern[pattern_index..], text[text_index]),
+            Some(&ESCAPE_CHARACTER) if pattern_index + 1 < pattern.len() => {
+                (pattern[pattern_index + 1] == text[text_index]).then_some(2)
+            }
+            Some(&char) => (char == text[text_index]).then_some(1),
+            None => None,
+        };
+
+        match (matched_len, backtrack) {
+            (Some(pattern_len), _) => {
+                pattern_index += pattern_len;
+                text_index += 1;
+            }
+            (None, Some((star_index, star_text_index))) => {
+                // Let the last `*` match one more char.
+                pattern_index = star_index + 1;
+                text_index = star_text_index + 1;
+                backtrack = Some((star_index, star_text_index + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+
+    // Trailing stars match the empty string.
+    pattern[pattern_index..].iter().all(|char| *char == '*')
+}
+
+/// Matches the char against the class starting the pattern, returning the length of the class if
+/// it matched. An unterminated class is matched as a literal `[`.
+fn match_class(pattern: &[char], char: char) -> Option<usize> {
+    let mut index = 1;
+
+    let negated = matches!(pattern.get(index), Some('!' | '^'));
+    if negated {
+        index += 1;
+    }
+
+    let mut matched = false;
+    let mut is_first = true;
+
+    while let Some(&class_char) = pattern.get(index) {
+        // A `]` right after the opening bracket is part of the class.
+        if class_char == ']' && !is_first {
+            return (matched != negated).then_some(index + 1);
+        }
+
+        let (start, start_len) = if class_char == ESCAPE_CHARACTER {
+            (*pattern.get(index + 1)?, 2)
+        } else {
+            (class_char, 1)
+        };
+
+        // Match ranges, e.g. `a-z`, unless the `-` is the last char of the class.
+        match (
+            pattern.get(index + start_len),
+            pattern.get(index + start_len + 1),
+        ) {
+            (Some('-'), Some(&end)) if end != ']' => {
+                matched |= (start..=end).contains(&char);
+                index += start_len + 2;
+            }
+            _ => {
+                matched |= start == char;
+                index += start_len;
+            }
+        }
+
+        is_first = false;
+    }
+
+    // Unterminated class.
+    (char == '[').then_some(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_glob_characters, expand_glob, matches_glob};
+    use std::fs;
+
+    #[test]
+    fn it_matches_glob_patterns() {
+        // Wildcards.
+        assert!(matches_glob("*.txt", "notes.txt"));
+        assert!(matches_glob("*.txt", ".txt"));
+        assert!(matches_glob("a*b*c", "aXXbYYc"));
+        assert!(!matches_glob("*.txt", "notes.md"));
+        assert!(matches_glob("file?.rs", "file1.rs"));
+        assert!(!matches_glob("file?.rs", "file.rs"));
+
+        // Character classes.
+        assert!(matches_glob("[abc]at", "bat"));
+        assert!(!matches_glob("[abc]at", "rat"));
+        assert!(matches_glob("[a-c]at", "cat"));
+        assert!(matches_glob("[!a-c]at", "rat"));
+        assert!(matches_glob("[^a-c]at", "rat"));
+        assert!(matches_glob("[]]", "]"));
+        assert!(matches_glob("[a-]", "-"));
+
+        // Unterminated classes and escapes are literal.
+        assert!(matches_glob("[abc", "[abc"));
+        assert!(matches_glob(r"\*", "*"));
+        assert!(!matches_glob(r"\*", "a"));
+
+        // Escaped text matches literally.
+        assert!(matches_glob(&escape_glob_characters("[a]*?"), "[a]*?"));
+        assert!(!matches_glob(&escape_glob_characters("a*"), "ab"));
+    }
+
+    #[test]
+    fn it_expands_glob_patterns_against_the_filesystem() {
+        let directory = std::env::temp_dir().join("codecrafters_shell_globbing");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("sub")).unwrap();
+        for file in ["a.txt", "b.txt", "c.md", ".hidden.txt", "sub/d.txt"] {
+            fs::write(directory.join(file), "").unwrap();
+        }
+
+        let directory = directory.display().to_string();
+        let paths = |names: &[&str]| -> Vec<String> {
+            names
+                .iter()
+                .map(|name| format!("{directory}/{name}"))
+                .collect()
+        };
+
+        assert_eq!(
+            paths(&["a.txt", "b.txt"]),
+            expand_glob(&format!("{directory}/*.txt"))
+        );
+        assert_eq!(
+            paths(&["a.txt", "b.txt"]),
+            expand_glob(&format!("{directory}/?.t[x]t"))
+        );
+        assert_eq!(
+            paths(&["a.txt"]),
+            expand_glob(&format!("{directory}/[!b].t?t"))
+        );
+        assert_eq!(
+            paths(&["a.txt", "b.txt", "c.md"]),
+            expand_glob(&format!("{directory}/[a-c].*"))
+        );
+        assert_eq!(
+            paths(&[".hidden.txt"]),
+            expand_glob(&format!("{directory}/.*.txt"))
+        );
+        assert_eq!(
+            paths(&["sub/d.txt"]),
+            expand_glob(&format!("{directory}/s*/*.txt"))
+        );
+        assert_eq!(paths(&["sub/"]), expand_glob(&format!("{directory}/*/")));
+
+        // No match.
+        assert!(expand_glob(&format!("{directory}/*.rs")).is_empty());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
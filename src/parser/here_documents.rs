@@ -0,0 +1,233 @@
+use crate::parser::quoting::{
+    chunk_here_document_body, chunk_quoted_string, format_parameter_operation, ChunkPart,
+    InputChunk, QuotingError,
+};
+use std::collections::VecDeque;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub(crate) enum HereDocumentError {
+    #[error("Here-document delimited by `{0}` is not terminated")]
+    Unterminated(String),
+
+    #[error(transparent)]
+    Quoting(#[from] QuotingError),
+}
+
+/// A here-document operator, i.e. `<<` or `<<-`.
+pub(crate) struct HereDocumentOperator {
+    /// Whether leading tabs are stripped from the body lines, i.e. `<<-`.
+    strip_tabs: bool,
+
+    /// The delimiter, if it is part of the operator chunk, e.g. `<<EOF` rather than `<< EOF`.
+    delimiter: Option<Delimiter>,
+}
+
+impl HereDocumentOperator {
+    pub(crate) fn has_delimiter(&self) -> bool {
+        self.delimiter.is_some()
+    }
+}
+
+/// The line terminating a here-document.
+struct Delimiter {
+    text: String,
+
+    /// Whether any part of the delimiter was quoted, which disables expansions in the body.
+    is_quoted: bool,
+}
+
+impl From<&[ChunkPart]> for Delimiter {
+    fn from(parts: &[ChunkPart]) -> Self {
+        let mut text = String::new();
+        let mut is_quoted = false;
+
+        for part in parts {
+            match part {
+                ChunkPart::Unquoted(value) => text.push_str(value),
+                ChunkPart::Quoted(value) => {
+                    text.push_str(value);
+                    is_quoted = true;
+                }
+                ChunkPart::Parameter(name) => text.push_str(&format!("${name}")),
+                ChunkPart::ParameterOperation(name, operation) => {
+                    text.push_str(&format_parameter_operation(name, operation));
+                }
+                ChunkPart::Tilde(user) => text.push_str(&format!("~{user}")),
+                ChunkPart::Command(source) => text.push_str(&format!("$({source})")),
+                ChunkPart::Arithmetic(expression) => text.push_str(&format!("$(({expression}))")),
+                ChunkPart::DoubleQuoted(part) => {
+                    text.push_str(&Delimiter::from(std::slice::from_ref(&**part)).text);
+                    is_quoted = true;
+                }
+            }
+        }
+
+        Self { text, is_quoted }
+    }
+}
+
+/// Parses the chunk as a here-document operator, if it is one.
+pub(crate) fn parse_operator(chunk: &InputChunk) -> Option<HereDocumentOperator> {
+    // Only an unquoted `<<` starts an operator, `<<<` being a here-string.
+    let (ChunkPart::Unquoted(text), rest) = chunk.parts().split_first()? else {
+        return None;
+    };
+    let text = text
+        .strip_prefix("<<")
+        .filter(|text| !text.starts_with('<'))?;
+
+    let strip_tabs = text.starts_with('-');
+    let text = text.strip_prefix('-').unwrap_or(text);
+
+    let mut parts = vec![];
+    if !text.is_empty() {
+        parts.push(ChunkPart::Unquoted(text.to_owned()));
+    }
+    parts.extend_from_slice(rest);
+
+    Some(HereDocumentOperator {
+        strip_tabs,
+        delimiter: (!parts.is_empty()).then(|| Delimiter::from(parts.as_slice())),
+    })
+}
+
+/// Moves the bodies of the here-documents out of the input, returning the remaining commands along
+/// with the bodies in the order of their operators.
+///
+/// The body of a here-document starts on the line after its operator, and ends with a line equal to
+/// its delimiter.
+pub(crate) fn extract_here_documents(
+    input: &str,
+) -> Result<(String, Vec<InputChunk>), HereDocumentError> {
+    let mut commands = String::new();
+    let mut bodies = vec![];
+
+    let mut pending: VecDeque<HereDocumentOperator> = VecDeque::new();
+    let mut operators_count = 0;
+    let mut body = String::new();
+
+    for line in input.split('\n') {
+        // Collect the lines of the pending here-document up to its delimiter.
+        if let Some(operator) = pending.front() {
+            let line = if operator.strip_tabs {
+                line.trim_start_matches('\t')
+            } else {
+                line
+            };
+
+            // Safe to unwrap as only operators with a delimiter are pending.
+            let delimiter = operator.delimiter.as_ref().unwrap();
+            if line == delimiter.text {
+                let body = std::mem::take(&mut body);
+                bodies.push(if delimiter.is_quoted {
+                    InputChunk::from(ChunkPart::Quoted(body))
+                } else {
+                    chunk_here_document_body(&body)?
+                });
+                pending.pop_front();
+            } else {
+                body.push_str(line);
+                body.push('\n');
+            }
+
+            continue;
+        }
+
+        if !commands.is_empty() {
+            commands.push('\n');
+        }
+        commands.push_str(line);
+
+        // Queue the operators which appeared on this line, skipping those which were already seen.
+        let operators = find_operators(&commands);
+        if operators.len() > operators_count {
+            let count = operators.len();
+            pending.extend(operators.into_iter().skip(operators_count));
+            operators_count = count;
+        }
+    }
+
+    match pending
+        .front()
+        .and_then(|operator| operator.delimiter.as_ref())
+    {
+        Some(delimiter) => Err(HereDocumentError::Unterminated(delimiter.text.clone())),
+        None => Ok((commands, bodies)),
+    }
+}
+
+/// Finds the here-document operators of the commands, along with their delimiter.
+///
+/// The commands may be incomplete, e.g. within quotes, in which case no operator is returned until
+/// they are complete.
+fn find_operators(commands: &str) -> Vec<HereDocumentOperator> {
+    let Ok(chunks) = chunk_quoted_string(commands) else {
+        return vec![];
+    };
+
+    let mut operators = vec![];
+    let mut chunks = chunks.iter();
+    while let Some(chunk) = chunks.next() {
+        let Some(mut operator) = parse_operator(chunk) else {
+            continue;
+        };
+
+        // The delimiter may be the next chunk, e.g. `<< EOF`.
+        if operator.delimiter.is_none() {
+            operator.delimiter = chunks.next().map(|chunk| Delimiter::from(chunk.parts()));
+        }
+
+        // Operators without delimiter are reported when splitting the commands.
+        if operator.delimiter.is_some() {
+            operators.push(operator);
+        }
+    }
+
+    operators
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_here_documents, HereDocumentError};
+    use crate::parser::quoting::{ChunkPart, InputChunk};
+
+    #[test]
+    fn it_extracts_here_documents() {
+        let (commands, bodies) =
+            extract_here_documents("cat <<EOF | cat <<- 'END'\nhello $USER\nEOF\n\tworld\n\tEND")
+                .unwrap();
+
+        assert_eq!("cat <<EOF | cat <<- 'END'", commands);
+        assert_eq!(2, bodies.len());
+        assert_eq!(
+            vec![
+                ChunkPart::Quoted("hello ".to_owned()),
+                ChunkPart::Parameter("USER".to_owned()),
+                ChunkPart::Quoted("\n".to_owned()),
+            ],
+            bodies[0].parts()
+        );
+        assert_eq!(
+            InputChunk::from(ChunkPart::Quoted("world\n".to_owned())),
+            bodies[1]
+        );
+    }
+
+    #[test]
+    fn it_keeps_commands_after_here_documents() {
+        let (commands, bodies) =
+            extract_here_documents("cat <<EOF\nhello\nEOF\necho '<<X'").unwrap();
+
+        assert_eq!("cat <<EOF\necho '<<X'", commands);
+        assert_eq!(1, bodies.len());
+    }
+
+    #[test]
+    fn it_rejects_unterminated_here_documents() {
+        assert!(matches!(
+            extract_here_documents("cat <<EOF\nhello"),
+            Err(HereDocumentError::Unterminated(delimiter)) if delimiter == "EOF"
+        ));
+    }
+}
@@ -1,98 +1,929 @@
+use std::fmt::{Display, Formatter};
+use std::iter::Peekable;
+use std::str::Chars;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub(crate) enum QuotingError {
-    #[error("Dangling quote encountered")]
-    DanglingQuote,
+    #[error("unexpected EOF while looking for matching `'' (opened at offset {0})")]
+    DanglingSingleQuote(usize),
+
+    #[error("unexpected EOF while looking for matching `\"' (opened at offset {0})")]
+    DanglingDoubleQuote(usize),
+
+    #[error("Unterminated command substitution")]
+    UnterminatedSubstitution,
+}
+
+/// A chunk of input, i.e. a word made of adjacent parts which may be quoted differently.
+#[derive(Clone, Default)]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub(crate) struct InputChunk {
+    parts: Vec<ChunkPart>,
+}
+
+/// A part of an input chunk, which retains how it was quoted for the expansion stage.
+#[derive(Clone)]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub(crate) enum ChunkPart {
+    /// Text which was neither quoted nor escaped.
+    Unquoted(String),
+
+    /// Text which was quoted or escaped, and must be taken literally.
+    Quoted(String),
+
+    /// A parameter to expand when running the command (e.g. `$?`, `$HOME`, or `${HOME}`).
+    Parameter(String),
+
+    /// A braced parameter whose value goes through an operation when running the command (e.g.
+    /// `${HOME:-/}`).
+    ParameterOperation(String, ParameterOperation),
+
+    /// A tilde prefix to replace with a home directory when running the command, holding the user
+    /// name (e.g. `~` for the current user, or `~root`).
+    Tilde(String),
+
+    /// The source of a command substitution, whose output replaces it when running the command
+    /// (e.g. `$(pwd)` or `` `pwd` ``).
+    Command(String),
+
+    /// An arithmetic expression, whose result replaces it when running the command (e.g.
+    /// `$((1 + x))`).
+    Arithmetic(String),
+
+    /// An expansion within double quotes, whose result is taken literally rather than split into
+    /// fields (e.g. `"$HOME"`).
+    DoubleQuoted(Box<ChunkPart>),
 }
 
-pub(crate) enum InputChunk {
-    RawText(String),
+/// An operation on the value of a braced parameter, whose word is only expanded if used.
+#[derive(Clone)]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub(crate) enum ParameterOperation {
+    /// The word if the parameter is unset or empty, i.e. `${NAME:-word}`.
+    Default(InputChunk),
 
-    /// A chunk where at least some part of the text was originally quoted.
+    /// The word if the parameter is unset or empty, which is assigned to it as well, i.e.
+    /// `${NAME:=word}`.
+    Assign(InputChunk),
+
+    /// The word if the parameter is set and not empty, or nothing otherwise, i.e. `${NAME:+word}`.
+    Alternative(InputChunk),
+
+    /// A failure with the word as message if the parameter is unset or empty, i.e.
+    /// `${NAME:?word}`.
+    Error(InputChunk),
+
+    /// The number of characters of the value, i.e. `${#NAME}`.
+    Length,
+
+    /// The characters of the value from an offset, and up to a length if any, both being
+    /// arithmetic expressions once expanded, i.e. `${NAME:offset}` or `${NAME:offset:length}`.
+    Substring(InputChunk, Option<InputChunk>),
+
+    /// The value without its shortest or longest prefix matching the glob pattern, i.e.
+    /// `${NAME#pattern}` or `${NAME##pattern}`.
+    RemovePrefix {
+        pattern: InputChunk,
+        is_longest: bool,
+    },
+
+    /// The value without its shortest or longest suffix matching the glob pattern, i.e.
+    /// `${NAME%pattern}` or `${NAME%%pattern}`.
+    RemoveSuffix {
+        pattern: InputChunk,
+        is_longest: bool,
+    },
+}
+
+/// Formats the braced parameter as it could have been typed, e.g. `${NAME:-word}`.
+pub(crate) fn format_parameter_operation(name: &str, operation: &ParameterOperation) -> String {
+    match operation {
+        ParameterOperation::Default(word) => format!("${{{name}:-{word}}}"),
+        ParameterOperation::Assign(word) => format!("${{{name}:={word}}}"),
+        ParameterOperation::Alternative(word) => format!("${{{name}:+{word}}}"),
+        ParameterOperation::Error(word) => format!("${{{name}:?{word}}}"),
+        ParameterOperation::Length => format!("${{#{name}}}"),
+        ParameterOperation::Substring(offset, None) => format!("${{{name}:{offset}}}"),
+        ParameterOperation::Substring(offset, Some(length)) => {
+            format!("${{{name}:{offset}:{length}}}")
+        }
+        ParameterOperation::RemovePrefix {
+            pattern,
+            is_longest,
+        } => {
+            let operator = if *is_longest { "##" } else { "#" };
+            format!("${{{name}{operator}{pattern}}}")
+        }
+        ParameterOperation::RemoveSuffix {
+            pattern,
+            is_longest,
+        } => {
+            let operator = if *is_longest { "%%" } else { "%" };
+            format!("${{{name}{operator}{pattern}}}")
+        }
+    }
+}
+
+impl InputChunk {
+    pub(crate) fn parts(&self) -> &[ChunkPart] {
+        &self.parts
+    }
+
+    /// Returns the text of the chunk if it is made of unquoted text only.
     ///
     /// # Internal
-    /// This is useful to know since it helps discriminate actual pipes / io redirection from quoted
-    /// text containing one.
-    QuotedText(String),
+    /// This is useful to discriminate actual pipes / io redirection from quoted text containing
+    /// one.
+    pub(crate) fn as_unquoted(&self) -> Option<&str> {
+        match self.parts.as_slice() {
+            [ChunkPart::Unquoted(text)] => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Splits the chunk into the name and value of a variable assignment, if it is one, e.g.
+    /// `FOO="bar baz"`.
+    pub(crate) fn split_assignment(&self) -> Option<(String, InputChunk)> {
+        let (ChunkPart::Unquoted(text), rest) = self.parts.split_first()? else {
+            return None;
+        };
+        let (name, value) = text.split_once('=')?;
+        if !is_variable_name(name) {
+            return None;
+        }
+
+        let mut parts = vec![];
+        if !value.is_empty() {
+            parts.push(ChunkPart::Unquoted(value.to_owned()));
+        }
+        parts.extend_from_slice(rest);
+
+        Some((name.to_owned(), InputChunk { parts }))
+    }
+
+    pub(crate) fn has_command_substitution(&self) -> bool {
+        self.parts.iter().any(|part| match part {
+            ChunkPart::DoubleQuoted(part) => matches!(**part, ChunkPart::Command(_)),
+            part => matches!(part, ChunkPart::Command(_)),
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    /// Appends a part, merging it into the last part if both are text quoted the same way.
+    pub(crate) fn push_part(&mut self, part: ChunkPart) {
+        match (self.parts.last_mut(), part) {
+            (Some(ChunkPart::Unquoted(text)), ChunkPart::Unquoted(next))
+            | (Some(ChunkPart::Quoted(text)), ChunkPart::Quoted(next)) => text.push_str(&next),
+            (_, part) => self.parts.push(part),
+        }
+    }
+
+    /// Appends a character, merging it into the last part if it is quoted the same way.
+    pub(crate) fn push_char(&mut self, char: char, is_quoted: bool) {
+        match (self.parts.last_mut(), is_quoted) {
+            (Some(ChunkPart::Unquoted(text)), false) | (Some(ChunkPart::Quoted(text)), true) => {
+                text.push(char);
+            }
+            (_, false) => self.parts.push(ChunkPart::Unquoted(char.to_string())),
+            (_, true) => self.parts.push(ChunkPart::Quoted(char.to_string())),
+        }
+    }
 }
 
-impl InputChunk {
-    fn new(text: String, is_quoted: bool) -> Self {
-        if is_quoted {
-            Self::QuotedText(text)
-        } else {
-            Self::RawText(text)
+/// Displays the chunk as it could have been typed, e.g. to list jobs.
+impl Display for InputChunk {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for part in &self.parts {
+            match part {
+                ChunkPart::Unquoted(text) => write!(f, "{text}")?,
+                ChunkPart::Quoted(text) => write!(f, "'{}'", text.replace('\'', r"'\''"))?,
+                ChunkPart::Parameter(name) => write!(f, "${{{name}}}")?,
+                ChunkPart::ParameterOperation(name, operation) => {
+                    write!(f, "{}", format_parameter_operation(name, operation))?;
+                }
+                ChunkPart::Tilde(user) => write!(f, "~{user}")?,
+                ChunkPart::Command(source) => write!(f, "$({source})")?,
+                ChunkPart::Arithmetic(expression) => write!(f, "$(({expression}))")?,
+                ChunkPart::DoubleQuoted(part) => {
+                    write!(f, "\"{}\"", InputChunk::from((**part).clone()))?;
+                }
+            }
         }
+
+        Ok(())
+    }
+}
+
+impl From<ChunkPart> for InputChunk {
+    fn from(part: ChunkPart) -> Self {
+        Self { parts: vec![part] }
     }
 }
 
 const ESCAPE_CHARACTER: char = '\\';
 const ESCAPABLE_DOUBLE_QUOTED_CHARACTERS: [char; 4] = [DOUBLE_QUOTE, '\\', '$', '\n'];
+const PARAMETER_CHARACTER: char = '$';
+const LENGTH_CHARACTER: char = '#';
+const BACKQUOTE: char = '`';
+const OPENING_PARENTHESIS: char = '(';
+const CLOSING_PARENTHESIS: char = ')';
+const OPENING_BRACE: char = '{';
+const CLOSING_BRACE: char = '}';
 const SINGLE_QUOTE: char = '\'';
+const TILDE: char = '~';
+const COMMAND_SEPARATOR: char = ';';
+const COMMENT_CHARACTER: char = '#';
 const DOUBLE_QUOTE: char = '"';
 const NEWLINE: char = '\n';
+const OPERATOR_CHARACTERS: [char; 4] = [
+    COMMAND_SEPARATOR,
+    NEWLINE,
+    OPENING_PARENTHESIS,
+    CLOSING_PARENTHESIS,
+];
+/// The characters of the control operators, e.g. `&&`, `||`, `&` or `|`.
+const CONTROL_OPERATOR_CHARACTERS: [char; 2] = ['&', '|'];
+/// The characters which end an unquoted tilde prefix, besides whitespace and command separators.
+const TILDE_PREFIX_TERMINATORS: [char; 5] = ['/', '|', '&', '<', '>'];
+const SPECIAL_PARAMETERS: [char; 4] = ['?', '#', '@', '*'];
 
 /// Split the provided string at whitespaces, taking into account single-quoting, double-quoting,
 /// and escaping rules.
 pub(crate) fn chunk_quoted_string(input: &str) -> Result<Vec<InputChunk>, QuotingError> {
     // Split arguments separated by spaces, apart if they are single-quoted.
     let mut split_args = Vec::new();
-    let mut current_arg = String::new();
-    let mut is_quoted_text = false;
+    let mut current_arg = InputChunk::default();
 
     let mut is_within_quotes = false;
     let mut is_within_double_quotes = false;
     let mut is_escaping = false;
 
-    for char in input.chars() {
+    // The byte offset of the last opening quote, to report it if dangling.
+    let mut quote_offset = 0;
+
+    let mut chars = input.chars().peekable();
+    while let Some(char) = chars.next() {
         if is_arg_boundary(char, &current_arg, is_within_quotes, is_escaping) {
             // Split the argument at this character, skipping the character itself.
-            split_args.push(InputChunk::new(current_arg, is_quoted_text));
-            current_arg = String::new();
-            is_quoted_text = false;
+            split_args.push(std::mem::take(&mut current_arg));
         } else if is_escaping {
             if is_within_double_quotes && !ESCAPABLE_DOUBLE_QUOTED_CHARACTERS.contains(&char) {
                 // Push the escape character.
-                current_arg.push(ESCAPE_CHARACTER);
+                current_arg.push_char(ESCAPE_CHARACTER, true);
             }
 
             // Push the current character if not a newline.
             if char != NEWLINE {
-                current_arg.push(char);
+                current_arg.push_char(char, true);
             }
 
             // Disable escape mode.
             is_escaping = false;
         } else if is_double_quoting_toggle(char, is_within_double_quotes, is_within_quotes) {
             // Toggle double-quoted and quoted mode mode.
+            if !is_within_double_quotes {
+                quote_offset = offset_before(input, &chars, char);
+            }
             is_within_double_quotes = !is_within_double_quotes;
             is_within_quotes = !is_within_quotes;
-            is_quoted_text = true;
         } else if is_single_quoting_toggle(char, is_within_double_quotes) {
             // Toggle quoted mode.
+            if !is_within_quotes {
+                quote_offset = offset_before(input, &chars, char);
+            }
             is_within_quotes = !is_within_quotes;
-            is_quoted_text = true;
         } else if is_escaping_toggle(char, is_within_double_quotes, is_within_quotes) {
             // Enable escape mode.
             is_escaping = true;
+        } else if is_comment_start(char, &current_arg, is_within_quotes) {
+            // Discard the rest of the line, keeping the newline as a word boundary.
+            while chars.next_if(|next| *next != NEWLINE).is_some() {}
+        } else if is_command_separator(char, is_within_quotes) {
+            // Keep separators and parentheses apart from the adjacent chunks, even if they aren't
+            // surrounded by spaces, e.g. `echo a; echo b` or `(echo a)`.
+            if !current_arg.is_empty() {
+                split_args.push(std::mem::take(&mut current_arg));
+            }
+            split_args.push(InputChunk::from(ChunkPart::Unquoted(char.to_string())));
+        } else if let Some(operator) =
+            capture_control_operator(char, &mut chars, &current_arg, is_within_quotes)
+        {
+            // Keep control operators apart from the adjacent chunks too, e.g. `echo a&&echo b`.
+            if !current_arg.is_empty() {
+                split_args.push(std::mem::take(&mut current_arg));
+            }
+            split_args.push(InputChunk::from(ChunkPart::Unquoted(operator)));
+        } else if let Some(user) = capture_tilde(char, &mut chars, &current_arg, is_within_quotes) {
+            // Keep tilde prefixes aside, so they get expanded when running the command.
+            current_arg.parts.push(ChunkPart::Tilde(user));
+        } else if let Some(text) = capture_ansi_c_quoted(input, char, &mut chars, is_within_quotes)?
+        {
+            // Decode ANSI-C quoted strings right away, their content being taken literally.
+            current_arg.push_part(ChunkPart::Quoted(text));
+        } else if let Some(substitution) =
+            capture_substitution(char, &mut chars, is_within_double_quotes, is_within_quotes)?
+        {
+            // Keep substitutions aside, so they are evaluated when running the command.
+            current_arg
+                .parts
+                .push(mark_double_quoted(substitution, is_within_double_quotes));
+        } else if let Some(parameter) =
+            capture_parameter(char, &mut chars, is_within_double_quotes, is_within_quotes)?
+        {
+            // Keep parameters aside, so they get expanded when running the command.
+            current_arg
+                .parts
+                .push(mark_double_quoted(parameter, is_within_double_quotes));
         } else if should_capture_char(char, is_within_quotes) {
             // Capture characters.
-            current_arg.push(char);
+            current_arg.push_char(char, is_within_quotes);
         }
     }
 
-    if is_within_quotes {
-        return Err(QuotingError::DanglingQuote);
+    if is_within_double_quotes {
+        return Err(QuotingError::DanglingDoubleQuote(quote_offset));
+    } else if is_within_quotes {
+        return Err(QuotingError::DanglingSingleQuote(quote_offset));
     }
 
     if !current_arg.is_empty() {
-        split_args.push(InputChunk::new(current_arg, is_quoted_text));
+        split_args.push(current_arg);
     }
 
     Ok(split_args)
 }
 
+/// Chunk the body of a here-document, where parameters and command substitutions are interpreted
+/// as within double quotes, but double quotes themselves are kept literally.
+pub(crate) fn chunk_here_document_body(body: &str) -> Result<InputChunk, QuotingError> {
+    let mut chunk = InputChunk::default();
+
+    let mut chars = body.chars().peekable();
+    while let Some(char) = chars.next() {
+        if char == ESCAPE_CHARACTER {
+            // Only `\`, `$`, `` ` `` and newlines can be escaped in the body.
+            match chars.next_if(|next| ['\\', '$', BACKQUOTE, NEWLINE].contains(next)) {
+                Some(NEWLINE) => {}
+                Some(escaped) => chunk.push_char(escaped, true),
+                None => chunk.push_char(char, true),
+            }
+        } else if let Some(substitution) = capture_substitution(char, &mut chars, true, true)? {
+            chunk.parts.push(substitution);
+        } else if let Some(parameter) = capture_parameter(char, &mut chars, true, true)? {
+            chunk.parts.push(parameter);
+        } else {
+            chunk.push_char(char, true);
+        }
+    }
+
+    Ok(chunk)
+}
+
+/// Wraps the expansion if it is within double quotes, so its result isn't split into fields.
+fn mark_double_quoted(part: ChunkPart, is_within_double_quotes: bool) -> ChunkPart {
+    if is_within_double_quotes {
+        ChunkPart::DoubleQuoted(Box::new(part))
+    } else {
+        part
+    }
+}
+
+/// The byte offset in the input of the character which was just consumed from the remaining ones.
+fn offset_before(input: &str, remaining: &Peekable<Chars>, char: char) -> usize {
+    input.len() - remaining.clone().map(char::len_utf8).sum::<usize>() - char.len_utf8()
+}
+
+/// Captures the user name of the tilde prefix starting at the current `~` character, if any.
+///
+/// Only a `~` starting an unquoted word is a tilde prefix, and only if it is followed by an unquoted
+/// user name up to a `/` or the end of the word.
+fn capture_tilde(
+    current_char: char,
+    chars: &mut Peekable<Chars>,
+    current_arg: &InputChunk,
+    is_within_quotes: bool,
+) -> Option<String> {
+    if current_char != TILDE || is_within_quotes || !current_arg.is_empty() {
+        return None;
+    }
+
+    let mut lookahead = chars.clone();
+    let mut user = String::new();
+    while let Some(char) = lookahead.next_if(|next| is_user_name_character(*next)) {
+        user.push(char);
+    }
+
+    match lookahead.peek() {
+        Some(next)
+            if !next.is_whitespace()
+                && !OPERATOR_CHARACTERS.contains(next)
+                && !TILDE_PREFIX_TERMINATORS.contains(next) =>
+        {
+            None
+        }
+        _ => {
+            *chars = lookahead;
+            Some(user)
+        }
+    }
+}
+
+/// Captures the control operator starting at the current character, if any, e.g. `&&` or `|`, unless
+/// it belongs to a redirection, e.g. `2>&1`, `>|` or `&>`.
+fn capture_control_operator(
+    current_char: char,
+    chars: &mut Peekable<Chars>,
+    current_arg: &InputChunk,
+    is_within_quotes: bool,
+) -> Option<String> {
+    let is_redirection = matches!(
+        current_arg.parts().last(),
+        Some(ChunkPart::Unquoted(text)) if text.ends_with(['<', '>'])
+    ) || (current_char == '&' && chars.peek() == Some(&'>'));
+    if is_within_quotes || is_redirection || !CONTROL_OPERATOR_CHARACTERS.contains(&current_char) {
+        return None;
+    }
+
+    // Operators are either single, e.g. `&`, or doubled, e.g. `&&`.
+    let mut operator = current_char.to_string();
+    operator.extend(chars.next_if_eq(&current_char));
+
+    Some(operator)
+}
+
+/// Whether the character can be part of a user name, as allowed by POSIX.
+fn is_user_name_character(char: char) -> bool {
+    char.is_ascii_alphanumeric() || ['.', '_', '-'].contains(&char)
+}
+
+/// Captures and decodes the ANSI-C quoted string starting at the current `$` character, if any,
+/// e.g. `$'a\tb'`.
+fn capture_ansi_c_quoted(
+    input: &str,
+    current_char: char,
+    chars: &mut Peekable<Chars>,
+    is_within_quotes: bool,
+) -> Result<Option<String>, QuotingError> {
+    // Only interpret `$'` outside quotes.
+    if current_char != PARAMETER_CHARACTER
+        || is_within_quotes
+        || chars.next_if_eq(&SINGLE_QUOTE).is_none()
+    {
+        return Ok(None);
+    }
+
+    let offset = offset_before(input, chars, SINGLE_QUOTE);
+    let mut text = String::new();
+    while let Some(char) = chars.next() {
+        match char {
+            SINGLE_QUOTE => return Ok(Some(text)),
+            ESCAPE_CHARACTER => decode_ansi_c_escape(chars, &mut text),
+            _ => text.push(char),
+        }
+    }
+
+    Err(QuotingError::DanglingSingleQuote(offset))
+}
+
+/// Decodes the C-style escape sequence following a backslash, keeping unknown ones as is.
+fn decode_ansi_c_escape(chars: &mut Peekable<Chars>, text: &mut String) {
+    let Some(char) = chars.next() else {
+        text.push(ESCAPE_CHARACTER);
+        return;
+    };
+
+    let decoded = match char {
+        'a' => Some('\x07'),
+        'b' => Some('\x08'),
+        'e' | 'E' => Some('\x1b'),
+        'f' => Some('\x0c'),
+        'n' => Some('\n'),
+        'r' => Some('\r'),
+        't' => Some('\t'),
+        'v' => Some('\x0b'),
+        '\\' | '\'' | '"' | '?' => Some(char),
+        '0'..='7' => decode_code_point(char.to_string(), chars, 8, 2),
+        'x' => decode_code_point(String::new(), chars, 16, 2),
+        'u' => decode_code_point(String::new(), chars, 16, 4),
+        'U' => decode_code_point(String::new(), chars, 16, 8),
+        'c' => chars
+            .next_if(char::is_ascii)
+            .map(|control| char::from(control.to_ascii_uppercase() as u8 ^ 0x40)),
+        _ => None,
+    };
+
+    match decoded {
+        Some(decoded) => text.push(decoded),
+        None => {
+            text.push(ESCAPE_CHARACTER);
+            text.push(char);
+        }
+    }
+}
+
+/// Decodes a character from its code point, reading up to `max_digits` more digits in the radix.
+fn decode_code_point(
+    mut digits: String,
+    chars: &mut Peekable<Chars>,
+    radix: u32,
+    max_digits: usize,
+) -> Option<char> {
+    for _ in 0..max_digits {
+        match chars.next_if(|next| next.is_digit(radix)) {
+            Some(digit) => digits.push(digit),
+            None => break,
+        }
+    }
+
+    u32::from_str_radix(&digits, radix)
+        .ok()
+        .and_then(char::from_u32)
+}
+
+/// Captures the command substitution or arithmetic expansion starting at the current character, if
+/// any.
+fn capture_substitution(
+    current_char: char,
+    chars: &mut Peekable<Chars>,
+    is_within_double_quotes: bool,
+    is_within_quotes: bool,
+) -> Result<Option<ChunkPart>, QuotingError> {
+    // Only interpret substitutions if they are not within a single-quoted string.
+    if is_within_quotes && !is_within_double_quotes {
+        return Ok(None);
+    }
+
+    if current_char == BACKQUOTE {
+        return Ok(Some(ChunkPart::Command(capture_backquoted(chars)?)));
+    }
+
+    if current_char == PARAMETER_CHARACTER && chars.next_if_eq(&OPENING_PARENTHESIS).is_some() {
+        let source = capture_parenthesized(chars)?;
+
+        return Ok(Some(match as_arithmetic_expression(&source) {
+            Some(expression) => ChunkPart::Arithmetic(expression.to_owned()),
+            None => ChunkPart::Command(source),
+        }));
+    }
+
+    Ok(None)
+}
+
+/// Returns the expression of the source if it is wrapped in a second pair of parentheses, i.e.
+/// `$((1 + 2))`, unlike a substitution starting with a subshell, e.g. `$((ls) | (cat))`.
+fn as_arithmetic_expression(source: &str) -> Option<&str> {
+    let inner = source.strip_prefix(OPENING_PARENTHESIS)?;
+
+    let mut depth = 1;
+    for (index, char) in inner.char_indices() {
+        match char {
+            OPENING_PARENTHESIS => depth += 1,
+            CLOSING_PARENTHESIS => depth -= 1,
+            _ => {}
+        }
+
+        if depth == 0 {
+            // The first parenthesis must close at the very end.
+            return (index + 1 == inner.len()).then(|| &inner[..index]);
+        }
+    }
+
+    None
+}
+
+/// Captures the source up to the closing backquote, where backslashes only escape `` ` ``, `\`,
+/// and `$`.
+fn capture_backquoted(chars: &mut Peekable<Chars>) -> Result<String, QuotingError> {
+    let mut source = String::new();
+
+    while let Some(char) = chars.next() {
+        match char {
+            BACKQUOTE => return Ok(source),
+            ESCAPE_CHARACTER => {
+                if let Some(escaped) = chars.next_if(|c| [BACKQUOTE, '\\', '$'].contains(c)) {
+                    source.push(escaped);
+                } else {
+                    source.push(char);
+                }
+            }
+            _ => source.push(char),
+        }
+    }
+
+    Err(QuotingError::UnterminatedSubstitution)
+}
+
+/// Captures the source up to the matching closing parenthesis, skipping over quoted and escaped
+/// parentheses.
+fn capture_parenthesized(chars: &mut Peekable<Chars>) -> Result<String, QuotingError> {
+    let mut source = String::new();
+    let mut depth = 1;
+
+    let mut is_within_single_quotes = false;
+    let mut is_within_double_quotes = false;
+
+    while let Some(char) = chars.next() {
+        let is_within_quotes = is_within_single_quotes || is_within_double_quotes;
+
+        match char {
+            ESCAPE_CHARACTER if !is_within_single_quotes => {
+                // Keep the escape sequence as is, it is interpreted when parsing the source.
+                source.push(char);
+                if let Some(escaped) = chars.next() {
+                    source.push(escaped);
+                }
+                continue;
+            }
+            SINGLE_QUOTE if !is_within_double_quotes => {
+                is_within_single_quotes = !is_within_single_quotes;
+            }
+            DOUBLE_QUOTE if !is_within_single_quotes => {
+                is_within_double_quotes = !is_within_double_quotes;
+            }
+            OPENING_PARENTHESIS if !is_within_quotes => depth += 1,
+            CLOSING_PARENTHESIS if !is_within_quotes => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(source);
+                }
+            }
+            _ => {}
+        }
+
+        source.push(char);
+    }
+
+    Err(QuotingError::UnterminatedSubstitution)
+}
+
+/// Captures the parameter starting at the current `$` character, if any.
+fn capture_parameter(
+    current_char: char,
+    chars: &mut Peekable<Chars>,
+    is_within_double_quotes: bool,
+    is_within_quotes: bool,
+) -> Result<Option<ChunkPart>, QuotingError> {
+    // Only interpret parameters if they are not within a single-quoted string.
+    if current_char != PARAMETER_CHARACTER || (is_within_quotes && !is_within_double_quotes) {
+        return Ok(None);
+    }
+
+    // Capture special parameters, e.g. `$?`, and single-digit positional ones, e.g. `$1`.
+    if let Some(special) =
+        chars.next_if(|next| SPECIAL_PARAMETERS.contains(next) || next.is_ascii_digit())
+    {
+        return Ok(Some(ChunkPart::Parameter(special.to_string())));
+    }
+
+    // Capture braced parameters, e.g. `${HOME}` or `${HOME:-/}`, only consuming the input if the
+    // name and the operation are valid.
+    if chars.peek() == Some(&OPENING_BRACE) {
+        let mut lookahead = chars.clone();
+        lookahead.next();
+
+        // Capture the length of parameters, e.g. `${#HOME}`, unlike the number of arguments `${#}`.
+        let mut after_length = lookahead.clone();
+        let is_length = after_length.next_if_eq(&LENGTH_CHARACTER).is_some()
+            && after_length.peek() != Some(&CLOSING_BRACE);
+        if is_length {
+            lookahead = after_length;
+        }
+
+        let name = match lookahead.next_if(|next| SPECIAL_PARAMETERS.contains(next)) {
+            Some(special) => special.to_string(),
+            None => {
+                let mut name = String::new();
+                while let Some(char) = lookahead.next_if(|next| is_name_character(*next, false)) {
+                    name.push(char);
+                }
+                name
+            }
+        };
+
+        // A `${` which isn't followed by a valid name and a closing brace is taken literally.
+        if !is_parameter_name(&name) {
+            return Ok(None);
+        }
+
+        let parameter = match lookahead.next_if_eq(&CLOSING_BRACE) {
+            Some(_) if is_length => ChunkPart::ParameterOperation(name, ParameterOperation::Length),
+            Some(_) => ChunkPart::Parameter(name),
+            None if is_length => return Ok(None),
+            None => match capture_parameter_operation(&mut lookahead, is_within_double_quotes)? {
+                Some(operation) => ChunkPart::ParameterOperation(name, operation),
+                None => return Ok(None),
+            },
+        };
+
+        *chars = lookahead;
+        return Ok(Some(parameter));
+    }
+
+    // Capture named parameters, e.g. `$HOME`.
+    let mut name = String::new();
+    while let Some(char) = chars.next_if(|next| is_name_character(*next, name.is_empty())) {
+        name.push(char);
+    }
+
+    // A `$` which isn't followed by a parameter name is taken literally.
+    Ok(Some(name)
+        .filter(|name| !name.is_empty())
+        .map(ChunkPart::Parameter))
+}
+
+/// Captures the operation following the name of a braced parameter up to the closing brace, if
+/// valid, e.g. `:-default}`.
+fn capture_parameter_operation(
+    chars: &mut Peekable<Chars>,
+    is_within_double_quotes: bool,
+) -> Result<Option<ParameterOperation>, QuotingError> {
+    // Capture pattern removals, e.g. `${FILE%.txt}`, a doubled operator removing the longest
+    // match. The pattern is matched the same within double quotes, e.g. `"${FILE%.*}"`.
+    if let Some(operator) = chars.next_if(|next| ['#', '%'].contains(next)) {
+        let is_longest = chars.next_if_eq(&operator).is_some();
+        let Some(pattern) = capture_braced_word(chars) else {
+            return Ok(None);
+        };
+        let pattern = chunk_parameter_word(&pattern, false)?;
+
+        return Ok(Some(if operator == '#' {
+            ParameterOperation::RemovePrefix {
+                pattern,
+                is_longest,
+            }
+        } else {
+            ParameterOperation::RemoveSuffix {
+                pattern,
+                is_longest,
+            }
+        }));
+    }
+
+    if chars.next_if_eq(&':').is_none() {
+        return Ok(None);
+    }
+    let Some(operator) = chars.next_if(|next| ['-', '=', '+', '?'].contains(next)) else {
+        // Capture substrings, e.g. `${HOME:1:2}`, negative offsets needing a space or parentheses
+        // not to be taken as defaults, e.g. `${HOME: -1}`.
+        let Some(expressions) = capture_braced_word(chars) else {
+            return Ok(None);
+        };
+        let (offset, length) = match expressions.split_once(':') {
+            Some((offset, length)) => (offset, Some(chunk_parameter_word(length, false)?)),
+            None => (expressions.as_str(), None),
+        };
+
+        let offset = chunk_parameter_word(offset, false)?;
+        return Ok(Some(ParameterOperation::Substring(offset, length)));
+    };
+    let Some(word) = capture_braced_word(chars) else {
+        return Ok(None);
+    };
+    let word = chunk_parameter_word(&word, is_within_double_quotes)?;
+
+    Ok(Some(match operator {
+        '-' => ParameterOperation::Default(word),
+        '=' => ParameterOperation::Assign(word),
+        '+' => ParameterOperation::Alternative(word),
+        _ => ParameterOperation::Error(word),
+    }))
+}
+
+/// Captures the word of a braced parameter up to the matching closing brace, skipping over quoted
+/// and escaped braces, or returns `None` if the brace isn't closed.
+fn capture_braced_word(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut word = String::new();
+    let mut depth = 0;
+
+    // Single quotes are matched even if the whole parameter is within double quotes, like bash
+    // does, although they are taken literally then.
+    let mut is_within_single_quotes = false;
+    let mut is_within_inner_double_quotes = false;
+
+    while let Some(char) = chars.next() {
+        let is_within_quotes = is_within_single_quotes || is_within_inner_double_quotes;
+
+        match char {
+            ESCAPE_CHARACTER if !is_within_single_quotes => {
+                // Keep the escape sequence as is, it is interpreted when chunking the word.
+                word.push(char);
+                word.extend(chars.next());
+                continue;
+            }
+            SINGLE_QUOTE if !is_within_inner_double_quotes => {
+                is_within_single_quotes = !is_within_single_quotes;
+            }
+            DOUBLE_QUOTE if !is_within_single_quotes => {
+                is_within_inner_double_quotes = !is_within_inner_double_quotes;
+            }
+            OPENING_BRACE if !is_within_quotes => depth += 1,
+            CLOSING_BRACE if !is_within_quotes => {
+                if depth == 0 {
+                    return Some(word);
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+
+        word.push(char);
+    }
+
+    None
+}
+
+/// Chunks the word of a parameter operation into a single chunk, e.g. `a "b c"` for
+/// `${X:-a "b c"}`, interpreting its quotes, escapes and expansions like the rest of the input.
+fn chunk_parameter_word(
+    word: &str,
+    is_within_double_quotes: bool,
+) -> Result<InputChunk, QuotingError> {
+    let mut chunk = InputChunk::default();
+
+    let mut is_within_single_quotes = false;
+    let mut is_within_double_quotes = is_within_double_quotes;
+
+    let mut chars = word.chars().peekable();
+    while let Some(char) = chars.next() {
+        let is_within_quotes = is_within_single_quotes || is_within_double_quotes;
+
+        if is_within_single_quotes {
+            if char == SINGLE_QUOTE {
+                is_within_single_quotes = false;
+            } else {
+                chunk.push_char(char, true);
+            }
+        } else if char == ESCAPE_CHARACTER {
+            match chars.next() {
+                Some(NEWLINE) => {}
+                Some(escaped) => {
+                    if is_within_double_quotes
+                        && !ESCAPABLE_DOUBLE_QUOTED_CHARACTERS.contains(&escaped)
+                    {
+                        chunk.push_char(ESCAPE_CHARACTER, true);
+                    }
+                    chunk.push_char(escaped, true);
+                }
+                None => chunk.push_char(char, true),
+            }
+        } else if char == SINGLE_QUOTE && !is_within_double_quotes {
+            is_within_single_quotes = true;
+        } else if char == DOUBLE_QUOTE {
+            is_within_double_quotes = !is_within_double_quotes;
+        } else if let Some(substitution) =
+            capture_substitution(char, &mut chars, is_within_double_quotes, is_within_quotes)?
+        {
+            chunk
+                .parts
+                .push(mark_double_quoted(substitution, is_within_double_quotes));
+        } else if let Some(parameter) =
+            capture_parameter(char, &mut chars, is_within_double_quotes, is_within_quotes)?
+        {
+            chunk
+                .parts
+                .push(mark_double_quoted(parameter, is_within_double_quotes));
+        } else if let Some(user) = capture_tilde(char, &mut chars, &chunk, is_within_quotes) {
+            chunk.parts.push(ChunkPart::Tilde(user));
+        } else {
+            chunk.push_char(char, is_within_quotes);
+        }
+    }
+
+    Ok(chunk)
+}
+
+/// Whether the text is a valid parameter name, either special, positional or a variable name.
+fn is_parameter_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    match (chars.next(), chars.next()) {
+        (Some(char), None) if SPECIAL_PARAMETERS.contains(&char) => true,
+        _ => is_positional_parameter(name) || is_variable_name(name),
+    }
+}
+
+/// Whether the parameter name is the number of a positional parameter, e.g. `0` or `12`.
+pub(crate) fn is_positional_parameter(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|char| char.is_ascii_digit())
+}
+
+/// Whether the text is a valid variable name, made of name characters.
+pub(crate) fn is_variable_name(name: &str) -> bool {
+    name.chars()
+        .enumerate()
+        .all(|(index, char)| is_name_character(char, index == 0))
+        && !name.is_empty()
+}
+
+/// Whether the character can be part of a parameter name, names cannot start with a digit.
+fn is_name_character(char: char, is_first: bool) -> bool {
+    char == '_' || char.is_ascii_alphabetic() || (!is_first && char.is_ascii_digit())
+}
+
 fn should_capture_char(current_char: char, is_within_quotes: bool) -> bool {
     // Skip whitespaces outside quoted strings.
     is_within_quotes || !current_char.is_whitespace()
@@ -121,19 +952,36 @@ fn is_double_quoting_toggle(
     (!is_within_quotes || is_within_double_quotes) && current_char == DOUBLE_QUOTE
 }
 
+fn is_command_separator(current_char: char, is_within_quotes: bool) -> bool {
+    // Only interpret semicolons, newlines and parentheses if they are not quoted.
+    !is_within_quotes && OPERATOR_CHARACTERS.contains(&current_char)
+}
+
+fn is_comment_start(current_char: char, current_arg: &InputChunk, is_within_quotes: bool) -> bool {
+    // Only interpret hashes starting an unquoted word, e.g. not `a#b`.
+    !is_within_quotes && current_char == COMMENT_CHARACTER && current_arg.is_empty()
+}
+
 fn is_arg_boundary(
     current_char: char,
-    current_arg: &str,
+    current_arg: &InputChunk,
     is_within_quotes: bool,
     is_escaping: bool,
 ) -> bool {
-    // Break at whitespaces when not within quotes, and the whitespace is not being escaped.
-    !is_escaping && !is_within_quotes && current_char.is_whitespace() && !current_arg.is_empty()
+    // Break at whitespaces when not within quotes, and the whitespace is not being escaped. Newlines
+    // are separators rather than mere boundaries.
+    !is_escaping
+        && !is_within_quotes
+        && current_char.is_whitespace()
+        && current_char != NEWLINE
+        && !current_arg.is_empty()
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{chunk_quoted_string, InputChunk, QuotingError};
+    use super::{
+        chunk_quoted_string, format_parameter_operation, ChunkPart, InputChunk, QuotingError,
+    };
 
     trait VecDisplay {
         fn display(&self) -> Vec<String>;
@@ -142,9 +990,30 @@ mod tests {
     impl VecDisplay for Vec<InputChunk> {
         fn display(&self) -> Vec<String> {
             self.iter()
-                .map(|chunk| match chunk {
-                    InputChunk::RawText(text) => text.clone(),
-                    InputChunk::QuotedText(text) => format!("[[{}]]", text.clone()),
+                .map(|chunk| {
+                    let text: String = chunk
+                        .parts()
+                        .iter()
+                        .map(|part| match part {
+                            ChunkPart::Unquoted(text) | ChunkPart::Quoted(text) => text.clone(),
+                            ChunkPart::Parameter(name) => format!("${{{name}}}"),
+                            ChunkPart::ParameterOperation(name, operation) => {
+                                format_parameter_operation(name, operation)
+                            }
+                            ChunkPart::Tilde(user) => format!("~{{{user}}}"),
+                            ChunkPart::Command(source) => format!("$({source})"),
+                            ChunkPart::Arithmetic(expression) => format!("$(({expression}))"),
+                            ChunkPart::DoubleQuoted(part) => {
+                                format!("\"{}\"", InputChunk::from((**part).clone()))
+                            }
+                        })
+                        .collect();
+
+                    if chunk.as_unquoted().is_some() {
+                        text
+                    } else {
+                        format!("[[{text}]]")
+                    }
                 })
                 .collect()
         }
@@ -187,10 +1056,14 @@ mod tests {
             chunk_quoted_string("hello wo'rld 'oh").unwrap().display()
         );
 
-        // Error on dangling single-quoted string.
+        // Error on dangling single-quoted string, at the opening quote.
         assert!(matches!(
             chunk_quoted_string("hello 'world"),
-            Err(QuotingError::DanglingQuote)
+            Err(QuotingError::DanglingSingleQuote(6))
+        ));
+        assert!(matches!(
+            chunk_quoted_string(r#"'a' "é" 'b"#),
+            Err(QuotingError::DanglingSingleQuote(9))
         ));
     }
 
@@ -229,6 +1102,12 @@ mod tests {
                 .unwrap()
                 .display()
         );
+
+        // Error on dangling double-quoted string, at the opening quote.
+        assert!(matches!(
+            chunk_quoted_string(r#"hello "wor'ld"#),
+            Err(QuotingError::DanglingDoubleQuote(6))
+        ));
     }
 
     #[test]
@@ -313,9 +1192,9 @@ world""#
 
     #[test]
     fn it_handles_escaping_outside_double_quotes() {
-        // Escape whitespace.
+        // Escape whitespace, considering escaped characters as quoted.
         assert_eq!(
-            vec!["hello   world"],
+            vec!["[[hello   world]]"],
             chunk_quoted_string(r#"hello\ \ \ world"#)
                 .unwrap()
                 .display()
@@ -323,13 +1202,13 @@ world""#
 
         // Escape single-quoting.
         assert_eq!(
-            vec!["hello", "'world'"],
+            vec!["hello", "[['world']]"],
             chunk_quoted_string(r#"hello \'world\'"#).unwrap().display()
         );
 
         // Escape double-quoting.
         assert_eq!(
-            vec!["hello", r#""world""#],
+            vec!["hello", r#"[["world"]]"#],
             chunk_quoted_string(r#"hello \"world\""#).unwrap().display()
         );
 
@@ -346,14 +1225,333 @@ the world"#
 
         // Escape backslash.
         assert_eq!(
-            vec![r#"he\\o"#, r#"wor\d"#],
+            vec![r#"[[he\\o]]"#, r#"[[wor\d]]"#],
             chunk_quoted_string(r#"he\\\\o wor\\d"#).unwrap().display()
         );
 
         // Does NOT print the backslash when not escaping itself.
         assert_eq!(
-            vec!["heo", "word"],
+            vec!["[[heo]]", "[[word]]"],
             chunk_quoted_string(r#"he\o wor\d"#).unwrap().display()
         );
     }
+
+    #[test]
+    fn it_captures_parameters_outside_single_quotes() {
+        // Capture parameters outside quotes and within double-quotes.
+        assert_eq!(
+            vec!["echo", "[[${?}]]", r#"[[status: "${?}"]]"#],
+            chunk_quoted_string(r#"echo $? "status: $?""#)
+                .unwrap()
+                .display()
+        );
+
+        // Capture the positional parameters as a whole, and their count.
+        assert_eq!(
+            vec!["[[${#}]]", r#"[["${@}"]]"#, "[[${*}]]"],
+            chunk_quoted_string(r#"$# "$@" $*"#).unwrap().display()
+        );
+
+        // Take parameters literally within single-quotes, or when escaped.
+        assert_eq!(
+            vec!["echo", "[[$?]]", "[[$?]]"],
+            chunk_quoted_string(r#"echo '$?' \$?"#).unwrap().display()
+        );
+
+        // Take a `$` literally if it isn't followed by a parameter name.
+        assert_eq!(
+            vec!["echo", "$", "[[$ ]]"],
+            chunk_quoted_string(r#"echo $ "$ ""#).unwrap().display()
+        );
+    }
+
+    #[test]
+    fn it_captures_named_parameters() {
+        // Capture plain and braced names.
+        assert_eq!(
+            vec!["echo", "[[${HOME}]]", "[[${HOME}]]", "[[${USER}_name]]"],
+            chunk_quoted_string(r#"echo $HOME ${HOME} ${USER}_name"#)
+                .unwrap()
+                .display()
+        );
+
+        // Stop plain names at the first non-name character.
+        assert_eq!(
+            vec!["[[${HOME}/dir]]", r#"[["${A_1}"-"${B}"]]"#],
+            chunk_quoted_string(r#"$HOME/dir "$A_1-$B""#)
+                .unwrap()
+                .display()
+        );
+
+        // Capture single digits, or braced numbers.
+        assert_eq!(
+            vec!["[[${1}abc]]", "[[${12}]]", "[[${0}]]"],
+            chunk_quoted_string(r#"$1abc ${12} $0"#).unwrap().display()
+        );
+
+        // Take empty or invalid names literally.
+        assert_eq!(
+            vec!["${}", "${1abc}", "${HOME", "$-"],
+            chunk_quoted_string(r#"${} ${1abc} ${HOME $-"#)
+                .unwrap()
+                .display()
+        );
+    }
+
+    #[test]
+    fn it_captures_parameter_operations() {
+        // Capture the word of each operation, up to the matching closing brace.
+        assert_eq!(
+            vec![
+                "[[${A:-a b}]]",
+                "[[${A:=}]]",
+                "[[${1:+{x}}]]",
+                "[[${?:?oops}]]"
+            ],
+            chunk_quoted_string(r#"${A:-a b} ${A:=} ${1:+{x}} ${?:?oops}"#)
+                .unwrap()
+                .display()
+        );
+
+        // Interpret quotes, escapes and expansions within the word.
+        assert_eq!(
+            vec!["[[${A:-'b }$'${B:-~}$(c)}]]", r#"[["${A:-''\''}'\'''}"]]"#],
+            chunk_quoted_string(r#"${A:-"b }"\$${B:-~}$(c)} "${A:-'}'}""#)
+                .unwrap()
+                .display()
+        );
+
+        // Capture lengths, unlike the number of arguments, and substrings.
+        assert_eq!(
+            vec![
+                "[[${#A}]]",
+                "[[${#}]]",
+                "[[${#1}]]",
+                "[[${A: -1}]]",
+                "[[${A:${B}:(1)}]]"
+            ],
+            chunk_quoted_string(r#"${#A} ${#} ${#1} ${A: -1} ${A:$B:(1)}"#)
+                .unwrap()
+                .display()
+        );
+
+        // Capture pattern removals, whose patterns are the same within double quotes.
+        assert_eq!(
+            vec![
+                "[[${A#*.}]]",
+                "[[${A##*.}]]",
+                r#"[["${A%.*}"]]"#,
+                "[[${A%%'*'}]]"
+            ],
+            chunk_quoted_string(r#"${A#*.} ${A##*.} "${A%.*}" ${A%%"*"}"#)
+                .unwrap()
+                .display()
+        );
+
+        // Take unknown operations or unterminated words literally.
+        assert_eq!(
+            vec!["${A-a}", "${#A:-a}", "[[${A:-a]]"],
+            chunk_quoted_string(r#"${A-a} ${#A:-a} "${A:-a""#)
+                .unwrap()
+                .display()
+        );
+    }
+
+    #[test]
+    fn it_captures_command_substitutions() {
+        // Capture the source, without splitting it at whitespaces.
+        assert_eq!(
+            vec!["echo", r#"[[$(echo "a b" | tr a-z A-Z)]]"#, "x"],
+            chunk_quoted_string(r#"echo $(echo "a b" | tr a-z A-Z) x"#)
+                .unwrap()
+                .display()
+        );
+
+        // Capture nested substitutions and quoted parentheses as part of the source.
+        assert_eq!(
+            vec![r#"[[dir: "$(basename $(pwd) ')')"]]"#],
+            chunk_quoted_string(r#""dir: $(basename $(pwd) ')')""#)
+                .unwrap()
+                .display()
+        );
+
+        // Capture backquoted substitutions, unescaping backquotes.
+        assert_eq!(
+            vec!["echo", "[[$(echo `pwd`)]]"],
+            chunk_quoted_string(r#"echo `echo \`pwd\``"#)
+                .unwrap()
+                .display()
+        );
+
+        // Take substitutions literally within single-quotes.
+        assert_eq!(
+            vec!["echo", "[[$(pwd) `pwd`]]"],
+            chunk_quoted_string(r#"echo '$(pwd) `pwd`'"#)
+                .unwrap()
+                .display()
+        );
+
+        // Error on unterminated substitutions.
+        assert!(matches!(
+            chunk_quoted_string("echo $(pwd"),
+            Err(QuotingError::UnterminatedSubstitution)
+        ));
+        assert!(matches!(
+            chunk_quoted_string("echo `pwd"),
+            Err(QuotingError::UnterminatedSubstitution)
+        ));
+    }
+
+    #[test]
+    fn it_captures_arithmetic_expansions() {
+        assert_eq!(
+            vec!["echo", "[[$(( 1 + (2 * x) ))]]", r#"[[a"$((1))"]]"#],
+            chunk_quoted_string(r#"echo $(( 1 + (2 * x) )) "a$((1))""#)
+                .unwrap()
+                .display()
+        );
+
+        // Substitutions starting with a subshell are not arithmetic expansions.
+        assert_eq!(
+            vec!["[[$((ls) | (cat))]]"],
+            chunk_quoted_string("$((ls) | (cat))").unwrap().display()
+        );
+        assert!(matches!(
+            chunk_quoted_string("$((ls) | (cat))").unwrap()[0].parts(),
+            [ChunkPart::Command(_)]
+        ));
+    }
+
+    #[test]
+    fn it_captures_tilde_prefixes() {
+        // Capture a tilde starting a word, with an optional user name up to a slash.
+        assert_eq!(
+            vec!["[[~{}]]", "[[~{}/file]]", "[[~{root}]]", "[[~{root}/file]]"],
+            chunk_quoted_string("~ ~/file ~root ~root/file")
+                .unwrap()
+                .display()
+        );
+
+        // End the prefix at operators as well.
+        assert_eq!(
+            vec![
+                "echo",
+                "[[~{}]]",
+                ";",
+                "(",
+                "[[~{}]]",
+                ")",
+                "[[~{}>f]]",
+                "[[~{}]]",
+                "|",
+                "[[~{}]]",
+                "&&"
+            ],
+            chunk_quoted_string("echo ~; (~) ~>f ~|~&&")
+                .unwrap()
+                .display()
+        );
+
+        // Take tildes literally within words, within quotes, or if escaped.
+        assert_eq!(
+            vec!["a~b", "[[~]]", "[[~/file]]", "[[~]]", "[[~root]]"],
+            chunk_quoted_string(r#"a~b '~' "~/file" \~ ~"root""#)
+                .unwrap()
+                .display()
+        );
+    }
+
+    #[test]
+    fn it_splits_command_separators() {
+        // Split unquoted semicolons into their own chunk, even if not surrounded by spaces.
+        assert_eq!(
+            vec!["echo", "a", ";", "echo", "b", ";"],
+            chunk_quoted_string("echo a; echo b;").unwrap().display()
+        );
+
+        // Likewise for newlines and parentheses.
+        assert_eq!(
+            vec!["(", "echo", "a", ")", "\n", "echo", "b", "\n"],
+            chunk_quoted_string("(echo a)\necho b\n").unwrap().display()
+        );
+
+        // Likewise for control operators, but not within redirections.
+        assert_eq!(
+            vec!["a", "&&", "b", "||", "c", "|", "d", "2>&1", ">|", "e", "&"],
+            chunk_quoted_string("a&&b||c|d 2>&1 >| e&")
+                .unwrap()
+                .display()
+        );
+        assert_eq!(
+            vec!["echo", "a", "&>", "f", ";", "echo", "a", "&>>", "f"],
+            chunk_quoted_string("echo a &> f; echo a &>> f")
+                .unwrap()
+                .display()
+        );
+
+        // Take quoted and escaped semicolons literally.
+        assert_eq!(
+            vec!["echo", "[[a;b]]", "[[;]]", "[[a;]]"],
+            chunk_quoted_string(r#"echo 'a;b' \; "a;""#)
+                .unwrap()
+                .display()
+        );
+    }
+
+    #[test]
+    fn it_skips_comments() {
+        // Discard the rest of the line after a hash starting a word.
+        assert_eq!(
+            vec!["echo", "hi", "\n", "echo", "b"],
+            chunk_quoted_string("echo hi # it's a comment\necho b #")
+                .unwrap()
+                .display()
+        );
+        assert_eq!(
+            vec!["echo", "a", ";"],
+            chunk_quoted_string("echo a;# comment").unwrap().display()
+        );
+
+        // Take quoted, escaped, and mid-word hashes literally.
+        assert_eq!(
+            vec!["echo", "a#b", "[[#]]", "[[#]]", "[[# c]]"],
+            chunk_quoted_string(r##"echo a#b \# '#' "# c""##)
+                .unwrap()
+                .display()
+        );
+    }
+
+    #[test]
+    fn it_decodes_ansi_c_quoted_strings() {
+        // Decode C-style escapes, merging with adjacent quoted text.
+        assert_eq!(
+            vec![
+                "echo",
+                "[[a\tb\n]]",
+                "[[A\u{e9}\u{1F600}'\\]]",
+                "[[x\x01y\\q]]"
+            ],
+            chunk_quoted_string(r"echo $'a\tb\n' $'\x41é\U0001F600\'\\' x$'\cay\q'")
+                .unwrap()
+                .display()
+        );
+        assert_eq!(
+            vec!["[[\x1b[0m a]]", "[[\x070]]"],
+            chunk_quoted_string(r#"$'\e[0m '"a" $'\0070'"#)
+                .unwrap()
+                .display()
+        );
+
+        // Take `$'` literally within quotes, and leave other parameters unaffected.
+        assert_eq!(
+            vec!["[[$'\\t']]", "[[${HOME}]]", "$"],
+            chunk_quoted_string(r#""$'\t'" $HOME $"#).unwrap().display()
+        );
+
+        // Error on dangling ANSI-C quoted string.
+        assert!(matches!(
+            chunk_quoted_string(r"echo $'a\'"),
+            Err(QuotingError::DanglingSingleQuote(6))
+        ));
+    }
 }
@@ -1,106 +1,444 @@
+use crate::parser::here_documents;
 use crate::parser::quoting::InputChunk;
-use crate::parser::{Command, Descriptor, Redirect, RedirectTo};
+use crate::parser::{
+    AndOrList, Assignment, Command, CompoundCommand, Descriptor, ListOperator, Redirect,
+    RedirectMode, RedirectTo,
+};
+use crate::state::MAX_NESTING_DEPTH;
 use regex::Regex;
+use std::collections::VecDeque;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub(crate) enum SplittingError {
-    #[error("Expected program, got: {0}")]
+    #[error("syntax error near unexpected token `{0}'")]
     ProgramExpected(String),
 
-    #[error("Dangling pipe, the command is not terminated")]
+    #[error("syntax error: unexpected end of file after `|'")]
     DanglingPipe,
 
-    #[error("Missing redirect destination")]
-    MissingRedirectDestination,
+    /// The token found instead of the destination, `newline` at the end of the command.
+    #[error("syntax error near unexpected token `{0}'")]
+    MissingRedirectDestination(String),
+
+    #[error("syntax error: unexpected end of file after `{0}'")]
+    DanglingListOperator(String),
+
+    #[error("{0}: Bad file descriptor")]
+    InvalidDescriptor(String),
+
+    #[error("Missing here-document body")]
+    MissingHereDocument,
+
+    #[error("Only single pipelines can run in the background")]
+    UnsupportedBackgroundList,
+
+    #[error("syntax error: unexpected end of file, missing `{0}'")]
+    UnterminatedGroup(String),
+
+    #[error("syntax error near unexpected token `{0}'")]
+    UnexpectedToken(String),
+
+    #[error("syntax error: groups nested too deeply")]
+    NestedTooDeeply,
+}
+
+/// How syntax errors name the end of the command, like bash.
+const NEWLINE_TOKEN: &str = "newline";
+
+/// The unquoted chunks after which a new command starts, where braces are reserved words.
+const COMMAND_STARTS: [&str; 10] = [";", "&", "|", "&&", "||", "\n", "(", ")", "{", "}"];
+
+/// Tracks how deep the chunks are nested within groups, e.g. `(cd /tmp; ls)` or `{ cd /tmp; ls; }`,
+/// so their separators and operators are left for the group to split.
+struct Nesting {
+    /// The words closing the open groups, from the outermost one.
+    closings: Vec<&'static str>,
+
+    /// Whether the next chunk starts a command, braces only delimiting groups there.
+    is_command_start: bool,
+}
+
+impl Default for Nesting {
+    fn default() -> Self {
+        Self::within(vec![])
+    }
+}
+
+impl Nesting {
+    fn within(closings: Vec<&'static str>) -> Self {
+        Self {
+            closings,
+            is_command_start: true,
+        }
+    }
+
+    /// Updates the open groups with the chunk, returning whether it belongs to a group, including
+    /// the words delimiting the group.
+    fn enter(&mut self, chunk: &InputChunk) -> bool {
+        let is_nested = !self.closings.is_empty();
+        let text = chunk.as_unquoted();
+
+        let is_delimiter = match text {
+            Some("(") => {
+                self.closings.push(")");
+                true
+            }
+            Some("{") if self.is_command_start => {
+                self.closings.push("}");
+                true
+            }
+            Some(closing @ (")" | "}")) if self.closings.last() == Some(&closing) => {
+                // Braces only close groups at the start of a command, e.g. after `;`.
+                let is_closing = closing == ")" || self.is_command_start;
+                if is_closing {
+                    self.closings.pop();
+                }
+                is_closing
+            }
+            _ => false,
+        };
+
+        self.is_command_start = text.is_some_and(|text| {
+            COMMAND_STARTS.contains(&text) && (is_delimiter || !["{", "}"].contains(&text))
+        });
+
+        is_nested || is_delimiter
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closings.is_empty()
+    }
+}
+
+/// Counts the groups which aren't closed yet, e.g. 1 for `(echo a` or `{ echo a;`.
+pub(crate) fn count_open_groups(chunks: &[InputChunk]) -> usize {
+    let mut nesting = Nesting::default();
+    for chunk in chunks {
+        nesting.enter(chunk);
+    }
+
+    nesting.closings.len()
+}
+
+/// Parses the input string into lists of pipelines separated by `;` or `&`, which run one after
+/// the other, or in the background for the latter.
+///
+/// The bodies of the here-documents are given in the order of their operators.
+pub(crate) fn split_lists(
+    chunks: Vec<InputChunk>,
+    here_documents: Vec<InputChunk>,
+) -> Result<Vec<AndOrList>, SplittingError> {
+    // Fail cleanly rather than overflowing the stack when splitting deeply nested groups.
+    let mut nesting = Nesting::default();
+    for chunk in &chunks {
+        nesting.enter(chunk);
+        if nesting.closings.len() > MAX_NESTING_DEPTH {
+            return Err(SplittingError::NestedTooDeeply);
+        }
+    }
+
+    split_nested_lists(chunks, &mut VecDeque::from(here_documents))
+}
+
+/// Parses the lists like [`split_lists`], taking the bodies of their here-documents from the queue,
+/// e.g. for the lists of a group.
+fn split_nested_lists(
+    chunks: Vec<InputChunk>,
+    here_documents: &mut VecDeque<InputChunk>,
+) -> Result<Vec<AndOrList>, SplittingError> {
+    // The chunks of each list, along with the separator terminating it, if any.
+    let mut segments: Vec<(Vec<InputChunk>, Option<String>)> = vec![(vec![], None)];
+    let mut nesting = Nesting::default();
+    for chunk in chunks {
+        // The separators within groups are split along with the group.
+        if nesting.enter(&chunk) {
+            if let Some((segment, _)) = segments.last_mut() {
+                segment.push(chunk);
+            }
+            continue;
+        }
+
+        // Only unquoted chunks can be separators.
+        match chunk.as_unquoted() {
+            Some(separator @ (";" | "&")) => {
+                if let Some((_, terminator)) = segments.last_mut() {
+                    *terminator = Some(separator.to_owned());
+                }
+                segments.push((vec![], None));
+            }
+            Some("\n") => {
+                // Newlines end lists like `;`, apart from empty lines and lines expecting more,
+                // e.g. ending with `&&`.
+                let Some((segment, terminator)) = segments.last_mut() else {
+                    continue;
+                };
+                let expects_more = segment
+                    .last()
+                    .and_then(InputChunk::as_unquoted)
+                    .is_some_and(|text| ["|", "&&", "||"].contains(&text));
+                if !segment.is_empty() && !expects_more {
+                    *terminator = Some(";".to_owned());
+                    segments.push((vec![], None));
+                }
+            }
+            _ => {
+                if let Some((segment, _)) = segments.last_mut() {
+                    segment.push(chunk);
+                }
+            }
+        }
+    }
+
+    let segments_count = segments.len();
+    let mut lists = vec![];
+    for (index, (segment, terminator)) in segments.into_iter().enumerate() {
+        match split_and_or_list(segment, here_documents)? {
+            Some(mut list) => {
+                list.background = terminator.as_deref() == Some("&");
+                if list.background && !list.rest.is_empty() {
+                    return Err(SplittingError::UnsupportedBackgroundList);
+                }
+
+                lists.push(list);
+            }
+            // A trailing separator is a no-op.
+            None if index + 1 == segments_count => {}
+            None => {
+                return Err(SplittingError::ProgramExpected(
+                    terminator.unwrap_or_default(),
+                ))
+            }
+        }
+    }
+
+    Ok(lists)
+}
+
+/// Parses the input string into pipelines chained with `&&` and `||`.
+fn split_and_or_list(
+    chunks: Vec<InputChunk>,
+    here_documents: &mut VecDeque<InputChunk>,
+) -> Result<Option<AndOrList>, SplittingError> {
+    if chunks.is_empty() {
+        return Ok(None);
+    }
+
+    let mut pipelines = vec![];
+    let mut operators = vec![];
+    let mut current_chunks = vec![];
+
+    let mut nesting = Nesting::default();
+    for chunk in chunks {
+        // Only unquoted chunks outside of groups can be list operators.
+        let operator = match chunk.as_unquoted().filter(|_| !nesting.enter(&chunk)) {
+            Some("&&") => ListOperator::And,
+            Some("||") => ListOperator::Or,
+            _ => {
+                current_chunks.push(chunk);
+                continue;
+            }
+        };
+
+        if current_chunks.is_empty() {
+            return Err(SplittingError::ProgramExpected(operator.to_string()));
+        }
+
+        pipelines.push(split_commands(
+            std::mem::take(&mut current_chunks),
+            here_documents,
+        )?);
+        operators.push(operator);
+    }
+
+    if let Some(operator) = operators.last().filter(|_| current_chunks.is_empty()) {
+        return Err(SplittingError::DanglingListOperator(operator.to_string()));
+    }
+    pipelines.push(split_commands(current_chunks, here_documents)?);
+
+    let mut pipelines = pipelines.into_iter();
+    let first = pipelines.next().expect("there is at least one pipeline");
+
+    Ok(Some(AndOrList {
+        first,
+        rest: operators.into_iter().zip(pipelines).collect(),
+        background: false,
+    }))
 }
 
 /// Parses the input string into a list of commands piped into each other.
-pub(crate) fn split_commands(chunks: Vec<InputChunk>) -> Result<Vec<Command>, SplittingError> {
+pub(crate) fn split_commands(
+    chunks: Vec<InputChunk>,
+    here_documents: &mut VecDeque<InputChunk>,
+) -> Result<Vec<Command>, SplittingError> {
     if chunks.is_empty() {
         return Ok(vec![]);
     }
 
-    let redirection_regex = Regex::new(r"^(?<from>\d+)?>(?<append>>)?(?<to>&\d+)?$").unwrap();
+    let redirection_regex = Regex::new(
+        r"^(?<from>\d+)?(?:(?<string><<<)|(?:(?<read><)|>(?:(?<append>>)|(?<clobber>\|))?)(?<to>&(?:\d+|-))?)$",
+    )
+    .unwrap();
 
     let mut commands = vec![];
 
-    let mut current_program: Option<String> = None;
-    let mut current_args: Vec<String> = vec![];
+    let mut current_assignments: Vec<Assignment> = vec![];
+    let mut current_program: Option<InputChunk> = None;
+    let mut current_compound: Option<CompoundCommand> = None;
+    let mut current_args: Vec<InputChunk> = vec![];
     let mut current_redirections: Vec<Redirect> = vec![];
 
     let mut iter = chunks.into_iter();
-    while let Some(value) = iter.next() {
-        match value {
-            InputChunk::QuotedText(text) => {
-                if current_program.is_none() {
-                    current_program = Some(text);
-                } else {
-                    current_args.push(text);
-                }
+    while let Some(chunk) = iter.next() {
+        // Only unquoted chunks can be pipes or redirections.
+        let operator = chunk
+            .as_unquoted()
+            .map(ToOwned::to_owned)
+            .unwrap_or_default();
+        let redirection = redirection_regex.captures(&operator);
+        let has_program = current_program.is_some() || current_compound.is_some();
+
+        // End the current command and start parsing the next one.
+        if operator == "|" {
+            if has_program || !current_assignments.is_empty() {
+                commands.push(Command::new(
+                    std::mem::take(&mut current_assignments),
+                    current_program.take(),
+                    current_compound.take(),
+                    std::mem::take(&mut current_args),
+                    std::mem::take(&mut current_redirections),
+                ));
+            } else {
+                return Err(SplittingError::ProgramExpected(operator));
             }
-            InputChunk::RawText(text) => {
-                // End the current command and start parsing the next one.
-                if text == "|" {
-                    if let Some(program) = current_program {
-                        commands.push(Command::new(program, current_args, current_redirections));
-
-                        current_program = None;
-                        current_args = vec![];
-                        current_redirections = vec![];
-                    } else {
-                        return Err(SplittingError::ProgramExpected(text));
-                    }
-                } else if let Some(groups) = redirection_regex.captures(&text) {
-                    if current_program.is_none() {
-                        return Err(SplittingError::ProgramExpected(text));
-                    }
-
-                    let descriptor_id: u8 = groups
-                        .name("from")
-                        // Safe to unwrap as the regex only matches digits.
-                        .map_or(1, |m| m.as_str().parse().unwrap());
-
-                    let append = groups.name("append").is_some();
-
-                    let destination = if let Some(descriptor) = groups.name("to") {
-                        // Safe to unwrap as the regex only matches digits.
-                        let descriptor_id: u8 = descriptor.as_str()[1..].parse().unwrap();
-                        RedirectTo::Descriptor(Descriptor(descriptor_id))
-                    } else {
-                        let filename = match iter
-                            .next()
-                            .ok_or(SplittingError::MissingRedirectDestination)?
-                        {
-                            InputChunk::QuotedText(text) => text,
-                            InputChunk::RawText(text) => {
-                                if text == "|" || redirection_regex.is_match(&text) {
-                                    return Err(SplittingError::MissingRedirectDestination);
-                                }
-
-                                text
-                            }
-                        };
-
-                        RedirectTo::File(filename)
-                    };
-
-                    current_redirections.push(Redirect {
-                        from: Descriptor(descriptor_id),
-                        append,
-                        to: destination,
-                    });
-                } else if current_program.is_none() {
-                    current_program = Some(text);
-                } else {
-                    current_args.push(text);
-                }
+        } else if operator == "("
+            || (operator == "{" && !has_program && current_assignments.is_empty())
+        {
+            // Groups replace the program, and are split on their own.
+            if has_program || !current_assignments.is_empty() {
+                return Err(SplittingError::UnexpectedToken(operator));
+            }
+
+            let closing = if operator == "(" { ")" } else { "}" };
+            let lists = split_nested_lists(take_group(&mut iter, closing)?, here_documents)?;
+            if lists.is_empty() {
+                return Err(SplittingError::ProgramExpected(closing.to_owned()));
             }
+
+            current_compound = Some(if operator == "(" {
+                CompoundCommand::Subshell(lists)
+            } else {
+                CompoundCommand::BraceGroup(lists)
+            });
+        } else if operator == ")" || (operator == "}" && !has_program) {
+            return Err(SplittingError::ProgramExpected(operator));
+        } else if let Some(here_document) = here_documents::parse_operator(&chunk) {
+            if !has_program {
+                return Err(SplittingError::ProgramExpected(operator));
+            }
+
+            // Skip the delimiter, the body was already extracted from the input.
+            if !here_document.has_delimiter() && iter.next().is_none() {
+                return Err(SplittingError::MissingRedirectDestination(
+                    NEWLINE_TOKEN.to_owned(),
+                ));
+            }
+
+            let body = here_documents
+                .pop_front()
+                .ok_or(SplittingError::MissingHereDocument)?;
+            current_redirections.push(Redirect {
+                from: Descriptor::stdin(),
+                mode: RedirectMode::Read,
+                to: RedirectTo::Text(body),
+            });
+        } else if operator == "&>" || operator == "&>>" {
+            if !has_program {
+                return Err(SplittingError::ProgramExpected(operator));
+            }
+
+            let mode = if operator == "&>>" {
+                RedirectMode::Append
+            } else {
+                RedirectMode::Truncate
+            };
+
+            // Redirect both stdout and stderr to the file, like `> file 2>&1`.
+            let filename = take_filename(&mut iter, &redirection_regex)?;
+            current_redirections.push(Redirect {
+                from: Descriptor::stdout(),
+                mode,
+                to: RedirectTo::File(filename),
+            });
+            current_redirections.push(Redirect {
+                from: Descriptor::stderr(),
+                mode,
+                to: RedirectTo::Descriptor(Descriptor::stdout()),
+            });
+        } else if let Some(groups) = redirection {
+            if !has_program {
+                return Err(SplittingError::ProgramExpected(operator));
+            }
+
+            let mode = if groups.name("read").is_some() || groups.name("string").is_some() {
+                RedirectMode::Read
+            } else if groups.name("append").is_some() {
+                RedirectMode::Append
+            } else if groups.name("clobber").is_some() {
+                RedirectMode::Clobber
+            } else {
+                RedirectMode::Truncate
+            };
+
+            // Input redirections apply to stdin by default, output ones to stdout.
+            let default_descriptor = if mode == RedirectMode::Read { 0 } else { 1 };
+            let descriptor_id = match groups.name("from") {
+                Some(descriptor) => parse_descriptor(descriptor.as_str())?,
+                None => default_descriptor,
+            };
+
+            let destination = if groups.name("to").is_some_and(|to| to.as_str() == "&-") {
+                RedirectTo::Closed
+            } else if let Some(descriptor) = groups.name("to") {
+                RedirectTo::Descriptor(Descriptor(parse_descriptor(&descriptor.as_str()[1..])?))
+            } else if groups.name("string").is_some() {
+                let mut text = iter.next().ok_or_else(|| {
+                    SplittingError::MissingRedirectDestination(NEWLINE_TOKEN.to_owned())
+                })?;
+
+                // Here-strings are fed as a line.
+                text.push_char('\n', true);
+                RedirectTo::Text(text)
+            } else {
+                RedirectTo::File(take_filename(&mut iter, &redirection_regex)?)
+            };
+
+            current_redirections.push(Redirect {
+                from: Descriptor(descriptor_id),
+                mode,
+                to: destination,
+            });
+        } else if current_compound.is_some() {
+            return Err(SplittingError::UnexpectedToken(chunk.to_string()));
+        } else if current_program.is_none() {
+            // Leading assignments apply to the program, or to the shell if there is none.
+            match chunk.split_assignment() {
+                Some((name, value)) => current_assignments.push(Assignment { name, value }),
+                None => current_program = Some(chunk),
+            }
+        } else {
+            current_args.push(chunk);
         }
     }
 
-    if let Some(program) = current_program {
-        commands.push(Command::new(program, current_args, current_redirections));
+    if current_program.is_some() || current_compound.is_some() || !current_assignments.is_empty() {
+        commands.push(Command::new(
+            current_assignments,
+            current_program,
+            current_compound,
+            current_args,
+            current_redirections,
+        ));
     } else {
         return Err(SplittingError::DanglingPipe);
     }
@@ -108,30 +446,101 @@ pub(crate) fn split_commands(chunks: Vec<InputChunk>) -> Result<Vec<Command>, Sp
     Ok(commands)
 }
 
+/// Takes the chunks of the group up to its closing word, which is skipped, the opening one being
+/// already taken.
+fn take_group(
+    iter: &mut impl Iterator<Item = InputChunk>,
+    closing: &'static str,
+) -> Result<Vec<InputChunk>, SplittingError> {
+    let mut chunks = vec![];
+
+    let mut nesting = Nesting::within(vec![closing]);
+    for chunk in iter {
+        nesting.enter(&chunk);
+        if nesting.is_closed() {
+            return Ok(chunks);
+        }
+        chunks.push(chunk);
+    }
+
+    Err(SplittingError::UnterminatedGroup(closing.to_owned()))
+}
+
+/// Parses the number of a descriptor, which the regex already checked is made of digits.
+fn parse_descriptor(text: &str) -> Result<u8, SplittingError> {
+    text.parse()
+        .map_err(|_| SplittingError::InvalidDescriptor(text.to_owned()))
+}
+
+/// Takes the filename of a redirection, which can't be another operator.
+fn take_filename(
+    iter: &mut impl Iterator<Item = InputChunk>,
+    redirection_regex: &Regex,
+) -> Result<InputChunk, SplittingError> {
+    let filename = iter
+        .next()
+        .ok_or_else(|| SplittingError::MissingRedirectDestination(NEWLINE_TOKEN.to_owned()))?;
+
+    if let Some(text) = filename
+        .as_unquoted()
+        .filter(|text| ["|", "&>", "&>>"].contains(text) || redirection_regex.is_match(text))
+    {
+        return Err(SplittingError::MissingRedirectDestination(text.to_owned()));
+    }
+
+    Ok(filename)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{split_commands, RedirectTo, SplittingError};
-    use crate::parser::quoting::InputChunk;
+    use super::{
+        split_and_or_list, split_commands, split_lists, RedirectMode, RedirectTo, SplittingError,
+    };
+    use crate::parser::quoting::{chunk_quoted_string, ChunkPart, InputChunk};
     use crate::parser::Descriptor;
+    use crate::parser::ListOperator;
+    use crate::state::MAX_NESTING_DEPTH;
+    use std::collections::VecDeque;
 
     fn raw(text: &str) -> InputChunk {
-        InputChunk::RawText(text.to_owned())
+        InputChunk::from(ChunkPart::Unquoted(text.to_owned()))
     }
 
     fn quoted(text: &str) -> InputChunk {
-        InputChunk::QuotedText(text.to_owned())
+        InputChunk::from(ChunkPart::Quoted(text.to_owned()))
     }
 
     #[test]
     fn it_parses_single_command_without_redirect() {
         let input = vec![raw("echo"), raw("hello")];
 
-        let commands = split_commands(input).unwrap();
+        let commands = split_commands(input, &mut VecDeque::new()).unwrap();
 
         assert_eq!(1, commands.len());
-        assert_eq!("echo", commands[0].program);
+        assert_eq!(Some(raw("echo")), commands[0].program);
         assert_eq!(1, commands[0].arguments.len());
-        assert_eq!("hello", commands[0].arguments[0]);
+        assert_eq!(raw("hello"), commands[0].arguments[0]);
+    }
+
+    #[test]
+    fn it_parses_leading_assignments() {
+        let input = chunk_quoted_string("A=1 B='x y' env C=2 | D=").unwrap();
+
+        let commands = split_commands(input, &mut VecDeque::new()).unwrap();
+
+        assert_eq!(2, commands.len());
+        assert_eq!(2, commands[0].assignments.len());
+        assert_eq!("A", commands[0].assignments[0].name);
+        assert_eq!(raw("1"), commands[0].assignments[0].value);
+        assert_eq!("B", commands[0].assignments[1].name);
+        assert_eq!(quoted("x y"), commands[0].assignments[1].value);
+        assert_eq!(Some(raw("env")), commands[0].program);
+        assert_eq!(vec![raw("C=2")], commands[0].arguments);
+
+        // Without program, the assignments apply to the shell.
+        assert_eq!("D", commands[1].assignments[0].name);
+        assert_eq!(InputChunk::default(), commands[1].assignments[0].value);
+        assert_eq!(None, commands[1].program);
     }
 
     #[test]
@@ -144,7 +553,7 @@ mod tests {
             quoted("hello"),
         ];
 
-        let commands = split_commands(input).unwrap();
+        let commands = split_commands(input, &mut VecDeque::new()).unwrap();
 
         assert_eq!(2, commands.len());
     }
@@ -160,19 +569,19 @@ mod tests {
             raw("err.txt"),
         ];
 
-        let commands = split_commands(input).unwrap();
+        let commands = split_commands(input, &mut VecDeque::new()).unwrap();
 
         assert_eq!(1, commands.len());
         assert_eq!(1, commands[0].arguments.len());
         assert_eq!(2, commands[0].redirects.len());
         assert_eq!(Descriptor(1), commands[0].redirects[0].from);
         assert_eq!(
-            RedirectTo::File("out.txt".to_owned()),
+            RedirectTo::File(raw("out.txt")),
             commands[0].redirects[0].to
         );
         assert_eq!(Descriptor(2), commands[0].redirects[1].from);
         assert_eq!(
-            RedirectTo::File("err.txt".to_owned()),
+            RedirectTo::File(raw("err.txt")),
             commands[0].redirects[1].to
         );
     }
@@ -191,7 +600,7 @@ mod tests {
             raw("second.txt"),
         ];
 
-        let commands = split_commands(input).unwrap();
+        let commands = split_commands(input, &mut VecDeque::new()).unwrap();
 
         assert_eq!(2, commands.len());
         assert_eq!(1, commands[0].redirects.len());
@@ -202,7 +611,7 @@ mod tests {
     fn it_parses_descriptor_redirections() {
         let input = vec![raw("echo"), raw("hello"), raw("1>&2")];
 
-        let commands = split_commands(input).unwrap();
+        let commands = split_commands(input, &mut VecDeque::new()).unwrap();
 
         assert_eq!(1, commands.len());
         assert_eq!(1, commands[0].redirects.len());
@@ -217,18 +626,138 @@ mod tests {
     fn it_parses_append_redirections() {
         let input = vec![raw("echo"), raw("hello"), raw(">>"), raw("out.txt")];
 
-        let commands = split_commands(input).unwrap();
+        let commands = split_commands(input, &mut VecDeque::new()).unwrap();
 
         assert_eq!(1, commands.len());
         assert_eq!(1, commands[0].redirects.len());
-        assert!(commands[0].redirects[0].append);
+        assert_eq!(RedirectMode::Append, commands[0].redirects[0].mode);
+    }
+
+    #[test]
+    fn it_parses_clobber_redirections() {
+        let input = vec![
+            raw("echo"),
+            raw(">|"),
+            raw("out.txt"),
+            raw("2>|"),
+            raw("err.txt"),
+        ];
+
+        let commands = split_commands(input, &mut VecDeque::new()).unwrap();
+
+        let redirects = &commands[0].redirects;
+        assert_eq!(2, redirects.len());
+        assert_eq!(Descriptor(1), redirects[0].from);
+        assert_eq!(RedirectMode::Clobber, redirects[0].mode);
+        assert_eq!(RedirectTo::File(raw("out.txt")), redirects[0].to);
+        assert_eq!(Descriptor(2), redirects[1].from);
+        assert_eq!(RedirectMode::Clobber, redirects[1].mode);
+    }
+
+    #[test]
+    fn it_parses_input_redirections() {
+        let input = vec![
+            raw("cat"),
+            raw("<"),
+            raw("in.txt"),
+            raw("0<"),
+            raw("other.txt"),
+        ];
+
+        let commands = split_commands(input, &mut VecDeque::new()).unwrap();
+
+        assert_eq!(1, commands.len());
+        assert_eq!(0, commands[0].arguments.len());
+        assert_eq!(2, commands[0].redirects.len());
+        for redirect in &commands[0].redirects {
+            assert_eq!(Descriptor(0), redirect.from);
+            assert_eq!(RedirectMode::Read, redirect.mode);
+        }
+        assert_eq!(RedirectTo::File(raw("in.txt")), commands[0].redirects[0].to);
+    }
+
+    #[test]
+    fn it_parses_arbitrary_descriptors() {
+        let input = vec![raw("cmd"), raw("3>"), raw("out.txt"), raw("4>&3")];
+
+        let commands = split_commands(input, &mut VecDeque::new()).unwrap();
+
+        let redirects = &commands[0].redirects;
+        assert_eq!(2, redirects.len());
+        assert_eq!(Descriptor(3), redirects[0].from);
+        assert_eq!(RedirectTo::File(raw("out.txt")), redirects[0].to);
+        assert_eq!(Descriptor(4), redirects[1].from);
+        assert_eq!(RedirectTo::Descriptor(Descriptor(3)), redirects[1].to);
+
+        assert!(matches!(
+            split_commands(vec![raw("cmd"), raw("256>&1")], &mut VecDeque::new()),
+            Err(SplittingError::InvalidDescriptor(descriptor)) if descriptor == "256"
+        ));
+    }
+
+    #[test]
+    fn it_parses_closed_descriptors() {
+        let input = chunk_quoted_string("cmd 2>&- a").unwrap();
+
+        let commands = split_commands(input, &mut VecDeque::new()).unwrap();
+
+        assert_eq!(vec![raw("a")], commands[0].arguments);
+        assert_eq!(1, commands[0].redirects.len());
+        assert_eq!(Descriptor(2), commands[0].redirects[0].from);
+        assert_eq!(RedirectTo::Closed, commands[0].redirects[0].to);
+    }
+
+    #[test]
+    fn it_parses_stdout_and_stderr_redirections() {
+        let input = chunk_quoted_string("ls &> out.txt &>> log.txt").unwrap();
+
+        let commands = split_commands(input, &mut VecDeque::new()).unwrap();
+
+        assert_eq!(1, commands.len());
+        assert_eq!(0, commands[0].arguments.len());
+        assert_eq!(4, commands[0].redirects.len());
+
+        let redirects = &commands[0].redirects;
+        assert_eq!(Descriptor(1), redirects[0].from);
+        assert_eq!(RedirectMode::Truncate, redirects[0].mode);
+        assert_eq!(RedirectTo::File(raw("out.txt")), redirects[0].to);
+        assert_eq!(Descriptor(2), redirects[1].from);
+        assert_eq!(RedirectTo::Descriptor(Descriptor(1)), redirects[1].to);
+        assert_eq!(RedirectMode::Append, redirects[2].mode);
+        assert_eq!(RedirectTo::File(raw("log.txt")), redirects[2].to);
+        assert_eq!(Descriptor(2), redirects[3].from);
+        assert_eq!(RedirectTo::Descriptor(Descriptor(1)), redirects[3].to);
+    }
+
+    #[test]
+    fn it_parses_here_strings_and_documents() {
+        let input = vec![raw("cat"), raw("<<<"), raw("hello"), raw("<<"), raw("EOF")];
+        let mut bodies = VecDeque::from([quoted("world\n")]);
+
+        let commands = split_commands(input, &mut bodies).unwrap();
+
+        assert_eq!(1, commands.len());
+        assert_eq!(0, commands[0].arguments.len());
+        assert_eq!(2, commands[0].redirects.len());
+        for redirect in &commands[0].redirects {
+            assert_eq!(Descriptor(0), redirect.from);
+            assert_eq!(RedirectMode::Read, redirect.mode);
+        }
+
+        let mut line = raw("hello");
+        line.push_char('\n', true);
+        assert_eq!(RedirectTo::Text(line), commands[0].redirects[0].to);
+        assert_eq!(
+            RedirectTo::Text(quoted("world\n")),
+            commands[0].redirects[1].to
+        );
     }
 
     #[test]
     fn it_ignores_quoted_pipes() {
         let input = vec![raw("echo"), raw("hello"), quoted("|"), raw("world")];
 
-        let commands = split_commands(input).unwrap();
+        let commands = split_commands(input, &mut VecDeque::new()).unwrap();
 
         assert_eq!(1, commands.len());
         assert_eq!(3, commands[0].arguments.len());
@@ -239,7 +768,7 @@ mod tests {
         // Starting with a pipe.
         let input = vec![raw("|"), raw("echo"), raw("hello")];
 
-        let res = split_commands(input);
+        let res = split_commands(input, &mut VecDeque::new());
 
         assert!(res.is_err());
         assert!(matches!(
@@ -250,7 +779,7 @@ mod tests {
         // Starting with a redirection.
         let input = vec![raw("2>"), raw("err.txt"), raw("echo"), raw("hello")];
 
-        let res = split_commands(input);
+        let res = split_commands(input, &mut VecDeque::new());
 
         assert!(res.is_err());
         assert!(matches!(
@@ -261,7 +790,7 @@ mod tests {
         // Ending with a pipe.
         let input = vec![raw("echo"), raw("hello"), raw("|")];
 
-        let res = split_commands(input);
+        let res = split_commands(input, &mut VecDeque::new());
 
         assert!(res.is_err());
         assert!(matches!(res.err().unwrap(), SplittingError::DanglingPipe));
@@ -269,12 +798,12 @@ mod tests {
         // Missing redirection destination.
         let input = vec![raw("echo"), raw("hello"), raw(">")];
 
-        let res = split_commands(input);
+        let res = split_commands(input, &mut VecDeque::new());
 
         assert!(res.is_err());
         assert!(matches!(
             res.err().unwrap(),
-            SplittingError::MissingRedirectDestination
+            SplittingError::MissingRedirectDestination(token) if token == "newline"
         ));
 
         // Missing redirection destination.
@@ -287,12 +816,12 @@ mod tests {
             raw("world"),
         ];
 
-        let res = split_commands(input);
+        let res = split_commands(input, &mut VecDeque::new());
 
         assert!(res.is_err());
         assert!(matches!(
             res.err().unwrap(),
-            SplittingError::MissingRedirectDestination
+            SplittingError::MissingRedirectDestination(token) if token == "|"
         ));
 
         // Missing redirection destination.
@@ -304,12 +833,241 @@ mod tests {
             raw("err.txt"),
         ];
 
-        let res = split_commands(input);
+        let res = split_commands(input, &mut VecDeque::new());
 
         assert!(res.is_err());
         assert!(matches!(
             res.err().unwrap(),
-            SplittingError::MissingRedirectDestination
+            SplittingError::MissingRedirectDestination(token) if token == "2>"
+        ));
+    }
+
+    #[test]
+    fn it_parses_and_or_lists() {
+        let input = vec![
+            raw("false"),
+            raw("&&"),
+            raw("echo"),
+            raw("no"),
+            raw("||"),
+            raw("echo"),
+            raw("ok"),
+            raw("|"),
+            raw("cat"),
+        ];
+
+        let list = split_and_or_list(input, &mut VecDeque::new())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(1, list.first.len());
+        assert_eq!(2, list.rest.len());
+        assert_eq!(ListOperator::And, list.rest[0].0);
+        assert_eq!(1, list.rest[0].1.len());
+        assert_eq!(ListOperator::Or, list.rest[1].0);
+        assert_eq!(2, list.rest[1].1.len());
+
+        // Quoted operators are arguments.
+        let input = vec![raw("echo"), quoted("&&"), quoted("||")];
+
+        let list = split_and_or_list(input, &mut VecDeque::new())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(2, list.first[0].arguments.len());
+        assert!(list.rest.is_empty());
+
+        // Operators don't need surrounding spaces.
+        for (input, operator) in [("a&&b", ListOperator::And), ("a||b", ListOperator::Or)] {
+            let list = split_and_or_list(chunk_quoted_string(input).unwrap(), &mut VecDeque::new())
+                .unwrap()
+                .unwrap();
+
+            assert_eq!(Some(raw("a")), list.first[0].program);
+            assert_eq!(operator, list.rest[0].0);
+            assert_eq!(Some(raw("b")), list.rest[0].1[0].program);
+        }
+
+        // Empty input.
+        assert!(split_and_or_list(vec![], &mut VecDeque::new())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn it_rejects_erroneous_and_or_lists() {
+        // Starting with an operator.
+        let res = split_and_or_list(vec![raw("&&"), raw("echo")], &mut VecDeque::new());
+
+        assert!(matches!(
+            res.err().unwrap(),
+            SplittingError::ProgramExpected(found) if found == "&&"
+        ));
+
+        // Consecutive operators.
+        let res = split_and_or_list(
+            vec![raw("true"), raw("&&"), raw("||"), raw("echo")],
+            &mut VecDeque::new(),
+        );
+
+        assert!(matches!(
+            res.err().unwrap(),
+            SplittingError::ProgramExpected(found) if found == "||"
+        ));
+
+        // Ending with an operator.
+        let res = split_and_or_list(vec![raw("true"), raw("||")], &mut VecDeque::new());
+
+        assert!(matches!(
+            res.err().unwrap(),
+            SplittingError::DanglingListOperator(found) if found == "||"
+        ));
+    }
+
+    #[test]
+    fn it_parses_lists_separated_by_semicolons() {
+        let input = vec![
+            raw("echo"),
+            raw("a"),
+            raw(";"),
+            raw("false"),
+            raw("||"),
+            raw("echo"),
+            quoted(";"),
+            raw(";"),
+        ];
+
+        let lists = split_lists(input, vec![]).unwrap();
+
+        assert_eq!(2, lists.len());
+        assert_eq!(1, lists[0].first[0].arguments.len());
+        assert_eq!(1, lists[1].rest.len());
+        assert_eq!(1, lists[1].rest[0].1[0].arguments.len());
+
+        // Empty input.
+        assert!(split_lists(vec![], vec![]).unwrap().is_empty());
+
+        // Newlines separate lists, apart from empty lines and after operators.
+        let input = chunk_quoted_string("\necho a\n\nfalse ||\necho b |\ncat\n").unwrap();
+
+        let lists = split_lists(input, vec![]).unwrap();
+
+        assert_eq!(2, lists.len());
+        assert_eq!(1, lists[1].rest.len());
+        assert_eq!(2, lists[1].rest[0].1.len());
+
+        // Starting with a separator, or with consecutive separators.
+        for input in [
+            vec![raw(";"), raw("echo")],
+            vec![raw("echo"), raw(";"), raw(";")],
+        ] {
+            assert!(matches!(
+                split_lists(input, vec![]).err().unwrap(),
+                SplittingError::ProgramExpected(found) if found == ";"
+            ));
+        }
+    }
+
+    #[test]
+    fn it_parses_subshells() {
+        let input = chunk_quoted_string("(cd /tmp && (pwd)\n) > out.txt | cat").unwrap();
+
+        let lists = split_lists(input, vec![]).unwrap();
+
+        let commands = &lists[0].first;
+        assert_eq!(2, commands.len());
+        assert_eq!(None, commands[0].program);
+        assert_eq!(1, commands[0].redirects.len());
+        assert_eq!("(cd /tmp && (pwd)) > out.txt", commands[0].to_string());
+
+        // Unbalanced parentheses, empty groups, and words around groups.
+        for (input, expected) in [
+            (
+                "(echo a",
+                "syntax error: unexpected end of file, missing `)'",
+            ),
+            ("echo a)", "syntax error near unexpected token `)'"),
+            ("()", "syntax error near unexpected token `)'"),
+            ("(echo a) b", "syntax error near unexpected token `b'"),
+            ("echo (a)", "syntax error near unexpected token `('"),
+        ] {
+            let error = split_lists(chunk_quoted_string(input).unwrap(), vec![]).err();
+            assert_eq!(Some(expected.to_owned()), error.map(|e| e.to_string()));
+        }
+
+        // Groups nested too deeply.
+        let depth = MAX_NESTING_DEPTH + 1;
+        let input = format!("{}true{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(matches!(
+            split_lists(chunk_quoted_string(&input).unwrap(), vec![]),
+            Err(SplittingError::NestedTooDeeply)
+        ));
+    }
+
+    #[test]
+    fn it_parses_brace_groups() {
+        let input = chunk_quoted_string("{ echo a; { echo }; }\n} 2> err.txt && echo {").unwrap();
+
+        let lists = split_lists(input, vec![]).unwrap();
+
+        assert_eq!(1, lists.len());
+        assert_eq!(
+            "{ echo a; { echo }; }; } 2> err.txt",
+            lists[0].first[0].to_string()
+        );
+        assert_eq!(vec![raw("{")], lists[0].rest[0].1[0].arguments);
+
+        // The closing brace must start a command.
+        for (input, expected) in [
+            (
+                "{ echo a }",
+                "syntax error: unexpected end of file, missing `}'",
+            ),
+            ("{ }", "syntax error near unexpected token `}'"),
+            ("echo a; }", "syntax error near unexpected token `}'"),
+        ] {
+            let error = split_lists(chunk_quoted_string(input).unwrap(), vec![]).err();
+            assert_eq!(Some(expected.to_owned()), error.map(|e| e.to_string()));
+        }
+    }
+
+    #[test]
+    fn it_parses_background_lists() {
+        let input = vec![
+            raw("sleep"),
+            raw("1"),
+            raw("&"),
+            raw("echo"),
+            quoted("&"),
+            raw(";"),
+            raw("sleep"),
+            raw("2"),
+            raw("|"),
+            raw("cat"),
+            raw("&"),
+        ];
+
+        let lists = split_lists(input, vec![]).unwrap();
+
+        assert_eq!(3, lists.len());
+        assert!(lists[0].background);
+        assert!(!lists[1].background);
+        assert_eq!(1, lists[1].first[0].arguments.len());
+        assert!(lists[2].background);
+        assert_eq!(2, lists[2].first.len());
+
+        // Starting with a separator.
+        assert!(matches!(
+            split_lists(vec![raw("&"), raw("echo")], vec![]).err().unwrap(),
+            SplittingError::ProgramExpected(found) if found == "&"
+        ));
+
+        // Conditional lists can't run in the background.
+        assert!(matches!(
+            split_lists(vec![raw("true"), raw("&&"), raw("echo"), raw("&")], vec![])
+                .err()
+                .unwrap(),
+            SplittingError::UnsupportedBackgroundList
         ));
     }
 }
@@ -1,33 +1,101 @@
+mod cache;
+mod hashing;
+
 use crate::io::FileDescriptor;
+use crate::jobs::{disable_job_control, set_foreground_process_group, terminal, ProcessGroup};
 use crate::parser::Descriptor;
 use is_executable::IsExecutable;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::env::VarError;
+use std::io::Write;
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Child, Command};
 use thiserror::Error;
 
+pub(crate) use cache::PathCache;
+pub(crate) use hashing::CommandHashes;
+
 #[derive(Error, Debug)]
 pub(crate) enum PathError {
     #[error("{0}: command not found")]
     CommandNotFound(String),
 
+    #[error("{0}: No such file or directory")]
+    NoSuchFile(String),
+
+    #[error("{0}: Is a directory")]
+    IsADirectory(String),
+
+    #[error("{0}: Permission denied")]
+    PermissionDenied(String),
+
     #[error("{0}: execution failed: {1:?}")]
     CommandError(String, std::io::Error),
 
     #[error("Failed to read environment variable: {0}")]
     GetEnvFailed(#[from] VarError),
+
+    #[error("Failed to start a subshell: {0}")]
+    ForkFailed(#[source] std::io::Error),
+}
+
+impl PathError {
+    /// The exit status of a command which can't be run, like bash: 127 if it is missing, or 126 if
+    /// it isn't executable.
+    pub(crate) fn exit_status(&self) -> Option<i32> {
+        match self {
+            PathError::CommandNotFound(_) | PathError::NoSuchFile(_) => Some(127),
+            PathError::IsADirectory(_) | PathError::PermissionDenied(_) => Some(126),
+            _ => None,
+        }
+    }
 }
 
-pub(crate) fn run_binary(
+/// Starts the binary in a child process without waiting for it to finish.
+///
+/// # Note
+/// The descriptors are moved into the command, and dropped once the child is spawned. This is
+/// required for pipes, whose read end only reaches EOF once every copy of the write end is closed.
+///
+/// Descriptors other than stdin, stdout and stderr, e.g. `3> file`, are duplicated onto their
+/// number in the child right before it executes the binary, which is only supported on Unix.
+///
+/// The executable is looked up in the PATH by the system, unless its location is already known.
+///
+/// The child may join or start a process group for job control, taking the terminal if it starts a
+/// foreground one.
+pub(crate) fn spawn_binary(
     cmd: &str,
+    location: Option<&Path>,
     args: &[String],
+    variables: &[(String, String)],
     mut descriptors: HashMap<Descriptor, FileDescriptor>,
-) -> Result<(), PathError> {
-    let mut command = Command::new(cmd);
+    process_group: Option<ProcessGroup>,
+) -> Result<Child, PathError> {
+    if is_path(cmd) {
+        check_executable_path(cmd)?;
+    }
+
+    // Run the known executable, still passing the command name as its first argument.
+    let mut command = match location {
+        Some(location) => {
+            let mut command = Command::new(location);
+            command.arg0(cmd);
+            command
+        }
+        None => Command::new(cmd),
+    };
 
-    // Pass command args.
+    // Pass command args, and the variables assigned only for this command.
     command.args(args);
+    command.envs(variables.iter().map(|(name, value)| (name, value)));
+
+    // Redirect standard input, only if requested, so the child inherits the terminal otherwise.
+    if let Some(stdin) = descriptors.remove(&Descriptor::stdin()) {
+        command.stdin(stdin);
+    }
 
     // Redirect standard output and error.
     let stdout = descriptors
@@ -40,8 +108,43 @@ pub(crate) fn run_binary(
     command.stdout(stdout);
     command.stderr(stderr);
 
-    // Start the program in a thread and wait for it to finish, ignoring the exit status.
-    let _ = command.status().map_err(|e| {
+    // Pass the extra descriptors, which stay open in the shell until the child is spawned.
+    let extra_descriptors: Vec<(RawFd, RawFd)> = descriptors
+        .iter()
+        .map(|(descriptor, file)| (file.as_raw_fd(), RawFd::from(descriptor.0)))
+        .collect();
+    if !extra_descriptors.is_empty() {
+        // SAFETY: the closure only calls async-signal-safe functions, as required after forking.
+        unsafe {
+            command.pre_exec(move || duplicate_descriptors(&extra_descriptors));
+        }
+    }
+
+    match process_group {
+        None => {}
+        Some(ProcessGroup::Join(process_group)) => {
+            command.process_group(process_group as i32);
+        }
+        Some(ProcessGroup::New { foreground }) => {
+            command.process_group(0);
+
+            // Take the terminal before running the binary, so it can't read it in the background
+            // before the shell gives it.
+            if let Some(terminal) = terminal().filter(|_| foreground) {
+                // SAFETY: the closure only calls async-signal-safe functions, as required after
+                // forking.
+                unsafe {
+                    command.pre_exec(move || {
+                        set_foreground_process_group(terminal, libc::getpgrp());
+                        Ok(())
+                    });
+                }
+            }
+        }
+    }
+
+    // Start the program in a child process.
+    let child = command.spawn().map_err(|e| {
         if e.kind() == std::io::ErrorKind::NotFound {
             PathError::CommandNotFound(cmd.to_owned())
         } else {
@@ -49,45 +152,192 @@ pub(crate) fn run_binary(
         }
     })?;
 
+    Ok(child)
+}
+
+/// Runs the function in a copy of the shell process without waiting for it to finish, e.g. for
+/// subshells, whose changes to the state of the shell are lost once they exit.
+///
+/// The descriptors are duplicated onto their number in the child, which joins or starts a process
+/// group like binaries do, then runs without job control. The child exits with the status returned
+/// by the function rather than returning.
+pub(crate) fn spawn_subshell(
+    descriptors: HashMap<Descriptor, FileDescriptor>,
+    process_group: Option<ProcessGroup>,
+    run: impl FnOnce() -> i32,
+) -> Result<u32, PathError> {
+    // Write the pending output first, so the child doesn't write it again.
+    let _ = std::io::stdout().flush();
+
+    // SAFETY: only this thread is copied into the child, which keeps running the code of the shell,
+    // so no lock it takes may be held by another thread at this point. The other threads only move
+    // bytes between pipes and their own buffers, i.e. `FileDescriptor::text`,
+    // `FileDescriptor::buffered` and the readers of command substitutions, without taking the locks
+    // of the standard streams or of the environment. They do allocate, but glibc takes the locks of
+    // the allocator around fork, so it is consistent in the child. New threads must keep to this.
+    let pid = unsafe { libc::fork() };
+    if pid < 0 {
+        return Err(PathError::ForkFailed(std::io::Error::last_os_error()));
+    }
+
+    let group = match process_group {
+        None => None,
+        Some(ProcessGroup::Join(process_group)) => Some(process_group as libc::pid_t),
+        Some(ProcessGroup::New { .. }) => Some(0),
+    };
+
+    if pid > 0 {
+        // Set the group from the shell as well, so the next commands can join it right away.
+        if let Some(group) = group {
+            // SAFETY: setpgid doesn't access memory, failures are left to the child.
+            unsafe { libc::setpgid(pid, if group == 0 { pid } else { group }) };
+        }
+
+        return Ok(pid as u32);
+    }
+
+    if let Some(group) = group {
+        // SAFETY: setpgid and getpgrp don't access memory.
+        unsafe { libc::setpgid(0, group) };
+
+        if let (Some(ProcessGroup::New { foreground: true }), Some(terminal)) =
+            (process_group, terminal())
+        {
+            // SAFETY: getpgrp doesn't access memory.
+            set_foreground_process_group(terminal, unsafe { libc::getpgrp() });
+        }
+    }
+    disable_job_control();
+
+    // Let Ctrl+C and Ctrl+Z interrupt and stop the subshell, rather than only its commands.
+    for signal in [libc::SIGINT, libc::SIGTSTP] {
+        // SAFETY: restoring the default behaviour doesn't rely on any handler.
+        unsafe { libc::signal(signal, libc::SIG_DFL) };
+    }
+
+    let targets: Vec<(RawFd, RawFd)> = descriptors
+        .iter()
+        .map(|(descriptor, file)| (file.as_raw_fd(), RawFd::from(descriptor.0)))
+        .collect();
+    let status = match duplicate_descriptors(&targets) {
+        Ok(()) => {
+            drop(descriptors);
+
+            // Never unwind back into the code of the parent shell.
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(run)).unwrap_or(1)
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            1
+        }
+    };
+
+    let _ = std::io::stdout().flush();
+    // SAFETY: exiting right away skips the cleanups which belong to the parent shell.
+    unsafe { libc::_exit(status) }
+}
+
+/// Runs the code in a copy of the shell like [`spawn_subshell`], and waits for the status it exits
+/// with, e.g. for tests which change the state of the process.
+#[cfg(test)]
+pub(crate) fn run_subshell(
+    descriptors: HashMap<Descriptor, FileDescriptor>,
+    run: impl FnOnce() -> i32,
+) -> i32 {
+    let pid = spawn_subshell(descriptors, None, run).unwrap();
+
+    let mut status = 0;
+    // SAFETY: the status is a valid pointer for the duration of the call.
+    unsafe { libc::waitpid(pid as libc::pid_t, &mut status, 0) };
+    assert!(libc::WIFEXITED(status));
+
+    libc::WEXITSTATUS(status)
+}
+
+/// Whether the command name is a path to the executable, e.g. `./script` or `/bin/ls`, rather than a
+/// name to look up in the PATH.
+pub(crate) fn is_path(name: &str) -> bool {
+    name.contains('/')
+}
+
+/// Checks that the path leads to an executable file, to report why it can't be run otherwise.
+fn check_executable_path(path: &str) -> Result<(), PathError> {
+    let location = Path::new(path);
+
+    if !location.exists() {
+        Err(PathError::NoSuchFile(path.to_owned()))
+    } else if location.is_dir() {
+        Err(PathError::IsADirectory(path.to_owned()))
+    } else if !location.is_executable() {
+        Err(PathError::PermissionDenied(path.to_owned()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Duplicates each source descriptor onto its target number, in the child process.
+///
+/// The sources are first moved out of the way of the targets, so duplicating a descriptor can't
+/// overwrite the source of another one, e.g. `4> a 3> b` where `a` is open as 3 in the shell.
+fn duplicate_descriptors(descriptors: &[(RawFd, RawFd)]) -> std::io::Result<()> {
+    const FIRST_TEMPORARY_DESCRIPTOR: RawFd = 100;
+
+    let mut sources = [0; u8::MAX as usize + 1];
+    for (index, (source, _)) in descriptors.iter().enumerate() {
+        // SAFETY: fcntl doesn't access memory, invalid descriptors are reported as errors.
+        let temporary =
+            unsafe { libc::fcntl(*source, libc::F_DUPFD_CLOEXEC, FIRST_TEMPORARY_DESCRIPTOR) };
+        if temporary < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        sources[index] = temporary;
+    }
+
+    for (index, (_, target)) in descriptors.iter().enumerate() {
+        // SAFETY: dup2 doesn't access memory, and the duplicate is inherited by the binary.
+        if unsafe { libc::dup2(sources[index], *target) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
     Ok(())
 }
 
-/// Finds a file whose name is an exact match in the user PATH.
+/// Finds the first executable whose name is an exact match in the user PATH, or the executable itself
+/// if the name is a path.
 pub(crate) fn find_file_in_path(name: &str) -> Result<Option<PathBuf>, PathError> {
-    // Check whether the file exists in any of the directories.
-    let location = get_path_directories()?
-        .into_iter()
-        .find_map(|dir| Some(dir.join(name)).filter(|location| location.exists()));
-
-    Ok(location)
+    Ok(find_executables_in_path(name)?.into_iter().next())
 }
 
-/// Finds executables matching the partial name in the user PATH.
-/// This is used for autocompletion, so the start of executable names must match the input.
-pub(crate) fn find_partial_executable_matches_in_path(
-    partial_name: &str,
-) -> Result<HashSet<String>, PathError> {
-    let matched_executables: HashSet<_> = get_path_directories()?
+/// Finds all the executables whose name is an exact match in the user PATH, in the PATH order, or
+/// the executable itself if the name is a path.
+pub(crate) fn find_executables_in_path(name: &str) -> Result<Vec<PathBuf>, PathError> {
+    let candidates = if is_path(name) {
+        vec![PathBuf::from(name)]
+    } else {
+        get_path_directories()?
+            .into_iter()
+            .map(|dir| dir.join(name))
+            .collect()
+    };
+
+    // Skip the files which can't be run, e.g. directories or files without permission.
+    let locations = candidates
         .into_iter()
-        // List files in PATH directories, ignoring errors (missing directory, permissions, ...).
-        .filter_map(|path| path.read_dir().ok())
-        .flatten()
-        // Ignore file errors.
-        .filter_map(Result::ok)
-        // Ignore invalid UTF-8 filenames.
-        .filter_map(|file| {
-            let file_name = file.file_name().into_string().ok();
-
-            file_name.map(|file_name| (file, file_name))
-        })
-        // Only keep files for which the start of the name matches the input.
-        .filter(|(_, file_name)| file_name.starts_with(partial_name))
-        // Only keep executable files.
-        .filter(|(file, _)| file.path().is_executable())
-        .map(|(_, file_name)| file_name)
+        .filter(|location| check_executable_path(&location.to_string_lossy()).is_ok())
         .collect();
 
-    Ok(matched_executables)
+    Ok(locations)
+}
+
+/// Whether the name starts with the partial name, optionally ignoring case.
+pub(crate) fn starts_with(name: &str, partial_name: &str, ignore_case: bool) -> bool {
+    if ignore_case {
+        name.to_lowercase()
+            .starts_with(&partial_name.to_lowercase())
+    } else {
+        name.starts_with(partial_name)
+    }
 }
 
 fn get_path_directories() -> Result<Vec<PathBuf>, PathError> {
@@ -100,3 +350,38 @@ fn get_path_directories() -> Result<Vec<PathBuf>, PathError> {
 
     Ok(directories)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{find_file_in_path, spawn_binary, PathError};
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    #[test]
+    fn it_runs_paths_without_looking_up_the_path() {
+        // Report missing files, directories and non-executable files.
+        assert!(matches!(
+            spawn_binary("./codecrafters_shell_missing", None, &[], &[], HashMap::new(), None),
+            Err(PathError::NoSuchFile(path)) if path == "./codecrafters_shell_missing"
+        ));
+        assert!(matches!(
+            spawn_binary("/", None, &[], &[], HashMap::new(), None),
+            Err(PathError::IsADirectory(_))
+        ));
+        assert!(matches!(
+            spawn_binary("./Cargo.toml", None, &[], &[], HashMap::new(), None),
+            Err(PathError::PermissionDenied(_))
+        ));
+
+        // Locate the executables themselves.
+        assert_eq!(
+            Some(PathBuf::from("/bin/sh")),
+            find_file_in_path("/bin/sh").unwrap()
+        );
+        assert_eq!(
+            None,
+            find_file_in_path("./codecrafters_shell_missing").unwrap()
+        );
+        assert_eq!(None, find_file_in_path("./Cargo.toml").unwrap());
+    }
+}
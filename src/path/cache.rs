@@ -0,0 +1,169 @@
+use crate::path::{starts_with, PathError};
+use is_executable::IsExecutable;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How long the PATH directories are trusted before being listed again, so newly installed
+/// executables are eventually picked up.
+const LISTING_LIFETIME: Duration = Duration::from_secs(10);
+
+/// A cache of the executables of the PATH directories, to avoid listing them on every completion
+/// or command.
+#[derive(Default)]
+pub(crate) struct PathCache {
+    /// The last listing, refreshed lazily when used, hence the interior mutability.
+    listing: RefCell<Option<PathListing>>,
+}
+
+/// The executables of the PATH directories, as listed at some point in time.
+struct PathListing {
+    /// The PATH the directories were listed for.
+    path: String,
+
+    listed_at: Instant,
+
+    /// The names of the executables of each directory, in the PATH order.
+    directories: Vec<(PathBuf, BTreeSet<String>)>,
+}
+
+impl PathCache {
+    /// Finds the executables whose name starts with the partial name, for autocompletion.
+    pub(crate) fn find_partial_matches(
+        &self,
+        partial_name: &str,
+        ignore_case: bool,
+    ) -> Result<HashSet<String>, PathError> {
+        self.with_listing(|listing| listing.find_partial_matches(partial_name, ignore_case))
+    }
+
+    /// Finds the location of the first executable with that name in the PATH, if it still exists.
+    pub(crate) fn find_executable(&self, name: &str) -> Result<Option<PathBuf>, PathError> {
+        let location = self.with_listing(|listing| listing.find_executable(name))?;
+
+        Ok(location.filter(|location| location.is_executable()))
+    }
+
+    /// Runs the function on the listing of the PATH directories, listing them again if the PATH
+    /// changed or the listing expired.
+    fn with_listing<T>(&self, run: impl FnOnce(&PathListing) -> T) -> Result<T, PathError> {
+        let path = std::env::var("PATH")?;
+
+        let mut cached = self.listing.borrow_mut();
+        let listing = match cached.take() {
+            Some(listing)
+                if listing.path == path && listing.listed_at.elapsed() < LISTING_LIFETIME =>
+            {
+                listing
+            }
+            _ => PathListing::new(path),
+        };
+
+        Ok(run(cached.insert(listing)))
+    }
+}
+
+impl PathListing {
+    fn new(path: String) -> Self {
+        let directories = path
+            .split(':')
+            .map(|directory| {
+                let directory = PathBuf::from(directory);
+                let executables = list_executables(&directory);
+
+                (directory, executables)
+            })
+            .collect();
+
+        Self {
+            path,
+            listed_at: Instant::now(),
+            directories,
+        }
+    }
+
+    fn find_partial_matches(&self, partial_name: &str, ignore_case: bool) -> HashSet<String> {
+        self.directories
+            .iter()
+            .flat_map(|(_, executables)| executables)
+            .filter(|name| starts_with(name, partial_name, ignore_case))
+            .cloned()
+            .collect()
+    }
+
+    fn find_executable(&self, name: &str) -> Option<PathBuf> {
+        self.directories
+            .iter()
+            .find(|(_, executables)| executables.contains(name))
+            .map(|(directory, _)| directory.join(name))
+    }
+}
+
+/// Lists the names of the executable files of the directory.
+fn list_executables(directory: &Path) -> BTreeSet<String> {
+    // Ignore errors, e.g. missing directory, permissions, ...
+    let Ok(entries) = directory.read_dir() else {
+        return BTreeSet::new();
+    };
+
+    entries
+        // Ignore file errors.
+        .filter_map(Result::ok)
+        // Only keep executable files.
+        .filter(|file| file.path().is_executable())
+        // Ignore invalid UTF-8 filenames.
+        .filter_map(|file| file.file_name().into_string().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PathListing;
+    use std::collections::HashSet;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn it_lists_executables_in_the_path_order() {
+        let directory = std::env::temp_dir().join("codecrafters_shell_path_cache");
+        let _ = fs::remove_dir_all(&directory);
+        for subdirectory in ["first", "second"] {
+            fs::create_dir_all(directory.join(subdirectory)).unwrap();
+        }
+        for (file, mode) in [
+            ("first/tool", 0o755),
+            ("first/notes", 0o644),
+            ("second/tool", 0o755),
+            ("second/toolbox", 0o755),
+        ] {
+            fs::write(directory.join(file), "").unwrap();
+            fs::set_permissions(directory.join(file), fs::Permissions::from_mode(mode)).unwrap();
+        }
+
+        let listing = PathListing::new(format!(
+            "{0}/first:{0}/missing:{0}/second",
+            directory.display()
+        ));
+
+        // Only complete executables, once each.
+        assert_eq!(
+            HashSet::from(["tool".to_owned(), "toolbox".to_owned()]),
+            listing.find_partial_matches("to", false)
+        );
+        assert_eq!(
+            HashSet::from(["toolbox".to_owned()]),
+            listing.find_partial_matches("TOOLB", true)
+        );
+        assert!(listing.find_partial_matches("no", false).is_empty());
+
+        // Resolve commands to the first directory containing them.
+        assert_eq!(
+            Some(directory.join("first/tool")),
+            listing.find_executable("tool")
+        );
+        assert_eq!(None, listing.find_executable("notes"));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
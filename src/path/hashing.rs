@@ -0,0 +1,127 @@
+use crate::path::{PathCache, PathError};
+use is_executable::IsExecutable;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The locations of the commands found so far, to skip looking them up in the PATH again, like the
+/// hash table of bash.
+#[derive(Default)]
+pub(crate) struct CommandHashes {
+    /// The PATH the locations were found in, the table being cleared when it changes.
+    path: Option<String>,
+
+    /// The commands by name.
+    entries: BTreeMap<String, HashedCommand>,
+}
+
+pub(crate) struct HashedCommand {
+    location: PathBuf,
+
+    /// How many times the command was run from this location.
+    hits: usize,
+}
+
+impl HashedCommand {
+    pub(crate) fn location(&self) -> &Path {
+        &self.location
+    }
+
+    pub(crate) fn hits(&self) -> usize {
+        self.hits
+    }
+}
+
+impl CommandHashes {
+    pub(crate) fn entries(&self) -> &BTreeMap<String, HashedCommand> {
+        &self.entries
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Finds the location of the command to run it, counting a hit.
+    pub(crate) fn locate(
+        &mut self,
+        name: &str,
+        path_cache: &PathCache,
+    ) -> Result<Option<PathBuf>, PathError> {
+        let Some(command) = self.find(name, path_cache)? else {
+            return Ok(None);
+        };
+        command.hits += 1;
+
+        Ok(Some(command.location.clone()))
+    }
+
+    /// Finds the location of the command without running it, returning whether it was found.
+    pub(crate) fn remember(
+        &mut self,
+        name: &str,
+        path_cache: &PathCache,
+    ) -> Result<bool, PathError> {
+        Ok(self.find(name, path_cache)?.is_some())
+    }
+
+    /// Finds the command in the table if its location still exists, otherwise in the PATH.
+    fn find(
+        &mut self,
+        name: &str,
+        path_cache: &PathCache,
+    ) -> Result<Option<&mut HashedCommand>, PathError> {
+        let path = std::env::var("PATH")?;
+        if self.path.as_ref() != Some(&path) {
+            self.entries.clear();
+            self.path = Some(path);
+        }
+
+        // Forget locations which are gone, e.g. uninstalled executables.
+        if self
+            .entries
+            .get(name)
+            .is_some_and(|command| !command.location.is_executable())
+        {
+            self.entries.remove(name);
+        }
+
+        if !self.entries.contains_key(name) {
+            let Some(location) = path_cache.find_executable(name)? else {
+                return Ok(None);
+            };
+
+            self.entries
+                .insert(name.to_owned(), HashedCommand { location, hits: 0 });
+        }
+
+        Ok(self.entries.get_mut(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CommandHashes;
+    use crate::path::PathCache;
+
+    #[test]
+    fn it_remembers_command_locations() {
+        let path_cache = PathCache::default();
+        let mut hashes = CommandHashes::default();
+
+        assert!(hashes.remember("sh", &path_cache).unwrap());
+        let location = hashes.locate("sh", &path_cache).unwrap().unwrap();
+        assert!(location.ends_with("sh"));
+        assert_eq!(location, hashes.locate("sh", &path_cache).unwrap().unwrap());
+
+        // Count the runs only.
+        assert_eq!(2, hashes.entries()["sh"].hits());
+
+        // Skip missing commands.
+        assert!(!hashes
+            .remember("codecrafters_shell_missing", &path_cache)
+            .unwrap());
+        assert_eq!(1, hashes.entries().len());
+
+        hashes.clear();
+        assert!(hashes.entries().is_empty());
+    }
+}
@@ -1,7 +1,18 @@
 use crate::builtin::{try_into_builtin, BuiltInCommandError};
-use crate::io::{resolve_redirects, IoError};
-use crate::parser::Command;
-use crate::path::{run_binary, PathError};
+use crate::io::{resolve_redirects, FileDescriptor, IoError, NULL_DEVICE};
+use crate::jobs::{take_terminal_back, terminal, JobState, ProcessGroup};
+use crate::parser::{
+    display_pipeline, expand_chunk, expand_words, is_incomplete_input, parse_input, AndOrList,
+    Command, CompoundCommand, Descriptor, ExpansionError, ListOperator, ParsingError,
+};
+use crate::path::{is_path, spawn_binary, spawn_subshell, PathError};
+use crate::state::{ShellOption, ShellState};
+use signal_hook::consts::{SIGINT, SIGTSTP};
+use std::collections::HashMap;
+use std::io::{pipe, IsTerminal, Read, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::thread;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -9,26 +20,1092 @@ pub(crate) enum RunnerError {
     #[error(transparent)]
     BuiltInCommand(#[from] BuiltInCommandError),
 
+    #[error(transparent)]
+    Expansion(#[from] ExpansionError),
+
     #[error(transparent)]
     Io(#[from] IoError),
 
+    #[error(transparent)]
+    Parsing(#[from] ParsingError),
+
     #[error(transparent)]
     Path(#[from] PathError),
+
+    #[error("Failed to create a pipe: {0}")]
+    PipeFailed(#[source] std::io::Error),
+
+    #[error("Failed to wait for the command to finish: {0}")]
+    WaitFailed(#[source] std::io::Error),
+
+    #[error("Failed to capture the command output: {0}")]
+    CaptureFailed(#[source] std::io::Error),
+
+    #[error("Failed to report the command failure: {0}")]
+    ReportFailed(#[source] std::io::Error),
+
+    #[error("commands nested too deeply")]
+    NestedTooDeeply,
+}
+
+impl RunnerError {
+    /// Whether the error stops running the next commands, rather than only failing its own, e.g.
+    /// `exit` or an unset parameter with `${VAR:?}`.
+    pub(crate) fn is_aborting(&self) -> bool {
+        matches!(
+            self,
+            RunnerError::BuiltInCommand(BuiltInCommandError::Exit(_))
+                | RunnerError::Expansion(ExpansionError::ParameterNotSet(..))
+        )
+    }
+}
+
+/// The outcome of starting a command of a pipeline.
+enum Execution {
+    /// The command ran to completion, e.g. builtins.
+    Finished(i32),
+
+    /// The command is running in a child process, with the given id.
+    Spawned(u32),
+}
+
+/// Runs the lists one after the other, and the pipelines of each list depending on the exit status
+/// of the previous one.
+///
+/// Each pipeline resolves and runs its commands, piping stdout of each one into stdin of the next.
+/// All the binaries of a pipeline are started concurrently, and the pipeline only completes once
+/// all of them have finished. Explicit redirections of a command take precedence over the pipes.
+/// The exit status of the last command of the pipeline is stored as the last status.
+pub(crate) fn run_commands(
+    lists: Vec<AndOrList>,
+    state: &mut ShellState,
+) -> Result<(), RunnerError> {
+    run_lists(&lists, &HashMap::new(), state)
 }
 
-/// Resolves and runs the provided commands, piping stdout of each one into stdin of the next.
-pub(crate) fn run_commands(commands: Vec<Command>) -> Result<(), RunnerError> {
-    // TODO: pipe commands into each other using https://doc.rust-lang.org/stable/std/io/fn.pipe.html
+/// Runs the provided commands like [`run_commands`], and returns the standard output of the last
+/// one instead of printing it, e.g. for command substitutions.
+pub(crate) fn capture_output(
+    lists: Vec<AndOrList>,
+    state: &mut ShellState,
+) -> Result<String, RunnerError> {
+    let (mut reader, writer) = pipe().map_err(RunnerError::PipeFailed)?;
 
-    for command in commands {
-        let descriptors = resolve_redirects(command.redirects())?;
+    // Read the output concurrently, so the commands don't block on a full pipe.
+    let output_reader = thread::spawn(move || {
+        let mut output = vec![];
+        reader.read_to_end(&mut output).map(|_| output)
+    });
 
-        if let Ok(builtin) = try_into_builtin(command.program()) {
-            builtin.run(command.arguments(), descriptors)?;
+    let descriptors = HashMap::from([(Descriptor::stdout(), FileDescriptor::PipeWriter(writer))]);
+    let result = run_lists(&lists, &descriptors, state);
+
+    // Close the write end once the pipelines have finished, so the reader reaches EOF.
+    drop(descriptors);
+    let output = output_reader
+        .join()
+        .expect("the output reader thread panicked")
+        .map_err(RunnerError::CaptureFailed)?;
+
+    match result {
+        // Exiting only exits the substitution, not the shell.
+        Err(RunnerError::BuiltInCommand(BuiltInCommandError::Exit(code))) => {
+            state.set_last_status(code);
+        }
+        // Likewise for unset parameters, e.g. `$(: ${VAR:?})`.
+        Err(error) if error.is_aborting() => eprintln!("{error}"),
+        result => result?,
+    }
+
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// Runs the commands of the script in the current shell, e.g. to source a file.
+///
+/// Each command is parsed once its lines are complete, so commands may span several lines, e.g.
+/// here-documents. Blank and comment lines are skipped, and failures are reported so the next
+/// commands still run, apart from aborting ones, e.g. exiting.
+pub(crate) fn run_script(script: &str, state: &mut ShellState) -> Result<(), RunnerError> {
+    // Fail cleanly rather than overflowing the stack when scripts source themselves.
+    state.nested(RunnerError::NestedTooDeeply, |state| {
+        let mut input = String::new();
+
+        let mut lines = script.lines().peekable();
+        while let Some(line) = lines.next() {
+            if input.is_empty() && (line.trim().is_empty() || line.trim_start().starts_with('#')) {
+                continue;
+            }
+
+            input.push_str(line);
+
+            // Keep capturing lines until the command is complete, or the script ends.
+            if is_incomplete_input(&input) && lines.peek().is_some() {
+                input.push('\n');
+                continue;
+            }
+
+            // Syntax errors only skip the command, with status 2 like bash.
+            let result = parse_input(&std::mem::take(&mut input), state)
+                .inspect_err(|_| state.set_last_status(2))
+                .map_err(RunnerError::from)
+                .and_then(|lists| run_commands(lists, state));
+            continue_after_failure(result, false)?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Runs the lists one after the other, regardless of their status, e.g. `false; echo ok`.
+///
+/// The given descriptors apply to all the commands, unless they are piped or redirected, e.g. the
+/// redirections of a group.
+fn run_lists(
+    lists: &[AndOrList],
+    descriptors: &HashMap<Descriptor, FileDescriptor>,
+    state: &mut ShellState,
+) -> Result<(), RunnerError> {
+    for (index, list) in lists.iter().enumerate() {
+        let result = if list.is_background() {
+            run_in_background(list.first(), descriptors, state)
         } else {
-            run_binary(command.program(), command.arguments(), descriptors)?;
+            run_and_or_list(list, descriptors, state)
+        };
+        continue_after_failure(result, index + 1 == lists.len())?;
+    }
+
+    Ok(())
+}
+
+/// Runs the first pipeline of the list, then each of the next ones if the last status satisfies
+/// its operator, e.g. `false || echo ok`.
+fn run_and_or_list(
+    list: &AndOrList,
+    descriptors: &HashMap<Descriptor, FileDescriptor>,
+    state: &mut ShellState,
+) -> Result<(), RunnerError> {
+    let pipelines = std::iter::once((None, list.first())).chain(
+        list.rest()
+            .iter()
+            .map(|(op, commands)| (Some(*op), commands.as_slice())),
+    );
+    let pipelines_count = list.rest().len() + 1;
+
+    for (index, (operator, commands)) in pipelines.enumerate() {
+        let should_run = match operator {
+            None => true,
+            Some(ListOperator::And) => state.last_status() == 0,
+            Some(ListOperator::Or) => state.last_status() != 0,
+        };
+        if !should_run {
+            continue;
+        }
+
+        let result = run_pipeline(commands, descriptors, state);
+        let is_last = index + 1 == pipelines_count;
+
+        // Exit on failure with `set -e`, unless the pipeline is followed by `&&` or `||`.
+        if is_last && state.is_enabled(ShellOption::Errexit) && state.last_status() != 0 {
+            continue_after_failure(result, false)?;
+            return Err(BuiltInCommandError::Exit(state.last_status()).into());
         }
+        continue_after_failure(result, is_last)?;
     }
 
     Ok(())
 }
+
+/// Starts the pipeline without waiting for it to finish, and records it as a job.
+fn run_in_background(
+    commands: &[Command],
+    descriptors: &HashMap<Descriptor, FileDescriptor>,
+    state: &mut ShellState,
+) -> Result<(), RunnerError> {
+    // Don't let background commands compete with the shell for the input, unless it is a terminal
+    // which stops them when they read it, until they are brought to the foreground.
+    let stdin = if std::io::stdin().is_terminal() {
+        None
+    } else {
+        Some(FileDescriptor::input_file(NULL_DEVICE)?)
+    };
+
+    // Start the pipeline in its own process group, so it can be given the terminal later on.
+    let process_group = Some(ProcessGroup::New { foreground: false });
+    let (executions, pipeline_error) =
+        start_pipeline(commands, stdin, descriptors, process_group, state)?;
+    let (_, children) = split_executions(executions);
+
+    // Report the job id and the process id of the last command, like bash.
+    if !children.is_empty() {
+        let job = state.jobs_mut().add(children, display_pipeline(commands));
+        eprintln!("[{}] {}", job.id(), job.pid().unwrap_or_default());
+    }
+
+    // Starting a job in the background always succeeds.
+    state.set_last_status(0);
+
+    match pipeline_error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// Reports the failure of a pipeline followed by other ones, so they still run, or returns it if
+/// it is the last one. Aborting errors always stop the execution, e.g. exiting.
+fn continue_after_failure(
+    result: Result<(), RunnerError>,
+    is_last: bool,
+) -> Result<(), RunnerError> {
+    match result {
+        Err(error) if error.is_aborting() => Err(error),
+        Err(error) if !is_last => {
+            eprintln!("{error}");
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Runs the pipeline with the given descriptors, apart from the pipes between its commands.
+fn run_pipeline(
+    commands: &[Command],
+    descriptors: &HashMap<Descriptor, FileDescriptor>,
+    state: &mut ShellState,
+) -> Result<(), RunnerError> {
+    // Start the pipeline in its own process group in interactive shells, so it gets the terminal
+    // and Ctrl+Z only stops the pipeline.
+    let process_group = terminal().map(|_| ProcessGroup::New { foreground: true });
+    let (executions, pipeline_error) =
+        start_pipeline(commands, None, descriptors, process_group, state)?;
+    let (mut last_status, children) = split_executions(executions);
+
+    if !children.is_empty() {
+        // Wait for all the commands to finish, to avoid leaving zombie processes behind, unless the
+        // pipeline is stopped in which case it is kept as a job.
+        let job = state.jobs_mut().add(children, display_pipeline(commands));
+        let id = job.id();
+        let result = job.wait();
+        take_terminal_back();
+
+        let job_state = job.state();
+        if job_state == JobState::Stopped {
+            eprintln!("\n{job}");
+        }
+        if job_state != JobState::Stopped || result.is_err() {
+            state.jobs_mut().remove(id);
+        }
+        result.map_err(RunnerError::WaitFailed)?;
+
+        match job_state {
+            JobState::Done(status) => {
+                // Start the next prompt on a new line after Ctrl+C, like bash.
+                if status == 128 + SIGINT {
+                    eprintln!();
+                }
+
+                last_status.get_or_insert(status);
+            }
+            _ => last_status = Some(128 + SIGTSTP),
+        }
+    } else {
+        // Take the terminal back from binaries which failed to start after taking it.
+        take_terminal_back();
+    }
+
+    let last_status = last_status.unwrap_or_default();
+    state.set_last_status(last_status);
+
+    match pipeline_error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// Starts the commands of the pipeline with the given descriptors, reading the input of the first
+/// command from the given input if any, and piping the output of each command into the next one.
+///
+/// The binaries are started in the process group of the shell, unless a group is given for the
+/// first binary, which the next ones then join.
+///
+/// Failing commands don't abort the pipeline, so the other commands still run to completion. The
+/// first error is returned along with the executions instead.
+fn start_pipeline(
+    commands: &[Command],
+    stdin: Option<FileDescriptor>,
+    base_descriptors: &HashMap<Descriptor, FileDescriptor>,
+    mut process_group: Option<ProcessGroup>,
+    state: &mut ShellState,
+) -> Result<(Vec<Execution>, Option<RunnerError>), RunnerError> {
+    let mut executions: Vec<Execution> = vec![];
+    let mut pipeline_error: Option<RunnerError> = None;
+
+    // The read end of the pipe from the previous command.
+    let mut previous_stdout: Option<FileDescriptor> = stdin;
+
+    let commands_count = commands.len();
+    for (index, command) in commands.iter().enumerate() {
+        let mut descriptors = HashMap::new();
+        for (descriptor, file) in base_descriptors {
+            descriptors.insert(*descriptor, file.try_clone()?);
+        }
+
+        // Read from the previous command output.
+        if let Some(reader) = previous_stdout.take() {
+            descriptors.insert(Descriptor::stdin(), reader);
+        }
+
+        // Write to the next command input, unless this is the last command of the pipeline.
+        if index + 1 < commands_count {
+            let (reader, writer) = pipe().map_err(RunnerError::PipeFailed)?;
+
+            descriptors.insert(Descriptor::stdout(), FileDescriptor::PipeWriter(writer));
+            previous_stdout = Some(FileDescriptor::PipeReader(reader));
+        }
+
+        // Do not abort the pipeline on failure, so the other commands still run to completion.
+        match run_command(command, descriptors, process_group, state) {
+            Ok(execution) => {
+                if let (Execution::Spawned(pid), Some(ProcessGroup::New { .. })) =
+                    (&execution, process_group)
+                {
+                    process_group = Some(ProcessGroup::Join(*pid));
+                }
+                executions.push(execution);
+            }
+            Err(error) => {
+                executions.push(Execution::Finished(1));
+                pipeline_error.get_or_insert(error);
+            }
+        }
+    }
+
+    Ok((executions, pipeline_error))
+}
+
+/// Runs a builtin command to completion, or spawns a binary in the given process group if any.
+fn run_command(
+    command: &Command,
+    mut descriptors: HashMap<Descriptor, FileDescriptor>,
+    process_group: Option<ProcessGroup>,
+    state: &mut ShellState,
+) -> Result<Execution, RunnerError> {
+    if let Some(compound) = command.compound() {
+        resolve_redirects(command.redirects(), &mut descriptors, state)?;
+        return run_compound(compound, descriptors, process_group, state);
+    }
+
+    // Expand the command right before running it, so it sees the latest state.
+    let mut words = vec![];
+    for chunk in command.program().into_iter().chain(command.arguments()) {
+        words.extend(expand_words(chunk, state)?);
+    }
+    let mut variables = vec![];
+    for assignment in command.assignments() {
+        let value = expand_chunk(assignment.value(), state)?;
+        variables.push((assignment.name().to_owned(), value));
+    }
+
+    // Print the expanded command with `set -x`, to the shell's stderr rather than the command's.
+    if state.is_enabled(ShellOption::Xtrace) {
+        let words: Vec<String> = variables
+            .iter()
+            .map(|(name, value)| format!("{name}={}", quote_word(value)))
+            .chain(words.iter().map(|word| quote_word(word)))
+            .collect();
+        eprintln!("+ {}", words.join(" "));
+    }
+
+    // Explicit redirections override the pipes.
+    resolve_redirects(command.redirects(), &mut descriptors, state)?;
+
+    let Some((program, arguments)) = words.split_first() else {
+        // Without program, the variables are assigned to the shell, e.g. `FOO=bar`.
+        for (name, value) in &variables {
+            std::env::set_var(name, value);
+        }
+
+        // The status is the one of the last command substitution, if any.
+        let has_substitution = command
+            .assignments()
+            .iter()
+            .any(|assignment| assignment.value().has_command_substitution());
+        let status = if has_substitution {
+            state.last_status()
+        } else {
+            0
+        };
+
+        return Ok(Execution::Finished(status));
+    };
+
+    if let Ok(builtin) = try_into_builtin(program) {
+        // Builtins run before the next commands of the pipeline are started, so their output must
+        // be buffered rather than fill the pipe.
+        if let Some(stdout) = descriptors.remove(&Descriptor::stdout()) {
+            descriptors.insert(Descriptor::stdout(), stdout.buffered()?);
+        }
+
+        // Keep the error output aside, as the descriptors are moved into the builtin.
+        let stderr = descriptors
+            .get(&Descriptor::stderr())
+            .map(FileDescriptor::try_clone)
+            .transpose()?;
+
+        // Builtins run within the shell, so the variables are only set while they run.
+        match with_variables(&variables, || builtin.run(arguments, descriptors, state)) {
+            Ok(status) => Ok(Execution::Finished(status)),
+            Err(error @ BuiltInCommandError::Exit(_)) => Err(error.into()),
+            Err(error) => {
+                // Report failures to the error output of the builtin, like binaries do.
+                let mut stderr = stderr.unwrap_or_else(FileDescriptor::stderr);
+                writeln!(stderr, "{error}").map_err(RunnerError::ReportFailed)?;
+
+                Ok(Execution::Finished(1))
+            }
+        }
+    } else {
+        // Look commands up in the hash table and the cached PATH directories, unless the PATH is
+        // assigned for this command only, leaving missing ones to the system in case they were
+        // just installed.
+        let location = if is_path(program) || variables.iter().any(|(name, _)| name == "PATH") {
+            None
+        } else {
+            state.locate_command(program).ok().flatten()
+        };
+
+        // Keep the error output aside, as the descriptors are moved into the child.
+        let stderr = descriptors
+            .get(&Descriptor::stderr())
+            .map(FileDescriptor::try_clone)
+            .transpose()?;
+
+        match spawn_binary(
+            program,
+            location.as_deref(),
+            arguments,
+            &variables,
+            descriptors,
+            process_group,
+        ) {
+            Ok(child) => Ok(Execution::Spawned(child.id())),
+            Err(error) => match error.exit_status() {
+                Some(status) => {
+                    // Report commands which can't run like bash, rather than as shell errors.
+                    let mut stderr = stderr.unwrap_or_else(FileDescriptor::stderr);
+                    writeln!(stderr, "{error}").map_err(RunnerError::ReportFailed)?;
+
+                    Ok(Execution::Finished(status))
+                }
+                None => Err(error.into()),
+            },
+        }
+    }
+}
+
+/// Runs the lists of a compound command, with the given descriptors.
+///
+/// Brace groups run within the shell like builtins, apart from background ones which must not be
+/// waited for, and run in a subshell instead.
+fn run_compound(
+    compound: &CompoundCommand,
+    mut descriptors: HashMap<Descriptor, FileDescriptor>,
+    process_group: Option<ProcessGroup>,
+    state: &mut ShellState,
+) -> Result<Execution, RunnerError> {
+    let lists = match compound {
+        CompoundCommand::BraceGroup(lists)
+            if !matches!(process_group, Some(ProcessGroup::New { foreground: false })) =>
+        {
+            // The group runs before the next commands of the pipeline are started, so its output
+            // must be buffered rather than fill the pipe.
+            if let Some(stdout) = descriptors.remove(&Descriptor::stdout()) {
+                descriptors.insert(Descriptor::stdout(), stdout.buffered()?);
+            }
+
+            state.nested(RunnerError::NestedTooDeeply, |state| {
+                run_lists(lists, &descriptors, state)
+            })?;
+            return Ok(Execution::Finished(state.last_status()));
+        }
+        CompoundCommand::Subshell(lists) | CompoundCommand::BraceGroup(lists) => lists,
+    };
+
+    // Run the lists in a copy of the shell, so their changes don't affect it, e.g. `(cd /tmp)`.
+    let pid = spawn_subshell(descriptors, process_group, || {
+        let result = state.nested(RunnerError::NestedTooDeeply, |state| {
+            run_lists(lists, &HashMap::new(), state)
+        });
+        match result {
+            Ok(()) => state.last_status(),
+            Err(RunnerError::BuiltInCommand(BuiltInCommandError::Exit(code))) => code,
+            Err(error) => {
+                eprintln!("{error}");
+                state.last_status()
+            }
+        }
+    })?;
+
+    Ok(Execution::Spawned(pid))
+}
+
+/// Splits the executions of a pipeline into the status of the last command if it finished, and the
+/// ids of the child processes.
+fn split_executions(executions: Vec<Execution>) -> (Option<i32>, Vec<u32>) {
+    let last_status = match executions.last() {
+        Some(Execution::Finished(status)) => Some(*status),
+        _ => None,
+    };
+
+    let children = executions
+        .into_iter()
+        .filter_map(|execution| match execution {
+            Execution::Finished(_) => None,
+            Execution::Spawned(pid) => Some(pid),
+        })
+        .collect();
+
+    (last_status, children)
+}
+
+/// Sets the environment variables while running the function, then restores their values.
+fn with_variables<T>(variables: &[(String, String)], run: impl FnOnce() -> T) -> T {
+    let previous_values: Vec<_> = variables
+        .iter()
+        .map(|(name, value)| {
+            let previous_value = std::env::var_os(name);
+            std::env::set_var(name, value);
+            (name, previous_value)
+        })
+        .collect();
+
+    let result = run();
+
+    // Restore in reverse order, in case a variable is assigned several times.
+    for (name, previous_value) in previous_values.into_iter().rev() {
+        match previous_value {
+            Some(value) => std::env::set_var(name, value),
+            None => std::env::remove_var(name),
+        }
+    }
+
+    result
+}
+
+/// Quotes the word if needed so it could be typed back, e.g. to trace commands.
+fn quote_word(word: &str) -> String {
+    let is_plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|char| char.is_alphanumeric() || "-_./=:,+@%".contains(char));
+
+    if is_plain {
+        word.to_owned()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+/// Converts the exit status of a child process into a numeric status.
+pub(crate) fn exit_code(status: ExitStatus) -> i32 {
+    // Processes terminated by a signal report 128 + the signal number, like bash does.
+    status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run_commands, run_script, RunnerError};
+    use crate::builtin::BuiltInCommandError;
+    use crate::parser::parse_input;
+    use crate::state::{ShellOption, ShellState};
+    use std::fs;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn it_pipes_commands_into_each_other() {
+        let output = std::env::temp_dir().join("codecrafters_shell_runner_pipes.txt");
+
+        let commands = parse_input(
+            &format!(
+                "printf 'hello\\nworld\\n' | grep o | tr a-z A-Z | sort -r > {}",
+                output.display()
+            ),
+            &ShellState::new(),
+        )
+        .unwrap();
+
+        run_commands(commands, &mut ShellState::new()).unwrap();
+
+        assert_eq!("WORLD\nHELLO\n", fs::read_to_string(&output).unwrap());
+
+        fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn it_pipes_builtin_output_into_binaries() {
+        let output = std::env::temp_dir().join("codecrafters_shell_runner_builtin_pipe.txt");
+
+        let commands = parse_input(
+            &format!("echo hello | cat | cat > {}", output.display()),
+            &ShellState::new(),
+        )
+        .unwrap();
+
+        run_commands(commands, &mut ShellState::new()).unwrap();
+
+        assert_eq!("hello\n", fs::read_to_string(&output).unwrap());
+
+        fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn it_pipes_large_builtin_outputs() {
+        let output = std::env::temp_dir().join("codecrafters_shell_runner_large_pipe.txt");
+        let mut state = ShellState::new();
+
+        // Write more than the capacity of a pipe, before the next command is started.
+        let commands = parse_input(
+            &format!(
+                "echo $(head -c 200000 /dev/zero | tr '\\0' a) | wc -c > {}",
+                output.display()
+            ),
+            &state,
+        )
+        .unwrap();
+
+        run_commands(commands, &mut state).unwrap();
+
+        assert_eq!("200001", fs::read_to_string(&output).unwrap().trim());
+
+        fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn it_redirects_builtin_output() {
+        let output = std::env::temp_dir().join("codecrafters_shell_runner_builtin_out.txt");
+        let errors = std::env::temp_dir().join("codecrafters_shell_runner_builtin_err.txt");
+        let mut state = ShellState::new();
+
+        let commands = parse_input(
+            &format!(
+                "echo hi > {0}; type codecrafters_shell_missing 2> {1}; pwd >> {0}",
+                output.display(),
+                errors.display()
+            ),
+            &state,
+        )
+        .unwrap();
+
+        run_commands(commands, &mut state).unwrap();
+
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(
+            format!("hi\n{}\n", cwd.display()),
+            fs::read_to_string(&output).unwrap()
+        );
+        assert_eq!(
+            "codecrafters_shell_missing: not found\n",
+            fs::read_to_string(&errors).unwrap()
+        );
+
+        fs::remove_file(output).unwrap();
+        fs::remove_file(errors).unwrap();
+    }
+
+    #[test]
+    fn it_redirects_both_outputs_at_once() {
+        let output = std::env::temp_dir().join("codecrafters_shell_runner_both_outputs.txt");
+        let mut state = ShellState::new();
+
+        let commands = parse_input(
+            &format!(
+                "echo a &> {0}; ls codecrafters_shell_missing &>> {0}; \
+                 type codecrafters_shell_missing &>> {0}",
+                output.display()
+            ),
+            &state,
+        )
+        .unwrap();
+
+        run_commands(commands, &mut state).unwrap();
+
+        let written = fs::read_to_string(&output).unwrap();
+        let lines: Vec<_> = written.lines().collect();
+        assert_eq!(3, lines.len());
+        assert_eq!("a", lines[0]);
+        assert!(lines[1].contains("codecrafters_shell_missing"));
+        assert_eq!("codecrafters_shell_missing: not found", lines[2]);
+
+        fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn it_closes_redirected_descriptors() {
+        let output = std::env::temp_dir().join("codecrafters_shell_runner_closed_out.txt");
+        let errors = std::env::temp_dir().join("codecrafters_shell_runner_closed_err.txt");
+        let mut state = ShellState::new();
+
+        // Run commands with their error output closed, which only fail if they write to it.
+        let commands = parse_input(
+            &format!(
+                "echo a 2>&- > {0}; echo $? >> {0}; \
+                 {{ ls codecrafters_shell_missing 2>&-; }} 2> {1}",
+                output.display(),
+                errors.display()
+            ),
+            &state,
+        )
+        .unwrap();
+
+        run_commands(commands, &mut state).unwrap();
+
+        assert_eq!("a\n0\n", fs::read_to_string(&output).unwrap());
+        assert_eq!("", fs::read_to_string(&errors).unwrap());
+        assert_ne!(0, state.last_status());
+
+        fs::remove_file(output).unwrap();
+        fs::remove_file(errors).unwrap();
+    }
+
+    #[test]
+    fn it_redirects_the_input_from_a_file() {
+        let input = std::env::temp_dir().join("codecrafters_shell_runner_input.txt");
+        let output = std::env::temp_dir().join("codecrafters_shell_runner_input_out.txt");
+        fs::write(&input, "hello\n").unwrap();
+
+        let commands = parse_input(
+            &format!("tr a-z A-Z < {} > {}", input.display(), output.display()),
+            &ShellState::new(),
+        )
+        .unwrap();
+
+        run_commands(commands, &mut ShellState::new()).unwrap();
+
+        assert_eq!("HELLO\n", fs::read_to_string(&output).unwrap());
+
+        // Fail clearly if the input file doesn't exist.
+        fs::remove_file(&input).unwrap();
+        let commands =
+            parse_input(&format!("cat < {}", input.display()), &ShellState::new()).unwrap();
+
+        let error = run_commands(commands, &mut ShellState::new()).unwrap_err();
+
+        assert_eq!(
+            format!(
+                "{}: No such file or directory (os error 2)",
+                input.display()
+            ),
+            error.to_string()
+        );
+
+        fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn it_redirects_the_input_of_builtins() {
+        let input = std::env::temp_dir().join("codecrafters_shell_runner_read.txt");
+        fs::write(&input, "first line\nsecond line\n").unwrap();
+
+        let commands = parse_input(
+            &format!("read CODECRAFTERS_RUNNER_LINE < {}", input.display()),
+            &ShellState::new(),
+        )
+        .unwrap();
+
+        run_commands(commands, &mut ShellState::new()).unwrap();
+
+        assert_eq!(
+            "first line",
+            std::env::var("CODECRAFTERS_RUNNER_LINE").unwrap()
+        );
+
+        fs::remove_file(input).unwrap();
+    }
+
+    #[test]
+    fn it_stores_the_status_of_the_last_command() {
+        let mut state = ShellState::new();
+
+        run_commands(parse_input("false", &state).unwrap(), &mut state).unwrap();
+        assert_eq!(1, state.last_status());
+
+        run_commands(parse_input("true", &state).unwrap(), &mut state).unwrap();
+        assert_eq!(0, state.last_status());
+
+        // Use the status of the last command of a pipeline.
+        run_commands(parse_input("false | true", &state).unwrap(), &mut state).unwrap();
+        assert_eq!(0, state.last_status());
+
+        run_commands(parse_input("true | false", &state).unwrap(), &mut state).unwrap();
+        assert_eq!(1, state.last_status());
+
+        // Builtins report their status too.
+        run_commands(parse_input("pwd > /dev/null", &state).unwrap(), &mut state).unwrap();
+        assert_eq!(0, state.last_status());
+    }
+
+    #[test]
+    fn it_reports_commands_which_cant_run() {
+        let errors = std::env::temp_dir().join("codecrafters_shell_runner_not_found.txt");
+        let mut state = ShellState::new();
+
+        let commands = parse_input(
+            &format!("codecrafters_shell_missing 2> {}", errors.display()),
+            &state,
+        )
+        .unwrap();
+        run_commands(commands, &mut state).unwrap();
+
+        // Print the error to the redirected stderr, and fail with 127 like bash.
+        assert_eq!(127, state.last_status());
+        assert_eq!(
+            "codecrafters_shell_missing: command not found\n",
+            fs::read_to_string(&errors).unwrap()
+        );
+
+        // Fail with 126 if the command isn't executable.
+        let commands = parse_input(&format!("/ 2> {}", errors.display()), &state).unwrap();
+        run_commands(commands, &mut state).unwrap();
+        assert_eq!(126, state.last_status());
+
+        fs::remove_file(errors).unwrap();
+    }
+
+    #[test]
+    fn it_reports_home_directories_of_unknown_users() {
+        let errors = std::env::temp_dir().join("codecrafters_shell_runner_cd_user.txt");
+        let mut state = ShellState::new();
+
+        // Unknown users are left as they are, so changing directory fails like bash.
+        let commands = parse_input(
+            &format!(
+                "cd ~codecrafters_shell_unknown_user/sub 2> {}",
+                errors.display()
+            ),
+            &state,
+        )
+        .unwrap();
+        run_commands(commands, &mut state).unwrap();
+
+        assert_eq!(1, state.last_status());
+        assert_eq!(
+            "cd: ~codecrafters_shell_unknown_user/sub: No such file or directory\n",
+            fs::read_to_string(&errors).unwrap()
+        );
+
+        fs::remove_file(errors).unwrap();
+    }
+
+    #[test]
+    fn it_runs_and_or_lists_depending_on_the_last_status() {
+        let output = std::env::temp_dir().join("codecrafters_shell_runner_and_or.txt");
+        let mut state = ShellState::new();
+
+        let mut run = |input: &str| {
+            let input = format!("{input} >> {}", output.display());
+            run_commands(parse_input(&input, &state).unwrap(), &mut state).unwrap();
+        };
+
+        run("false || echo ok");
+        run("false && echo no");
+        run("true && echo yes");
+        run("true || echo no");
+        run("false && echo no || echo fallback");
+
+        assert_eq!("ok\nyes\nfallback\n", fs::read_to_string(&output).unwrap());
+
+        fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn it_runs_lists_one_after_the_other() {
+        let output = std::env::temp_dir().join("codecrafters_shell_runner_lists.txt");
+        let _ = fs::remove_file(&output);
+
+        let path = output.display();
+        let commands = parse_input(
+            &format!("echo a >> {path}; false; echo b >> {path} ; echo 'a;b' >> {path};"),
+            &ShellState::new(),
+        )
+        .unwrap();
+
+        run_commands(commands, &mut ShellState::new()).unwrap();
+
+        assert_eq!("a\nb\na;b\n", fs::read_to_string(&output).unwrap());
+
+        fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn it_runs_subshells_without_affecting_the_shell() {
+        let output = std::env::temp_dir().join("codecrafters_shell_runner_subshells.txt");
+        let _ = fs::remove_file(&output);
+
+        let path = output.display();
+        let mut state = ShellState::new();
+        let commands = parse_input(
+            &format!(
+                "(CODECRAFTERS_SUBSHELL=1; cd /; pwd; exit 3) > {path}; \
+                 echo $? ${{CODECRAFTERS_SUBSHELL}}end >> {path}; \
+                 (echo a; (echo b)) | cat >> {path}"
+            ),
+            &state,
+        )
+        .unwrap();
+
+        run_commands(commands, &mut state).unwrap();
+
+        assert_eq!("/\n3 end\na\nb\n", fs::read_to_string(&output).unwrap());
+        assert_ne!(std::path::Path::new("/"), std::env::current_dir().unwrap());
+
+        fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn it_redirects_brace_groups_as_a_whole() {
+        let output = std::env::temp_dir().join("codecrafters_shell_runner_braces.txt");
+        let _ = fs::remove_file(&output);
+
+        let path = output.display();
+        let mut state = ShellState::new();
+        let commands = parse_input(
+            &format!(
+                "{{ echo a; CODECRAFTERS_BRACES=1; sh -c 'echo b'; }} > {path}; \
+                 {{ echo $CODECRAFTERS_BRACES; false; }} | cat >> {path}"
+            ),
+            &state,
+        )
+        .unwrap();
+
+        run_commands(commands, &mut state).unwrap();
+
+        assert_eq!("a\nb\n1\n", fs::read_to_string(&output).unwrap());
+        assert_eq!(0, state.last_status());
+
+        fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn it_runs_background_pipelines_without_waiting() {
+        let mut state = ShellState::new();
+        let start = Instant::now();
+
+        let commands = parse_input("sleep 5 | cat & true", &state).unwrap();
+        run_commands(commands, &mut state).unwrap();
+
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(0, state.last_status());
+    }
+
+    #[test]
+    fn it_keeps_stopped_pipelines_as_jobs() {
+        let mut state = ShellState::new();
+
+        // The command stops itself, like with Ctrl+Z.
+        let commands = parse_input("sh -c 'kill -STOP $$; exit 3'", &state).unwrap();
+        run_commands(commands, &mut state).unwrap();
+
+        assert_eq!(148, state.last_status());
+        assert!(state.jobs_mut().poll()[0]
+            .to_string()
+            .contains("Stopped   sh -c"));
+
+        // Resume it in the foreground until it finishes.
+        let commands = parse_input("fg > /dev/null", &state).unwrap();
+        run_commands(commands, &mut state).unwrap();
+
+        assert_eq!(3, state.last_status());
+        assert!(state.jobs_mut().poll().is_empty());
+    }
+
+    #[test]
+    fn it_runs_scripts_in_the_current_shell() {
+        let output = std::env::temp_dir().join("codecrafters_shell_runner_script.txt");
+        let mut state = ShellState::new();
+
+        let script = format!(
+            "# Set up the aliases\n\nalias shout='tr a-z A-Z'\nshout <<EOF > {}\nhello\nEOF\nfalse",
+            output.display()
+        );
+        run_script(&script, &mut state).unwrap();
+
+        assert_eq!("HELLO\n", fs::read_to_string(&output).unwrap());
+        assert_eq!(Some(&"tr a-z A-Z".to_owned()), state.aliases().get("shout"));
+        assert_eq!(1, state.last_status());
+
+        fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn it_expands_the_ignored_arguments_of_colon() {
+        let output = std::env::temp_dir().join("codecrafters_shell_runner_colon.txt");
+        let mut state = ShellState::new();
+        state.set_last_status(1);
+
+        let input = format!(": $(echo side effect > {}) ignored", output.display());
+        run_commands(parse_input(&input, &state).unwrap(), &mut state).unwrap();
+
+        assert_eq!("side effect\n", fs::read_to_string(&output).unwrap());
+        assert_eq!(0, state.last_status());
+
+        fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn it_sets_the_status_of_syntax_errors() {
+        let mut state = ShellState::new();
+
+        // The error only skips its own command.
+        run_script("true\n| echo", &mut state).unwrap();
+        assert_eq!(2, state.last_status());
+
+        run_script("true &&\ntrue", &mut state).unwrap();
+        assert_eq!(0, state.last_status());
+    }
+
+    #[test]
+    fn it_exits_on_failure_with_errexit() {
+        let mut state = ShellState::new();
+        state.set_option(ShellOption::Errexit, true);
+
+        // Failures followed by `&&` or `||` don't exit.
+        let lists = parse_input("false || true; false && true", &state).unwrap();
+        run_commands(lists, &mut state).unwrap();
+        assert_eq!(1, state.last_status());
+
+        let lists = parse_input("true && false; true", &state).unwrap();
+        assert!(matches!(
+            run_commands(lists, &mut state),
+            Err(RunnerError::BuiltInCommand(BuiltInCommandError::Exit(1)))
+        ));
+    }
+
+    #[test]
+    fn it_assigns_variables_for_a_single_command() {
+        let output = std::env::temp_dir().join("codecrafters_shell_runner_assignments.txt");
+        let mut state = ShellState::new();
+
+        let lists = parse_input(
+            &format!(
+                "CODECRAFTERS_ASSIGNED_A=1 CODECRAFTERS_ASSIGNED_B='x y' printenv CODECRAFTERS_ASSIGNED_A CODECRAFTERS_ASSIGNED_B > {}",
+                output.display()
+            ),
+            &state,
+        )
+        .unwrap();
+        run_commands(lists, &mut state).unwrap();
+
+        assert_eq!("1\nx y\n", fs::read_to_string(&output).unwrap());
+        assert!(std::env::var("CODECRAFTERS_ASSIGNED_A").is_err());
+
+        // Without program, the variables are assigned to the shell.
+        let lists = parse_input("CODECRAFTERS_ASSIGNED_C=$(echo z; false)", &state).unwrap();
+        run_commands(lists, &mut state).unwrap();
+
+        assert_eq!("z", std::env::var("CODECRAFTERS_ASSIGNED_C").unwrap());
+        assert_eq!(1, state.last_status());
+
+        fs::remove_file(output).unwrap();
+    }
+}
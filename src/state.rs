@@ -0,0 +1,205 @@
+use crate::history::History;
+use crate::jobs::Jobs;
+use crate::path::{CommandHashes, PathCache, PathError};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use strum_macros::{Display, EnumIter, EnumString};
+
+/// How deep command substitutions, groups and sourced scripts can nest, to fail cleanly rather than
+/// overflowing the stack, e.g. with a script sourcing itself.
+pub(crate) const MAX_NESTING_DEPTH: usize = 100;
+
+/// The state of the shell, persisted across REPL iterations.
+pub(crate) struct ShellState {
+    /// The exit status of the last command, exposed as `$?`.
+    last_status: i32,
+
+    /// The name of the shell or of the script it runs, exposed as `$0`.
+    name: String,
+
+    /// The arguments of the script, exposed as `$1`, `$2`, etc.
+    arguments: Vec<String>,
+
+    /// The commands previously entered by the user.
+    history: History,
+
+    /// The values replacing the first word of commands, by alias name.
+    aliases: BTreeMap<String, String>,
+
+    /// The pipelines running in the background.
+    jobs: Jobs,
+
+    /// The options enabled with `set`.
+    options: BTreeSet<ShellOption>,
+
+    /// The executables found in the PATH directories.
+    path_cache: PathCache,
+
+    /// The locations of the commands found in the PATH, listed with `hash`.
+    command_hashes: CommandHashes,
+
+    /// How many command substitutions, groups and sourced scripts are running within each other.
+    nesting_depth: usize,
+}
+
+/// An option changing the behaviour of the shell, toggled with `set -o NAME` and `set +o NAME`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display, EnumString, EnumIter)]
+#[strum(serialize_all = "lowercase")]
+pub(crate) enum ShellOption {
+    /// Exit as soon as a pipeline fails, unless it is followed by `&&` or `||`, i.e. `-e`.
+    Errexit,
+    /// Refuse to overwrite existing files with `>`, unless forced with `>|`, i.e. `-C`.
+    Noclobber,
+    /// Print the commands to stderr before running them, i.e. `-x`.
+    Xtrace,
+}
+
+impl ShellOption {
+    /// The option toggled by the single-letter flag, e.g. `set -e`.
+    pub(crate) fn from_flag(flag: char) -> Option<Self> {
+        match flag {
+            'e' => Some(Self::Errexit),
+            'C' => Some(Self::Noclobber),
+            'x' => Some(Self::Xtrace),
+            _ => None,
+        }
+    }
+}
+
+impl ShellState {
+    pub(crate) fn new() -> Self {
+        Self {
+            last_status: 0,
+            name: std::env::args().next().unwrap_or_default(),
+            arguments: vec![],
+            history: History::default(),
+            aliases: BTreeMap::new(),
+            jobs: Jobs::default(),
+            options: BTreeSet::new(),
+            path_cache: PathCache::default(),
+            command_hashes: CommandHashes::default(),
+            nesting_depth: 0,
+        }
+    }
+
+    pub(crate) fn last_status(&self) -> i32 {
+        self.last_status
+    }
+
+    pub(crate) fn set_last_status(&mut self, status: i32) {
+        self.last_status = status;
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn arguments(&self) -> &[String] {
+        &self.arguments
+    }
+
+    /// Sets the name and arguments of the script being run, e.g. `$0` and `$1` for `shell script a`.
+    pub(crate) fn set_script(&mut self, name: String, arguments: Vec<String>) {
+        self.name = name;
+        self.arguments = arguments;
+    }
+
+    /// Runs the function one nesting level deeper, or fails with the given error past the maximum
+    /// depth.
+    pub(crate) fn nested<T, E>(
+        &mut self,
+        too_deep: E,
+        run: impl FnOnce(&mut Self) -> Result<T, E>,
+    ) -> Result<T, E> {
+        if self.nesting_depth >= MAX_NESTING_DEPTH {
+            return Err(too_deep);
+        }
+
+        self.nesting_depth += 1;
+        let result = run(self);
+        self.nesting_depth -= 1;
+
+        result
+    }
+
+    /// Replaces the positional parameters, e.g. for `source script a`, returning the previous ones.
+    pub(crate) fn replace_arguments(&mut self, arguments: Vec<String>) -> Vec<String> {
+        std::mem::replace(&mut self.arguments, arguments)
+    }
+
+    pub(crate) fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub(crate) fn history_mut(&mut self) -> &mut History {
+        &mut self.history
+    }
+
+    pub(crate) fn aliases(&self) -> &BTreeMap<String, String> {
+        &self.aliases
+    }
+
+    pub(crate) fn aliases_mut(&mut self) -> &mut BTreeMap<String, String> {
+        &mut self.aliases
+    }
+
+    pub(crate) fn jobs_mut(&mut self) -> &mut Jobs {
+        &mut self.jobs
+    }
+
+    pub(crate) fn path_cache(&self) -> &PathCache {
+        &self.path_cache
+    }
+
+    pub(crate) fn command_hashes(&self) -> &CommandHashes {
+        &self.command_hashes
+    }
+
+    pub(crate) fn command_hashes_mut(&mut self) -> &mut CommandHashes {
+        &mut self.command_hashes
+    }
+
+    /// Finds the location of the command to run in the PATH, remembering it for the next runs.
+    pub(crate) fn locate_command(&mut self, name: &str) -> Result<Option<PathBuf>, PathError> {
+        self.command_hashes.locate(name, &self.path_cache)
+    }
+
+    /// Remembers the location of the command in the PATH, returning whether it was found.
+    pub(crate) fn hash_command(&mut self, name: &str) -> Result<bool, PathError> {
+        self.command_hashes.remember(name, &self.path_cache)
+    }
+
+    pub(crate) fn is_enabled(&self, option: ShellOption) -> bool {
+        self.options.contains(&option)
+    }
+
+    pub(crate) fn set_option(&mut self, option: ShellOption, enabled: bool) {
+        if enabled {
+            self.options.insert(option);
+        } else {
+            self.options.remove(&option);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ShellState, MAX_NESTING_DEPTH};
+
+    #[test]
+    fn it_fails_past_the_maximum_nesting_depth() {
+        fn nest(state: &mut ShellState, levels: usize) -> Result<usize, ()> {
+            if levels == 0 {
+                return Ok(state.nesting_depth);
+            }
+            state.nested((), |state| nest(state, levels - 1))
+        }
+
+        let mut state = ShellState::new();
+        assert_eq!(Ok(MAX_NESTING_DEPTH), nest(&mut state, MAX_NESTING_DEPTH));
+        assert_eq!(Err(()), nest(&mut state, MAX_NESTING_DEPTH + 1));
+
+        // Failing leaves the depth as it was.
+        assert_eq!(0, state.nesting_depth);
+    }
+}